@@ -31,6 +31,9 @@ class PostProcessor:
             self.rename_file_if_needed(file_path)
         self.update_mod_file()
         self.add_error_enums()
+        self.add_book_levels()
+        self.add_multi_level_quotes()
+        self.add_recent_trades()
 
     def add_error_enums(self):
         """
@@ -51,6 +54,88 @@ class PostProcessor:
             print(f"Updating {file_path} to use ErrorCode enum.")
             file_path.write_text(content)
 
+    def add_book_levels(self):
+        """
+        Replace the positional arrays of the order book models with the typed
+        `BookLevel` and `BookTrade` tuples from `manual_models::book`.
+        """
+        imports = {
+            "book.rs": "{BookLevel, BookTrade}",
+            "lwt.rs": "BookLevel",
+            "book_rpc_result.rs": "BookLevel",
+        }
+        replacements = {
+            "book.rs": [
+                ("trades: Option<Vec<serde_json::Value>>", "trades: Option<Vec<BookTrade>>"),
+                ("bid_changes: Option<Vec<serde_json::Value>>", "bid_changes: Option<Vec<BookLevel>>"),
+                ("ask_changes: Option<Vec<serde_json::Value>>", "ask_changes: Option<Vec<BookLevel>>"),
+            ],
+            "lwt.rs": [
+                ("b: Option<Vec<serde_json::Value>>", "b: Option<BookLevel>"),
+                ("a: Option<Vec<serde_json::Value>>", "a: Option<BookLevel>"),
+            ],
+            "book_rpc_result.rs": [
+                ("bids: Vec<Vec<rust_decimal::Decimal>>", "bids: Vec<BookLevel>"),
+                ("asks: Vec<Vec<rust_decimal::Decimal>>", "asks: Vec<BookLevel>"),
+            ],
+        }
+        for file_name, pairs in replacements.items():
+            file_path = OUTPUT_FOLDER / file_name
+            content = file_path.read_text()
+            original_content = content
+            for old, new in pairs:
+                content = content.replace(old, new)
+            content = content.replace(
+                "use crate::models;",
+                f"use crate::{{manual_models::book::{imports[file_name]}, models}};",
+            )
+            if content != original_content:
+                print(f"Updating {file_path} to use typed book levels.")
+                file_path.write_text(content)
+
+    def add_multi_level_quotes(self):
+        """
+        The generator emits an empty struct for the array-of-tuples
+        `SingleSidedMultiLevelQuote` schema; replace it with a list of `BookLevel`.
+        """
+        file_path = OUTPUT_FOLDER / "single_sided_multi_level_quote.rs"
+        content = file_path.read_text()
+        header = content.split("use crate::models;")[0]
+        fixed = header + dedent("""
+            use crate::manual_models::book::BookLevel;
+
+            /// SingleSidedMultiLevelQuote : Up to 10 `[price, amount]` levels; an empty array removes the side.
+            pub type SingleSidedMultiLevelQuote = Vec<BookLevel>;
+            """).lstrip()
+        if content != fixed:
+            print(f"Updating {file_path} to a list of book levels.")
+            file_path.write_text(fixed)
+
+    def add_recent_trades(self):
+        """
+        `recent_trades` items are arrays, which the generator models as a struct with
+        optional fields; use the hand-written tuple model instead.
+        """
+        fixes = {
+            "recent_trade.rs": """
+                pub use crate::manual_models::recent_trade::RecentTrade;
+                """,
+            "recent_trades.rs": """
+                use crate::manual_models::recent_trade::RecentTrade;
+
+                /// RecentTrades : Trades of a `recent_trades` notification; the first one after subscribing is a snapshot.
+                pub type RecentTrades = Vec<RecentTrade>;
+                """,
+        }
+        for name, body in fixes.items():
+            file_path = OUTPUT_FOLDER / name
+            content = file_path.read_text()
+            header = content.split("use crate::models;")[0].split("use serde::")[0]
+            fixed = header + dedent(body).lstrip()
+            if content != fixed:
+                print(f"Updating {file_path} to the manual recent trade model.")
+                file_path.write_text(fixed)
+
     def process_file(self, file_path: Path):
         """
         Process a single file to fix issues.
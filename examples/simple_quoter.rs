@@ -5,10 +5,9 @@ use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use simple_logger::init_with_level;
 use thalex_rust_sdk::{
-    models::{
-        AmendParams, CancelParams, Delay, DirectionEnum, InsertParams, OrderStatus, OrderTypeEnum,
-        SetCancelOnDisconnectParams, StatusEnum,
-    },
+    models::{AmendParams, CancelParams, Delay, DirectionEnum, OrderStatus},
+    order_builder::OrderBuilder,
+    order_manager::{OrderFeed, OrderManager},
     types::ExternalEvent,
     ws_client::WsClient,
 };
@@ -35,7 +34,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     init_with_level(Info).unwrap();
     dotenv::dotenv().ok();
     let client = WsClient::from_env().await.unwrap();
-    let _ = client.set_cancel_on_disconnect().await;
+    client
+        .set_cancel_on_disconnect(6)
+        .await
+        .expect("Failed to set cancel on disconnect");
     let position = client
         .rpc()
         .accounting()
@@ -90,16 +92,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let bid_order = client
                         .rpc()
                         .trading()
-                        .insert(InsertParams {
-                            direction: DirectionEnum::Buy,
-                            amount: ORDER_SIZE,
-                            price: Some(bid_price),
-                            instrument_name: Some(MARKET_NAME.to_string()),
-                            order_type: Some(OrderTypeEnum::Limit),
-                            post_only: Some(true),
-                            reject_post_only: Some(true),
-                            ..Default::default()
-                        })
+                        .insert(
+                            OrderBuilder::limit(
+                                DirectionEnum::Buy,
+                                MARKET_NAME,
+                                ORDER_SIZE,
+                                bid_price,
+                            )
+                            .book_or_cancel()
+                            .build_for(&client)
+                            .unwrap(),
+                        )
                         .await
                         .unwrap();
                     info!("Placed bid order: {bid_price:?}");
@@ -111,16 +114,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let ask_order = client
                         .rpc()
                         .trading()
-                        .insert(InsertParams {
-                            direction: DirectionEnum::Sell,
-                            amount: ORDER_SIZE,
-                            price: Some(ask_price),
-                            instrument_name: Some(MARKET_NAME.to_string()),
-                            order_type: Some(OrderTypeEnum::Limit),
-                            post_only: Some(true),
-                            reject_post_only: Some(true),
-                            ..Default::default()
-                        })
+                        .insert(
+                            OrderBuilder::limit(
+                                DirectionEnum::Sell,
+                                MARKET_NAME,
+                                ORDER_SIZE,
+                                ask_price,
+                            )
+                            .book_or_cancel()
+                            .build_for(&client)
+                            .unwrap(),
+                        )
                         .await
                         .unwrap();
                     info!("Placed ask order: {ask_price:?}");
@@ -187,39 +191,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .await;
 
+    // The order manager tracks every state an order goes through, so we only need to
+    // release our slot once an order has nothing left on the book.
+    let orders = OrderManager::start(client.clone(), OrderFeed::Session)
+        .await
+        .expect("Failed to start order manager");
     let state_for_orders = state.clone();
-
-    let _ = client
-        .subscriptions()
-        .accounting()
-        .session_orders(move |msg| {
-            let state = Arc::clone(&state_for_orders);
-            async move {
-                for order in msg {
-                    info!(
-                        "Order Update: id={} instrument_name={:?} order_type={:?} amount={} price={:?} status={:?}",
-                        order.order_id, order.instrument_name, order.order_type, order.amount, order.price, order.status
-                    );
-                    match order.status {
-                        StatusEnum::Filled | StatusEnum::Cancelled => {
-                            let mut state = state.lock().await;
-                            info!("Order {} is no longer active.", order.order_id);
-                            info!("Removing order from strategy state.");
-                            if let Some(bid_order) = &state.bid_order
-                                && bid_order.order_id == order.order_id {
-                                    state.bid_order = None;
-                                }
-                            if let Some(ask_order) = &state.ask_order
-                                && ask_order.order_id == order.order_id {
-                                    state.ask_order = None;
-                                }
-                        }
-                        _ => { }
-                    }
-                }
+    let release = Arc::new(move |order: &OrderStatus| {
+        if !order.remaining_amount.is_zero() {
+            return;
+        }
+        let state = Arc::clone(&state_for_orders);
+        let order_id = order.order_id.clone();
+        tokio::spawn(async move {
+            let mut state = state.lock().await;
+            info!("Order {order_id} is no longer active, removing it from strategy state.");
+            if state
+                .bid_order
+                .as_ref()
+                .is_some_and(|o| o.order_id == order_id)
+            {
+                state.bid_order = None;
             }
-        })
-        .await;
+            if state
+                .ask_order
+                .as_ref()
+                .is_some_and(|o| o.order_id == order_id)
+            {
+                state.ask_order = None;
+            }
+        });
+    });
+    let release_on_fill = release.clone();
+    orders.on_fill(move |order, fill| {
+        info!(
+            "Fill on order {}: {} @ {}",
+            order.order_id, fill.amount, fill.price
+        );
+        release_on_fill(order);
+    });
+    orders.on_cancel(move |order| release(order));
 
     let client_for_callback = client.clone();
     let state_for_callback = state.clone();
@@ -267,13 +278,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     loop {
         match client.run_till_event().await {
             ExternalEvent::Connected => {
+                // Logging in re-applies cancel on disconnect.
                 client.login().await.ok();
-                client
-                    .rpc()
-                    .session_management()
-                    .set_cancel_on_disconnect(SetCancelOnDisconnectParams { timeout_secs: 6 })
-                    .await
-                    .ok();
                 client.resubscribe_all().await.ok();
                 state.lock().await.bid_order = None;
                 state.lock().await.ask_order = None;
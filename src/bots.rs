@@ -0,0 +1,797 @@
+//! Typed builders for `private/create_bot` and a watcher for `account.bots`.
+//!
+//! Each builder takes the fields the exchange requires up front, has setters for the
+//! optional ones and checks the combination client-side before anything is sent:
+//!
+//! ```ignore
+//! let params = GridBuilder::new("BTC-PERPETUAL", grid, dec!(0.01), end_time)
+//!     .exit_prices(dec!(110000), dec!(80000))
+//!     .label("grid")
+//!     .build()?;
+//! client.rpc().bot().create_bot(params).await?;
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    models::{
+        self, AccountBotsNotification, Bot, CreateBotParams, DFollow1, DHedge1, Grid1, Levels1,
+        Ocq1, Sgsl1, bot::StopReason,
+    },
+    types::{Error, RequestScope},
+    ws_client::WsClient,
+};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum BotValidationError {
+    #[error("end_time {end_time} is not in the future (now {now})")]
+    EndTimeInPast { end_time: Decimal, now: Decimal },
+    #[error("{field} must be positive, got {value}")]
+    NonPositive { field: &'static str, value: Decimal },
+    #[error("{0} must not be negative")]
+    Negative(&'static str),
+    #[error("{0} must be strictly ascending")]
+    NotMonotonic(&'static str),
+    #[error("{0} needs at least two levels")]
+    TooFewLevels(&'static str),
+    #[error("{0}")]
+    Inconsistent(&'static str),
+}
+
+/// Signal-driven stop loss / take profit (`sgsl`).
+#[derive(Clone, Debug)]
+pub struct SgslBuilder {
+    params: Sgsl1,
+}
+
+impl SgslBuilder {
+    /// Holds `target_position` while the signal is above `entry_price` and
+    /// `exit_position` once it drops below `exit_price`.
+    pub fn new(
+        instrument_name: impl Into<String>,
+        signal: models::sgsl_1::Signal,
+        entry_price: Decimal,
+        target_position: Decimal,
+        exit_price: Decimal,
+        exit_position: Decimal,
+        end_time: Decimal,
+    ) -> Self {
+        SgslBuilder {
+            params: Sgsl1 {
+                strategy: "sgsl".to_string(),
+                instrument_name: instrument_name.into(),
+                signal,
+                entry_price,
+                target_position,
+                exit_price,
+                exit_position,
+                end_time,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Maximum slippage per trade, in % of the mark price.
+    pub fn max_slippage(mut self, max_slippage: Decimal) -> Self {
+        self.params.max_slippage = Some(max_slippage);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.params.label = Some(label.into());
+        self
+    }
+
+    pub fn build(self) -> Result<CreateBotParams, BotValidationError> {
+        self.build_at(now())
+    }
+
+    /// Validates against `now`, a unix timestamp in seconds.
+    pub fn build_at(self, now: Decimal) -> Result<CreateBotParams, BotValidationError> {
+        let p = &self.params;
+        check_end_time(p.end_time, now)?;
+        check_optional_positive("max_slippage", p.max_slippage)?;
+        check_positive("entry_price", p.entry_price)?;
+        check_positive("exit_price", p.exit_price)?;
+        if p.exit_price > p.entry_price {
+            return Err(BotValidationError::Inconsistent(
+                "exit_price must not be above entry_price",
+            ));
+        }
+        Ok(CreateBotParams::Sgsl1(self.params))
+    }
+}
+
+/// One-cancels-the-other quoting around a signal price (`ocq`).
+#[derive(Clone, Debug)]
+pub struct OcqBuilder {
+    params: Ocq1,
+}
+
+impl OcqBuilder {
+    /// Quotes `quote_size` at `signal + bid_offset` and `signal + ask_offset` while the
+    /// position stays within `min_position..=max_position`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        instrument_name: impl Into<String>,
+        signal: models::ocq_1::Signal,
+        bid_offset: Decimal,
+        ask_offset: Decimal,
+        quote_size: Decimal,
+        min_position: Decimal,
+        max_position: Decimal,
+        end_time: Decimal,
+    ) -> Self {
+        OcqBuilder {
+            params: Ocq1 {
+                strategy: "ocq".to_string(),
+                instrument_name: instrument_name.into(),
+                signal,
+                bid_offset,
+                ask_offset,
+                quote_size,
+                min_position,
+                max_position,
+                end_time,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Offset of the order that flattens back to the target position.
+    pub fn exit_offset(mut self, exit_offset: Decimal) -> Self {
+        self.params.exit_offset = Some(exit_offset);
+        self
+    }
+
+    pub fn target_position(mut self, target_position: Decimal) -> Self {
+        self.params.target_position = Some(target_position);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.params.label = Some(label.into());
+        self
+    }
+
+    pub fn build(self) -> Result<CreateBotParams, BotValidationError> {
+        self.build_at(now())
+    }
+
+    pub fn build_at(self, now: Decimal) -> Result<CreateBotParams, BotValidationError> {
+        let p = &self.params;
+        check_end_time(p.end_time, now)?;
+        check_positive("quote_size", p.quote_size)?;
+        if p.bid_offset >= p.ask_offset {
+            return Err(BotValidationError::Inconsistent(
+                "bid_offset must be below ask_offset",
+            ));
+        }
+        if p.exit_offset
+            .is_some_and(|exit| exit < p.bid_offset || exit > p.ask_offset)
+        {
+            return Err(BotValidationError::Inconsistent(
+                "exit_offset must lie between bid_offset and ask_offset",
+            ));
+        }
+        if p.min_position >= p.max_position {
+            return Err(BotValidationError::Inconsistent(
+                "min_position must be below max_position",
+            ));
+        }
+        if p.target_position
+            .is_some_and(|target| target < p.min_position || target > p.max_position)
+        {
+            return Err(BotValidationError::Inconsistent(
+                "target_position must lie between min_position and max_position",
+            ));
+        }
+        Ok(CreateBotParams::Ocq1(self.params))
+    }
+}
+
+/// Trades `step_size` at each of a set of bid and ask levels (`levels`).
+#[derive(Clone, Debug)]
+pub struct LevelsBuilder {
+    params: Levels1,
+}
+
+impl LevelsBuilder {
+    /// `bids` and `asks` are given in ascending price order.
+    pub fn new(
+        instrument_name: impl Into<String>,
+        bids: Vec<Decimal>,
+        asks: Vec<Decimal>,
+        step_size: Decimal,
+        end_time: Decimal,
+    ) -> Self {
+        LevelsBuilder {
+            params: Levels1 {
+                strategy: "levels".to_string(),
+                instrument_name: instrument_name.into(),
+                bids,
+                asks,
+                step_size,
+                end_time,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Position the levels are counted from, and its average price.
+    pub fn base_position(mut self, base_position: Decimal, target_mean_price: Decimal) -> Self {
+        self.params.base_position = Some(base_position);
+        self.params.target_mean_price = Some(target_mean_price);
+        self
+    }
+
+    /// Prices beyond the levels at which the bot closes out and stops.
+    pub fn exit_prices(mut self, upside: Decimal, downside: Decimal) -> Self {
+        self.params.upside_exit_price = Some(upside);
+        self.params.downside_exit_price = Some(downside);
+        self
+    }
+
+    pub fn max_slippage(mut self, max_slippage: Decimal) -> Self {
+        self.params.max_slippage = Some(max_slippage);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.params.label = Some(label.into());
+        self
+    }
+
+    pub fn build(self) -> Result<CreateBotParams, BotValidationError> {
+        self.build_at(now())
+    }
+
+    pub fn build_at(self, now: Decimal) -> Result<CreateBotParams, BotValidationError> {
+        let p = &self.params;
+        check_end_time(p.end_time, now)?;
+        check_positive("step_size", p.step_size)?;
+        check_optional_positive("max_slippage", p.max_slippage)?;
+        check_ascending("bids", &p.bids)?;
+        check_ascending("asks", &p.asks)?;
+        if let (Some(bid), Some(ask)) = (p.bids.last(), p.asks.first())
+            && bid >= ask
+        {
+            return Err(BotValidationError::Inconsistent(
+                "every bid level must be below every ask level",
+            ));
+        }
+        let low = p.bids.first().or(p.asks.first());
+        let high = p.asks.last().or(p.bids.last());
+        match (low, high) {
+            (Some(&low), Some(&high)) => {
+                check_exit_prices(p.upside_exit_price, p.downside_exit_price, low, high)?
+            }
+            _ => {
+                return Err(BotValidationError::Inconsistent(
+                    "no bid or ask levels given",
+                ));
+            }
+        }
+        Ok(CreateBotParams::Levels1(self.params))
+    }
+}
+
+/// Trades `step_size` whenever the price crosses a grid level (`grid`).
+#[derive(Clone, Debug)]
+pub struct GridBuilder {
+    params: Grid1,
+}
+
+impl GridBuilder {
+    /// `grid` is given in ascending price order.
+    pub fn new(
+        instrument_name: impl Into<String>,
+        grid: Vec<Decimal>,
+        step_size: Decimal,
+        end_time: Decimal,
+    ) -> Self {
+        GridBuilder {
+            params: Grid1 {
+                strategy: "grid".to_string(),
+                instrument_name: instrument_name.into(),
+                grid,
+                step_size,
+                end_time,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Position held at `target_mean_price`, which must lie inside the grid.
+    pub fn base_position(mut self, base_position: Decimal, target_mean_price: Decimal) -> Self {
+        self.params.base_position = Some(base_position);
+        self.params.target_mean_price = Some(target_mean_price);
+        self
+    }
+
+    /// Prices beyond the grid at which the bot closes out and stops.
+    pub fn exit_prices(mut self, upside: Decimal, downside: Decimal) -> Self {
+        self.params.upside_exit_price = Some(upside);
+        self.params.downside_exit_price = Some(downside);
+        self
+    }
+
+    pub fn max_slippage(mut self, max_slippage: Decimal) -> Self {
+        self.params.max_slippage = Some(max_slippage);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.params.label = Some(label.into());
+        self
+    }
+
+    pub fn build(self) -> Result<CreateBotParams, BotValidationError> {
+        self.build_at(now())
+    }
+
+    pub fn build_at(self, now: Decimal) -> Result<CreateBotParams, BotValidationError> {
+        let p = &self.params;
+        check_end_time(p.end_time, now)?;
+        check_positive("step_size", p.step_size)?;
+        check_optional_positive("max_slippage", p.max_slippage)?;
+        if p.grid.len() < 2 {
+            return Err(BotValidationError::TooFewLevels("grid"));
+        }
+        check_ascending("grid", &p.grid)?;
+        let (low, high) = (p.grid[0], p.grid[p.grid.len() - 1]);
+        check_exit_prices(p.upside_exit_price, p.downside_exit_price, low, high)?;
+        if p.target_mean_price
+            .is_some_and(|price| price < low || price > high)
+        {
+            return Err(BotValidationError::Inconsistent(
+                "target_mean_price must lie inside the grid",
+            ));
+        }
+        Ok(CreateBotParams::Grid1(self.params))
+    }
+}
+
+/// Keeps the delta of a position or the whole portfolio near a target (`dhedge`).
+#[derive(Clone, Debug)]
+pub struct DHedgeBuilder {
+    params: DHedge1,
+}
+
+impl DHedgeBuilder {
+    /// Hedges with `instrument_name` every `period` seconds.
+    pub fn new(instrument_name: impl Into<String>, period: Decimal) -> Self {
+        DHedgeBuilder {
+            params: DHedge1 {
+                strategy: "dhedge".to_string(),
+                instrument_name: instrument_name.into(),
+                period,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Hedge only the delta of this position instead of the whole portfolio.
+    pub fn position(mut self, position: impl Into<String>) -> Self {
+        self.params.position = Some(position.into());
+        self
+    }
+
+    pub fn target_delta(mut self, target_delta: Decimal) -> Self {
+        self.params.target_delta = Some(target_delta);
+        self
+    }
+
+    /// Hedge once the delta is `threshold` away from target, back to within `tolerance`.
+    pub fn band(mut self, threshold: Decimal, tolerance: Decimal) -> Self {
+        self.params.threshold = Some(threshold);
+        self.params.tolerance = Some(tolerance);
+        self
+    }
+
+    pub fn max_slippage(mut self, max_slippage: Decimal) -> Self {
+        self.params.max_slippage = Some(max_slippage);
+        self
+    }
+
+    /// Without an end time the bot runs until cancelled.
+    pub fn end_time(mut self, end_time: Decimal) -> Self {
+        self.params.end_time = Some(end_time);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.params.label = Some(label.into());
+        self
+    }
+
+    pub fn build(self) -> Result<CreateBotParams, BotValidationError> {
+        self.build_at(now())
+    }
+
+    pub fn build_at(self, now: Decimal) -> Result<CreateBotParams, BotValidationError> {
+        let p = &self.params;
+        if let Some(end_time) = p.end_time {
+            check_end_time(end_time, now)?;
+        }
+        check_band(p.period, p.threshold, p.tolerance)?;
+        check_optional_positive("max_slippage", p.max_slippage)?;
+        Ok(CreateBotParams::DHedge1(self.params))
+    }
+}
+
+/// Keeps a position at a fixed ratio to another instrument's position (`dfollow`).
+#[derive(Clone, Debug)]
+pub struct DFollowBuilder {
+    params: DFollow1,
+}
+
+impl DFollowBuilder {
+    pub fn new(
+        instrument_name: impl Into<String>,
+        target_instrument: impl Into<String>,
+        target_amount: Decimal,
+        period: Decimal,
+        end_time: Decimal,
+    ) -> Self {
+        DFollowBuilder {
+            params: DFollow1 {
+                strategy: "dfollow".to_string(),
+                instrument_name: instrument_name.into(),
+                target_instrument: target_instrument.into(),
+                target_amount,
+                period,
+                end_time,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn band(mut self, threshold: Decimal, tolerance: Decimal) -> Self {
+        self.params.threshold = Some(threshold);
+        self.params.tolerance = Some(tolerance);
+        self
+    }
+
+    pub fn max_slippage(mut self, max_slippage: Decimal) -> Self {
+        self.params.max_slippage = Some(max_slippage);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.params.label = Some(label.into());
+        self
+    }
+
+    pub fn build(self) -> Result<CreateBotParams, BotValidationError> {
+        self.build_at(now())
+    }
+
+    pub fn build_at(self, now: Decimal) -> Result<CreateBotParams, BotValidationError> {
+        let p = &self.params;
+        check_end_time(p.end_time, now)?;
+        check_band(p.period, p.threshold, p.tolerance)?;
+        check_optional_positive("max_slippage", p.max_slippage)?;
+        if p.instrument_name == p.target_instrument {
+            return Err(BotValidationError::Inconsistent(
+                "target_instrument must differ from instrument_name",
+            ));
+        }
+        Ok(CreateBotParams::DFollow1(self.params))
+    }
+}
+
+fn now() -> Decimal {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(Decimal::ZERO, |d| Decimal::from(d.as_secs()))
+}
+
+fn check_end_time(end_time: Decimal, now: Decimal) -> Result<(), BotValidationError> {
+    if end_time <= now {
+        return Err(BotValidationError::EndTimeInPast { end_time, now });
+    }
+    Ok(())
+}
+
+fn check_positive(field: &'static str, value: Decimal) -> Result<(), BotValidationError> {
+    if value <= Decimal::ZERO {
+        return Err(BotValidationError::NonPositive { field, value });
+    }
+    Ok(())
+}
+
+fn check_optional_positive(
+    field: &'static str,
+    value: Option<Decimal>,
+) -> Result<(), BotValidationError> {
+    value.map_or(Ok(()), |value| check_positive(field, value))
+}
+
+fn check_ascending(field: &'static str, levels: &[Decimal]) -> Result<(), BotValidationError> {
+    if levels.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(BotValidationError::NotMonotonic(field));
+    }
+    if let Some(&first) = levels.first() {
+        check_positive(field, first)?;
+    }
+    Ok(())
+}
+
+/// Exit prices, where given, must lie outside `low..=high`.
+fn check_exit_prices(
+    upside: Option<Decimal>,
+    downside: Option<Decimal>,
+    low: Decimal,
+    high: Decimal,
+) -> Result<(), BotValidationError> {
+    if upside.is_some_and(|price| price <= high) {
+        return Err(BotValidationError::Inconsistent(
+            "upside_exit_price must be above the highest level",
+        ));
+    }
+    if downside.is_some_and(|price| price >= low) {
+        return Err(BotValidationError::Inconsistent(
+            "downside_exit_price must be below the lowest level",
+        ));
+    }
+    Ok(())
+}
+
+fn check_band(
+    period: Decimal,
+    threshold: Option<Decimal>,
+    tolerance: Option<Decimal>,
+) -> Result<(), BotValidationError> {
+    if period < Decimal::ZERO {
+        return Err(BotValidationError::Negative("period"));
+    }
+    check_optional_positive("threshold", threshold)?;
+    if tolerance.is_some_and(|tolerance| tolerance < Decimal::ZERO) {
+        return Err(BotValidationError::Negative("tolerance"));
+    }
+    if let (Some(threshold), Some(tolerance)) = (threshold, tolerance)
+        && tolerance > threshold
+    {
+        return Err(BotValidationError::Inconsistent(
+            "tolerance must not exceed threshold",
+        ));
+    }
+    Ok(())
+}
+
+/// Runs `$body` with `$b` bound to the inner model and `$m` to its module, for fields
+/// every strategy shares.
+macro_rules! with_bot {
+    ($bot:expr, |$b:ident, $m:ident| $body:expr) => {
+        match $bot {
+            Bot::Sgsl($b) => {
+                #[allow(unused_imports)]
+                use models::sgsl as $m;
+                $body
+            }
+            Bot::Ocq($b) => {
+                #[allow(unused_imports)]
+                use models::ocq as $m;
+                $body
+            }
+            Bot::Levels($b) => {
+                #[allow(unused_imports)]
+                use models::levels as $m;
+                $body
+            }
+            Bot::Grid($b) => {
+                #[allow(unused_imports)]
+                use models::grid as $m;
+                $body
+            }
+            Bot::DHedge($b) => {
+                #[allow(unused_imports)]
+                use models::d_hedge as $m;
+                $body
+            }
+            Bot::DFollow($b) => {
+                #[allow(unused_imports)]
+                use models::d_follow as $m;
+                $body
+            }
+        }
+    };
+}
+
+impl Bot {
+    pub fn bot_id(&self) -> &str {
+        with_bot!(self, |b, _m| &b.bot_id)
+    }
+
+    pub fn instrument_name(&self) -> &str {
+        with_bot!(self, |b, _m| &b.instrument_name)
+    }
+
+    pub fn is_active(&self) -> bool {
+        with_bot!(self, |b, m| b.status == m::Status::Active)
+    }
+
+    /// Why the bot stopped, in the strategy-independent [`StopReason`].
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        with_bot!(self, |b, m| b.stop_reason.map(|reason| match reason {
+            m::StopReason::ClientCancel => StopReason::ClientCancel,
+            m::StopReason::ClientBulkCancel => StopReason::ClientBulkCancel,
+            m::StopReason::EndTime => StopReason::EndTime,
+            m::StopReason::InstrumentDeactivated => StopReason::InstrumentDeactivated,
+            m::StopReason::MarginBreach => StopReason::MarginBreach,
+            m::StopReason::AdminCancel => StopReason::AdminCancel,
+            m::StopReason::Conflict => StopReason::Conflict,
+            m::StopReason::Strategy => StopReason::Strategy,
+            m::StopReason::SelfTradePrevention => StopReason::SelfTradePrevention,
+        }))
+    }
+
+    pub fn realized_pnl(&self) -> Decimal {
+        with_bot!(self, |b, _m| b.realized_pnl)
+    }
+
+    pub fn fee(&self) -> Decimal {
+        with_bot!(self, |b, _m| b.fee)
+    }
+}
+
+/// A change between two `account.bots` snapshots.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BotEvent {
+    Started(Bot),
+    /// `reason` is `None` when an active bot dropped out of the snapshot.
+    Stopped {
+        bot: Bot,
+        reason: Option<StopReason>,
+    },
+    PnlChanged {
+        bot_id: String,
+        previous: Decimal,
+        realized_pnl: Decimal,
+    },
+}
+
+impl BotEvent {
+    pub fn bot_id(&self) -> &str {
+        match self {
+            BotEvent::Started(bot) | BotEvent::Stopped { bot, .. } => bot.bot_id(),
+            BotEvent::PnlChanged { bot_id, .. } => bot_id,
+        }
+    }
+}
+
+pub type BotCallback = Box<dyn Fn(&BotEvent) + Send + Sync>;
+
+#[derive(Default)]
+struct BotSnapshot {
+    bots: HashMap<String, Bot>,
+    seeded: bool,
+}
+
+/// Turns the full `account.bots` snapshots into started, stopped and P&L events, in
+/// `bot_id` order.
+///
+/// The first snapshot only seeds the monitor, so bots already running or stopped when it
+/// subscribes produce no events; bots first seen stopped later are recorded silently too.
+#[derive(Clone, Default)]
+pub struct BotMonitor {
+    snapshot: Arc<RwLock<BotSnapshot>>,
+    callbacks: Arc<RwLock<Vec<BotCallback>>>,
+    subscription: Arc<Mutex<Option<SubscriptionHandle>>>,
+}
+
+impl BotMonitor {
+    pub fn new() -> Self {
+        BotMonitor::default()
+    }
+
+    pub async fn start(self, client: Arc<WsClient>) -> Result<Self, Error> {
+        let handler = self.clone();
+        let subscription = client
+            .subscribe_shared_channel(
+                RequestScope::Private,
+                "account.bots".to_string(),
+                move |msg: AccountBotsNotification| {
+                    handler.apply(msg.notification);
+                },
+            )
+            .await?;
+        *self.subscription.lock().unwrap() = Some(subscription);
+        info!("Bot monitor started");
+        Ok(self)
+    }
+
+    /// Unsubscribes from `account.bots`. The last snapshot is kept.
+    pub async fn stop(&self, client: &WsClient) {
+        let subscription = self.subscription.lock().unwrap().take();
+        if let Err(e) = client.release(subscription).await {
+            warn!("Failed to unsubscribe account.bots: {e}");
+        }
+    }
+
+    pub fn on_event(&self, callback: impl Fn(&BotEvent) + Send + Sync + 'static) {
+        self.callbacks.write().unwrap().push(Box::new(callback));
+    }
+
+    pub fn get(&self, bot_id: &str) -> Option<Bot> {
+        self.snapshot.read().unwrap().bots.get(bot_id).cloned()
+    }
+
+    pub fn active(&self) -> Vec<Bot> {
+        let snapshot = self.snapshot.read().unwrap();
+        snapshot
+            .bots
+            .values()
+            .filter(|bot| bot.is_active())
+            .cloned()
+            .collect()
+    }
+
+    /// Diffs `snapshot` against the previous one, fires the callbacks and returns the
+    /// events.
+    pub fn apply(&self, snapshot: Vec<Bot>) -> Vec<BotEvent> {
+        let current: HashMap<String, Bot> = snapshot
+            .into_iter()
+            .map(|bot| (bot.bot_id().to_string(), bot))
+            .collect();
+        let (previous, seeded) = {
+            let mut snapshot = self.snapshot.write().unwrap();
+            (
+                std::mem::replace(&mut snapshot.bots, current.clone()),
+                std::mem::replace(&mut snapshot.seeded, true),
+            )
+        };
+        if !seeded {
+            return Vec::new();
+        }
+
+        let mut events = Vec::new();
+        for (bot_id, bot) in &current {
+            match previous.get(bot_id) {
+                None if bot.is_active() => events.push(BotEvent::Started(bot.clone())),
+                None => {}
+                Some(before) if before.is_active() && !bot.is_active() => {
+                    events.push(BotEvent::Stopped {
+                        bot: bot.clone(),
+                        reason: bot.stop_reason(),
+                    })
+                }
+                Some(before) if before.realized_pnl() != bot.realized_pnl() => {
+                    events.push(BotEvent::PnlChanged {
+                        bot_id: bot_id.clone(),
+                        previous: before.realized_pnl(),
+                        realized_pnl: bot.realized_pnl(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for (bot_id, before) in &previous {
+            if before.is_active() && !current.contains_key(bot_id) {
+                events.push(BotEvent::Stopped {
+                    bot: before.clone(),
+                    reason: None,
+                });
+            }
+        }
+        events.sort_by(|a, b| a.bot_id().cmp(b.bot_id()));
+
+        let callbacks = self.callbacks.read().unwrap();
+        for event in &events {
+            for callback in callbacks.iter() {
+                callback(event);
+            }
+        }
+        events
+    }
+}
@@ -0,0 +1,135 @@
+use bytes::Bytes;
+use log::warn;
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc;
+
+use crate::{models::RpcErrorResponse, types::RequestScope, ws_client::deserialise_to_type};
+
+/// Type-erased handler invoked with the raw bytes of every message on a channel.
+pub type ChannelHandler = Box<dyn FnMut(Bytes) + Send + 'static>;
+
+/// Wraps a typed callback into a [`ChannelHandler`] that parses each message into `P` first.
+pub(crate) fn typed_handler<P, F>(mut callback: F) -> ChannelHandler
+where
+    P: DeserializeOwned + Send + 'static,
+    F: FnMut(P) + Send + 'static,
+{
+    Box::new(move |msg: Bytes| match deserialise_to_type::<P>(&msg) {
+        Ok(parsed) => callback(parsed),
+        Err(e) => warn!("Failed to parse channel message: {e}; raw: {msg:?}"),
+    })
+}
+
+/// A set of channels to subscribe to at once.
+///
+/// All channels of the same scope are sent in a single `public/subscribe` or
+/// `private/subscribe` request by [`WsClient::subscribe_batch`](crate::ws_client::WsClient::subscribe_batch)
+/// or [`WsClient::subscribe_shared`](crate::ws_client::WsClient::subscribe_shared).
+#[derive(Default)]
+pub struct ChannelBatch {
+    pub(crate) public: Vec<(String, ChannelHandler)>,
+    pub(crate) private: Vec<(String, ChannelHandler)>,
+}
+
+impl ChannelBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a callback for `channel`. `P` is usually the `*Notification` model of the
+    /// channel, which carries the `channel_name` alongside the payload.
+    pub fn add<P, F>(
+        &mut self,
+        scope: RequestScope,
+        channel: impl Into<String>,
+        callback: F,
+    ) -> &mut Self
+    where
+        P: DeserializeOwned + Send + 'static,
+        F: FnMut(P) + Send + 'static,
+    {
+        let entry = (channel.into(), typed_handler(callback));
+        match scope {
+            RequestScope::Public => self.public.push(entry),
+            RequestScope::Private => self.private.push(entry),
+        }
+        self
+    }
+
+    /// Registers `channel` and returns a receiver yielding its parsed messages.
+    pub fn stream<P>(
+        &mut self,
+        scope: RequestScope,
+        channel: impl Into<String>,
+    ) -> mpsc::UnboundedReceiver<P>
+    where
+        P: DeserializeOwned + Send + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded_channel::<P>();
+        self.add(scope, channel, move |msg: P| {
+            let _ = tx.send(msg);
+        });
+        rx
+    }
+
+    pub fn len(&self) -> usize {
+        self.public.len() + self.private.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.public.is_empty() && self.private.is_empty()
+    }
+}
+
+/// Per-channel outcome of [`WsClient::subscribe_batch`](crate::ws_client::WsClient::subscribe_batch).
+#[derive(Debug, Default, Clone)]
+pub struct BatchSubscribeResult {
+    /// Channels confirmed by the server.
+    pub subscribed: Vec<String>,
+    /// Channels that were not confirmed, with the error if the whole request was rejected.
+    pub failed: Vec<(String, Option<RpcErrorResponse>)>,
+}
+
+impl BatchSubscribeResult {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// One handler added to a shared channel by
+/// [`WsClient::subscribe_shared`](crate::ws_client::WsClient::subscribe_shared).
+///
+/// Shared channels fan every message out to all of their handlers and stay subscribed until
+/// the last handle is given to [`WsClient::release`](crate::ws_client::WsClient::release).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SubscriptionHandle {
+    pub(crate) channel: String,
+    pub(crate) id: u64,
+}
+
+impl SubscriptionHandle {
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+}
+
+/// Per-channel outcome of
+/// [`WsClient::subscribe_shared`](crate::ws_client::WsClient::subscribe_shared).
+#[derive(Debug, Default, Clone)]
+pub struct SharedSubscribeResult {
+    /// Handlers now receiving the messages of their channel.
+    pub subscribed: Vec<SubscriptionHandle>,
+    /// Channels that were not confirmed, with the error if the whole request was rejected.
+    pub failed: Vec<(String, Option<RpcErrorResponse>)>,
+}
+
+impl SharedSubscribeResult {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Whether `channel` was subscribed by this request.
+    pub fn is_subscribed(&self, channel: &str) -> bool {
+        self.subscribed.iter().any(|h| h.channel == channel)
+    }
+}
@@ -0,0 +1,353 @@
+use std::{collections::HashSet, fmt, sync::Arc};
+
+use dashmap::DashMap;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    instrument_registry::InstrumentQuery,
+    models::{
+        DirectionEnum, InsertParams, InsertRequestLegsInner, Instrument, OptionTypeEnum,
+        OrderTypeEnum, RequiredMarginForOrderParams, RequiredMarginForOrderParamsLegsInner,
+        RfqLegsInner, TimeInForceEnum, TypeEnum,
+    },
+    ws_client::WsClient,
+};
+
+const MIN_LEGS: usize = 2;
+const MAX_LEGS: usize = 4;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ComboError {
+    #[error("combinations take {MIN_LEGS} to {MAX_LEGS} legs, got {0}")]
+    LegCount(usize),
+    #[error("instrument {0} appears in more than one leg")]
+    DuplicateLeg(String),
+    #[error("leg {instrument_name} has quantity {quantity}; quantities must be non-zero integers")]
+    InvalidQuantity {
+        instrument_name: String,
+        quantity: Decimal,
+    },
+    #[error("no instrument matches {0}")]
+    InstrumentNotFound(String),
+}
+
+/// One leg of a [`Combo`]: units of `instrument_name` per unit of the combination,
+/// negative for short legs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ComboLeg {
+    pub instrument_name: String,
+    pub quantity: Decimal,
+}
+
+impl ComboLeg {
+    pub fn new(instrument_name: impl Into<String>, quantity: Decimal) -> Self {
+        ComboLeg {
+            instrument_name: instrument_name.into(),
+            quantity,
+        }
+    }
+}
+
+/// A validated set of legs, convertible to every API that takes combination legs.
+///
+/// Buying one unit of the combination trades each leg's quantity; selling trades the
+/// opposite. Combination orders are immediate-or-cancel only.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Combo {
+    legs: Vec<ComboLeg>,
+}
+
+impl Combo {
+    pub fn new(legs: Vec<ComboLeg>) -> Result<Self, ComboError> {
+        if !(MIN_LEGS..=MAX_LEGS).contains(&legs.len()) {
+            return Err(ComboError::LegCount(legs.len()));
+        }
+        let mut seen = HashSet::new();
+        for leg in &legs {
+            if leg.quantity.is_zero() || !leg.quantity.fract().is_zero() {
+                return Err(ComboError::InvalidQuantity {
+                    instrument_name: leg.instrument_name.clone(),
+                    quantity: leg.quantity,
+                });
+            }
+            if !seen.insert(leg.instrument_name.as_str()) {
+                return Err(ComboError::DuplicateLeg(leg.instrument_name.clone()));
+            }
+        }
+        Ok(Combo { legs })
+    }
+
+    pub fn legs(&self) -> &[ComboLeg] {
+        &self.legs
+    }
+
+    /// The same legs with every quantity negated.
+    pub fn inverted(&self) -> Combo {
+        Combo {
+            legs: self
+                .legs
+                .iter()
+                .map(|l| ComboLeg::new(l.instrument_name.clone(), -l.quantity))
+                .collect(),
+        }
+    }
+
+    /// Name used in `book.<instrument>...` and `ticker.<instrument>...` channels,
+    /// e.g. `[BTC-30MAY25-93000-C:-1,BTC-30MAY25-80000-C:1]`.
+    pub fn book_name(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn insert_legs(&self) -> Vec<InsertRequestLegsInner> {
+        self.legs
+            .iter()
+            .map(|l| InsertRequestLegsInner::new(l.instrument_name.clone(), l.quantity))
+            .collect()
+    }
+
+    pub fn margin_legs(&self) -> Vec<RequiredMarginForOrderParamsLegsInner> {
+        self.legs
+            .iter()
+            .map(|l| {
+                RequiredMarginForOrderParamsLegsInner::new(l.instrument_name.clone(), l.quantity)
+            })
+            .collect()
+    }
+
+    /// RFQ legs, with fees charged on the full quantity of every leg.
+    pub fn rfq_legs(&self) -> Vec<RfqLegsInner> {
+        self.legs
+            .iter()
+            .map(|l| RfqLegsInner::new(l.instrument_name.clone(), l.quantity, l.quantity.abs()))
+            .collect()
+    }
+
+    /// Immediate-or-cancel limit order for `amount` units at `price` per unit.
+    pub fn insert_params(
+        &self,
+        direction: DirectionEnum,
+        amount: Decimal,
+        price: Decimal,
+    ) -> InsertParams {
+        InsertParams {
+            direction,
+            legs: Some(self.insert_legs()),
+            amount,
+            price: Some(price),
+            order_type: Some(OrderTypeEnum::Limit),
+            time_in_force: Some(TimeInForceEnum::ImmediateOrCancel),
+            ..Default::default()
+        }
+    }
+
+    /// Margin of buying `amount` units at `price`; use [`Combo::inverted`] for a sale.
+    pub fn margin_params(&self, amount: Decimal, price: Decimal) -> RequiredMarginForOrderParams {
+        RequiredMarginForOrderParams {
+            instrument_name: None,
+            legs: Some(self.margin_legs()),
+            price,
+            amount,
+        }
+    }
+}
+
+impl fmt::Display for Combo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let legs: Vec<String> = self
+            .legs
+            .iter()
+            .map(|l| format!("{}:{}", l.instrument_name, l.quantity.normalize()))
+            .collect();
+        write!(f, "[{}]", legs.join(","))
+    }
+}
+
+/// Standard option and futures structures, resolved against the instrument cache.
+///
+/// Underlyings are index names such as `BTCUSD` and expiries ISO dates (`YYYY-mm-dd`),
+/// as in [`InstrumentQuery`]. Every template is the long side of the structure.
+#[derive(Clone)]
+pub struct ComboTemplates {
+    instruments: Arc<DashMap<String, Instrument>>,
+}
+
+impl ComboTemplates {
+    pub fn new(instruments: Arc<DashMap<String, Instrument>>) -> Self {
+        ComboTemplates { instruments }
+    }
+
+    pub fn for_client(client: &WsClient) -> Self {
+        ComboTemplates::new(client.instruments_cache.clone())
+    }
+
+    fn find(&self, query: InstrumentQuery) -> Result<String, ComboError> {
+        self.instruments
+            .iter()
+            .find(|e| query.matches(e.value()))
+            .map(|e| e.key().clone())
+            .ok_or_else(|| ComboError::InstrumentNotFound(format!("{query:?}")))
+    }
+
+    /// Option of `underlying` expiring on `expiry_date` at exactly `strike`.
+    pub fn option(
+        &self,
+        underlying: &str,
+        expiry_date: &str,
+        strike: Decimal,
+        option_type: OptionTypeEnum,
+    ) -> Result<String, ComboError> {
+        self.find(InstrumentQuery {
+            underlying: Some(underlying.to_string()),
+            instrument_type: Some(TypeEnum::Option),
+            option_type: Some(option_type),
+            expiry_date: Some(expiry_date.to_string()),
+            min_strike: Some(strike),
+            max_strike: Some(strike),
+        })
+    }
+
+    pub fn future(&self, underlying: &str, expiry_date: &str) -> Result<String, ComboError> {
+        self.find(InstrumentQuery {
+            underlying: Some(underlying.to_string()),
+            instrument_type: Some(TypeEnum::Future),
+            expiry_date: Some(expiry_date.to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn combo(&self, legs: Vec<(String, i64)>) -> Result<Combo, ComboError> {
+        Combo::new(
+            legs.into_iter()
+                .map(|(name, quantity)| ComboLeg::new(name, Decimal::from(quantity)))
+                .collect(),
+        )
+    }
+
+    /// Long `long_strike`, short `short_strike`; a bull spread for calls with
+    /// `long_strike < short_strike`.
+    pub fn vertical(
+        &self,
+        underlying: &str,
+        expiry_date: &str,
+        option_type: OptionTypeEnum,
+        long_strike: Decimal,
+        short_strike: Decimal,
+    ) -> Result<Combo, ComboError> {
+        self.combo(vec![
+            (
+                self.option(underlying, expiry_date, long_strike, option_type)?,
+                1,
+            ),
+            (
+                self.option(underlying, expiry_date, short_strike, option_type)?,
+                -1,
+            ),
+        ])
+    }
+
+    /// Short the near expiry, long the far expiry, same strike.
+    pub fn calendar(
+        &self,
+        underlying: &str,
+        near_expiry: &str,
+        far_expiry: &str,
+        strike: Decimal,
+        option_type: OptionTypeEnum,
+    ) -> Result<Combo, ComboError> {
+        self.combo(vec![
+            (
+                self.option(underlying, near_expiry, strike, option_type)?,
+                -1,
+            ),
+            (self.option(underlying, far_expiry, strike, option_type)?, 1),
+        ])
+    }
+
+    pub fn straddle(
+        &self,
+        underlying: &str,
+        expiry_date: &str,
+        strike: Decimal,
+    ) -> Result<Combo, ComboError> {
+        self.strangle(underlying, expiry_date, strike, strike)
+    }
+
+    pub fn strangle(
+        &self,
+        underlying: &str,
+        expiry_date: &str,
+        put_strike: Decimal,
+        call_strike: Decimal,
+    ) -> Result<Combo, ComboError> {
+        self.combo(vec![
+            (
+                self.option(underlying, expiry_date, put_strike, OptionTypeEnum::Put)?,
+                1,
+            ),
+            (
+                self.option(underlying, expiry_date, call_strike, OptionTypeEnum::Call)?,
+                1,
+            ),
+        ])
+    }
+
+    /// Long the wings, short two of the body.
+    pub fn butterfly(
+        &self,
+        underlying: &str,
+        expiry_date: &str,
+        option_type: OptionTypeEnum,
+        low_strike: Decimal,
+        mid_strike: Decimal,
+        high_strike: Decimal,
+    ) -> Result<Combo, ComboError> {
+        self.combo(vec![
+            (
+                self.option(underlying, expiry_date, low_strike, option_type)?,
+                1,
+            ),
+            (
+                self.option(underlying, expiry_date, mid_strike, option_type)?,
+                -2,
+            ),
+            (
+                self.option(underlying, expiry_date, high_strike, option_type)?,
+                1,
+            ),
+        ])
+    }
+
+    /// Long the call, short the put.
+    pub fn risk_reversal(
+        &self,
+        underlying: &str,
+        expiry_date: &str,
+        put_strike: Decimal,
+        call_strike: Decimal,
+    ) -> Result<Combo, ComboError> {
+        self.combo(vec![
+            (
+                self.option(underlying, expiry_date, put_strike, OptionTypeEnum::Put)?,
+                -1,
+            ),
+            (
+                self.option(underlying, expiry_date, call_strike, OptionTypeEnum::Call)?,
+                1,
+            ),
+        ])
+    }
+
+    /// Short the near future, long the far one: rolls a long position forward.
+    pub fn futures_roll(
+        &self,
+        underlying: &str,
+        near_expiry: &str,
+        far_expiry: &str,
+    ) -> Result<Combo, ComboError> {
+        self.combo(vec![
+            (self.future(underlying, near_expiry)?, -1),
+            (self.future(underlying, far_expiry)?, 1),
+        ])
+    }
+}
@@ -0,0 +1,551 @@
+//! Client-side hedging of the whole portfolio's delta with perpetuals or futures.
+//!
+//! The server-side `dhedge` bot hedges a single position. [`DeltaHedger`] takes the net
+//! delta of everything on one base asset from [`LiveGreeks`] and trades it back into a
+//! band with an immediate-or-cancel limit order on the cheapest hedge instrument.
+
+use std::{
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use log::{debug, info, warn};
+use rust_decimal::{Decimal, prelude::FromPrimitive};
+use tokio::{task::JoinHandle, time::interval};
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    greeks_aggregator::LiveGreeks,
+    models::{
+        Delay, DirectionEnum, InsertParams, OrderTypeEnum, Ticker, TickerNotification,
+        TimeInForceEnum,
+    },
+    order_manager::{OrderManager, SubmitError, SubmittedOrder, is_closed},
+    portfolio_tracker::PortfolioTracker,
+    ticks::{Rounding, TickSpec},
+    types::{Error, RequestScope},
+    ws_client::WsClient,
+};
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+/// How long a sent hedge may take to show up in the portfolio before it is no longer
+/// netted out of the delta.
+const IN_FLIGHT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What to hedge, with what, and when.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HedgeConfig {
+    /// Base asset whose delta is hedged, e.g. `BTC`.
+    pub base: String,
+    /// Perpetuals and futures to hedge with, in order of preference on equal cost.
+    pub instruments: Vec<String>,
+    pub target_delta: Decimal,
+    /// Hedge once the delta is more than this away from target...
+    pub threshold: Decimal,
+    /// ...back to within this of it.
+    pub tolerance: Decimal,
+    /// Smallest hedge worth sending.
+    pub min_size: Decimal,
+    /// Limit of the hedge order, and the most an instrument's touch may be away from
+    /// its mark, in basis points of the mark.
+    pub max_slippage_bps: Decimal,
+    pub interval: Duration,
+    pub dry_run: bool,
+    pub label: Option<String>,
+}
+
+impl HedgeConfig {
+    /// Hedges `base` with its perpetual, keeping delta within 0.1 of flat.
+    pub fn new(base: impl Into<String>) -> Self {
+        let base = base.into();
+        HedgeConfig {
+            instruments: vec![format!("{base}-PERPETUAL")],
+            base,
+            target_delta: Decimal::ZERO,
+            threshold: Decimal::new(1, 1),
+            tolerance: Decimal::ZERO,
+            min_size: Decimal::ZERO,
+            max_slippage_bps: Decimal::from(10),
+            interval: Duration::from_secs(10),
+            dry_run: false,
+            label: None,
+        }
+    }
+
+    pub fn instruments<I, S>(mut self, instruments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.instruments = instruments.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn target_delta(mut self, target_delta: Decimal) -> Self {
+        self.target_delta = target_delta;
+        self
+    }
+
+    pub fn band(mut self, threshold: Decimal, tolerance: Decimal) -> Self {
+        self.threshold = threshold;
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn min_size(mut self, min_size: Decimal) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_slippage_bps(mut self, max_slippage_bps: Decimal) -> Self {
+        self.max_slippage_bps = max_slippage_bps;
+        self
+    }
+
+    /// How often the delta is checked.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Report the hedges that would be sent without sending them.
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+}
+
+/// A hedge order the hedger wants to send.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedHedge {
+    pub instrument_name: String,
+    pub direction: DirectionEnum,
+    pub amount: Decimal,
+    pub limit_price: Decimal,
+    /// Net delta that was hedged.
+    pub delta: Decimal,
+    /// Distance from mark to the touch we cross, in basis points; negative if the
+    /// touch is better than mark.
+    pub cost_bps: Decimal,
+}
+
+impl PlannedHedge {
+    /// Delta the hedge adds once it has filled.
+    pub fn signed_amount(&self) -> Decimal {
+        signed(self.direction, self.amount)
+    }
+
+    pub fn params(&self, label: Option<String>) -> InsertParams {
+        InsertParams {
+            direction: self.direction,
+            instrument_name: Some(self.instrument_name.clone()),
+            amount: self.amount,
+            price: Some(self.limit_price),
+            order_type: Some(OrderTypeEnum::Limit),
+            time_in_force: Some(TimeInForceEnum::ImmediateOrCancel),
+            label,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HedgeOutcome {
+    DryRun,
+    Sent(Box<SubmittedOrder>),
+    Failed(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HedgeReport {
+    pub plan: PlannedHedge,
+    pub outcome: HedgeOutcome,
+}
+
+pub type HedgeCallback = Box<dyn Fn(&HedgeReport) + Send + Sync>;
+
+/// A sent hedge that is not in the portfolio yet.
+struct InFlightHedge {
+    client_order_id: i32,
+    /// The insert as sent, to tell the order apart from older ones under the same id.
+    params: InsertParams,
+    instrument_name: String,
+    direction: DirectionEnum,
+    /// Delta the hedge adds: the filled amount once known, the full amount until then.
+    amount: Decimal,
+    /// Whether the order is closed and `amount` is final.
+    settled: bool,
+    /// Position in the instrument before the hedge was sent.
+    position: Decimal,
+    sent: Instant,
+}
+
+struct Shared {
+    config: HedgeConfig,
+    tickers: DashMap<String, Ticker>,
+    specs: DashMap<String, TickSpec>,
+    callbacks: RwLock<Vec<HedgeCallback>>,
+    orders: OnceLock<OrderManager>,
+    portfolio: OnceLock<PortfolioTracker>,
+    in_flight: Mutex<Vec<InFlightHedge>>,
+}
+
+impl Shared {
+    fn orders(&self) -> &OrderManager {
+        self.orders.get_or_init(OrderManager::new)
+    }
+
+    /// Delta of the hedges sent but not yet in the portfolio. Hedges of unknown outcome
+    /// are looked up first; one that was never placed is forgotten.
+    async fn in_flight_delta(&self, client: &WsClient) -> Decimal {
+        let Some(portfolio) = self.portfolio.get() else {
+            return Decimal::ZERO;
+        };
+        let pending = std::mem::take(&mut *self.in_flight.lock().unwrap());
+        let mut kept = Vec::new();
+        for mut hedge in pending {
+            if !hedge.settled {
+                match self.orders().resolve(client, &hedge.params).await {
+                    Ok(Some(SubmittedOrder::Live(order))) => {
+                        hedge.settle_with(&order.filled_amount, is_closed(&order))
+                    }
+                    Ok(Some(SubmittedOrder::Closed(order))) => {
+                        hedge.settle_with(&order.filled_amount, true)
+                    }
+                    Ok(Some(SubmittedOrder::Acknowledged(_))) => {}
+                    Ok(None) => continue,
+                    Err(e) => warn!("Hedge {} still unresolved: {e}", hedge.client_order_id),
+                }
+            }
+            let moved = portfolio.position(&hedge.instrument_name) - hedge.position;
+            if hedge.settled
+                && (hedge.amount.is_zero()
+                    || signed(hedge.direction, moved) >= signed(hedge.direction, hedge.amount))
+            {
+                continue;
+            }
+            if hedge.sent.elapsed() > IN_FLIGHT_TIMEOUT {
+                warn!(
+                    "Hedge {} on {} not seen in the portfolio after {IN_FLIGHT_TIMEOUT:?}",
+                    hedge.client_order_id, hedge.instrument_name
+                );
+                continue;
+            }
+            kept.push(hedge);
+        }
+        let delta = kept.iter().map(|h| h.amount).sum();
+        self.in_flight.lock().unwrap().extend(kept);
+        delta
+    }
+
+    fn cost_bps(&self, instrument_name: &str, direction: DirectionEnum) -> Option<Decimal> {
+        let ticker = self.tickers.get(instrument_name)?;
+        let mark = Some(ticker.mark_price).filter(|m| *m > Decimal::ZERO)?;
+        let cost = match direction {
+            DirectionEnum::Buy => ticker.best_ask_price? - mark,
+            DirectionEnum::Sell => mark - ticker.best_bid_price?,
+        };
+        Some(cost / mark * BPS)
+    }
+
+    fn plan(&self, delta: Decimal) -> Option<PlannedHedge> {
+        let config = &self.config;
+        let excess = delta - config.target_delta;
+        if excess.abs() <= config.threshold {
+            return None;
+        }
+        let direction = if excess > Decimal::ZERO {
+            DirectionEnum::Sell
+        } else {
+            DirectionEnum::Buy
+        };
+        let (instrument_name, cost_bps) = config
+            .instruments
+            .iter()
+            .filter_map(|name| Some((name, self.cost_bps(name, direction)?)))
+            .filter(|(_, cost)| *cost <= config.max_slippage_bps)
+            .min_by_key(|(_, cost)| *cost)?;
+        let spec = *self.specs.get(instrument_name)?;
+        let mark = self.tickers.get(instrument_name)?.mark_price;
+
+        let amount = spec.amount(excess.abs() - config.tolerance, Rounding::Down);
+        if !spec.is_tradable(amount) || *amount < config.min_size {
+            debug!(
+                "Hedge of {excess} {} is below the minimum size",
+                config.base
+            );
+            return None;
+        }
+        let slippage = mark * config.max_slippage_bps / BPS;
+        let limit = match direction {
+            DirectionEnum::Buy => mark + slippage,
+            DirectionEnum::Sell => mark - slippage,
+        };
+        Some(PlannedHedge {
+            instrument_name: instrument_name.clone(),
+            direction,
+            amount: amount.into(),
+            limit_price: spec.passive_price(limit, direction).into(),
+            delta,
+            cost_bps,
+        })
+    }
+
+    async fn hedge(&self, client: &WsClient, delta: Decimal) -> Option<HedgeReport> {
+        let delta = delta + self.in_flight_delta(client).await;
+        let plan = self.plan(delta)?;
+        let outcome = if self.config.dry_run {
+            HedgeOutcome::DryRun
+        } else {
+            self.send(client, &plan).await
+        };
+        match &outcome {
+            HedgeOutcome::Failed(e) => warn!("Delta hedge failed: {e}"),
+            _ => info!(
+                "Delta {} {}: {} {} {} at {} ({:?})",
+                delta,
+                self.config.base,
+                plan.direction,
+                plan.amount,
+                plan.instrument_name,
+                plan.limit_price,
+                outcome
+            ),
+        }
+        let report = HedgeReport { plan, outcome };
+        for callback in self.callbacks.read().unwrap().iter() {
+            callback(&report);
+        }
+        Some(report)
+    }
+
+    /// Sends `plan` with a fresh `client_order_id` and, when following a portfolio,
+    /// tracks it until it shows up there.
+    async fn send(&self, client: &WsClient, plan: &PlannedHedge) -> HedgeOutcome {
+        let client_order_id = client.next_client_order_id();
+        let params = InsertParams {
+            client_order_id: Some(client_order_id),
+            ..plan.params(self.config.label.clone())
+        };
+        let position = self
+            .portfolio
+            .get()
+            .map(|portfolio| portfolio.position(&plan.instrument_name));
+        let result = self.orders().submit(client, params.clone()).await;
+        if let Err(e @ SubmitError::Rejected(_)) = result {
+            return HedgeOutcome::Failed(e.to_string());
+        }
+
+        let mut in_flight = InFlightHedge {
+            client_order_id,
+            params,
+            instrument_name: plan.instrument_name.clone(),
+            direction: plan.direction,
+            amount: plan.signed_amount(),
+            settled: false,
+            position: position.unwrap_or_default(),
+            sent: Instant::now(),
+        };
+        let outcome = match result {
+            Ok(order) => {
+                match &order {
+                    SubmittedOrder::Live(o) => {
+                        in_flight.settle_with(&o.filled_amount, is_closed(o))
+                    }
+                    SubmittedOrder::Closed(o) => in_flight.settle_with(&o.filled_amount, true),
+                    SubmittedOrder::Acknowledged(_) => {}
+                }
+                HedgeOutcome::Sent(Box::new(order))
+            }
+            // Unknown outcome: counted in full until it is resolved.
+            Err(e) => HedgeOutcome::Failed(e.to_string()),
+        };
+        if position.is_some() {
+            self.in_flight.lock().unwrap().push(in_flight);
+        }
+        outcome
+    }
+}
+
+impl InFlightHedge {
+    fn settle_with(&mut self, filled_amount: &Decimal, closed: bool) {
+        if closed {
+            self.amount = signed(self.direction, *filled_amount);
+            self.settled = true;
+        }
+    }
+}
+
+/// Keeps the portfolio delta on one base asset inside [`HedgeConfig`]'s band.
+///
+/// Delta comes from [`LiveGreeks`]: perpetuals and futures count as one, options use
+/// their ticker delta. No hedge is sent while any position on the base asset is
+/// missing greeks. Among the configured instruments the one whose touch is closest to
+/// its mark in the hedge direction is used.
+///
+/// Hedges go through [`OrderManager::submit`]. Until a sent hedge shows up in the
+/// portfolio, its fill (or, while the outcome is unknown, its full amount) is added to
+/// the delta, so the same exposure is not hedged twice.
+pub struct DeltaHedger {
+    shared: Arc<Shared>,
+    client: Option<Arc<WsClient>>,
+    subscriptions: Vec<SubscriptionHandle>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl DeltaHedger {
+    /// Creates a hedger without subscribing to anything; feed it with
+    /// [`DeltaHedger::update_ticker`] and [`DeltaHedger::set_tick_spec`] to plan by hand.
+    pub fn new(config: HedgeConfig) -> Self {
+        DeltaHedger {
+            shared: Arc::new(Shared {
+                config,
+                tickers: DashMap::new(),
+                specs: DashMap::new(),
+                callbacks: RwLock::new(Vec::new()),
+                orders: OnceLock::new(),
+                portfolio: OnceLock::new(),
+                in_flight: Mutex::new(Vec::new()),
+            }),
+            client: None,
+            subscriptions: Vec::new(),
+            task: None,
+        }
+    }
+
+    /// Tracks hedge orders in `orders` instead of a private [`OrderManager`]. Has no
+    /// effect once a hedge was sent.
+    pub fn with_orders(self, orders: OrderManager) -> Self {
+        let _ = self.shared.orders.set(orders);
+        self
+    }
+
+    /// Subscribes to the hedge instruments' tickers and starts hedging `portfolio`.
+    pub async fn start(
+        mut self,
+        client: Arc<WsClient>,
+        portfolio: PortfolioTracker,
+        delay: Delay,
+    ) -> Result<Self, Error> {
+        self.client = Some(client.clone());
+        let _ = self.shared.portfolio.set(portfolio.clone());
+        for name in &self.shared.config.instruments {
+            let spec = client.fetch_tick_spec(name).await?;
+            self.shared.specs.insert(name.clone(), spec);
+            let shared = self.shared.clone();
+            let instrument_name = name.clone();
+            let handle = client
+                .subscribe_shared_channel(
+                    RequestScope::Public,
+                    format!("ticker.{name}.{delay}"),
+                    move |msg: TickerNotification| {
+                        shared
+                            .tickers
+                            .insert(instrument_name.clone(), msg.notification);
+                    },
+                )
+                .await?;
+            self.subscriptions.push(handle);
+        }
+
+        let greeks = LiveGreeks::start(client.clone(), portfolio, delay);
+        let shared = self.shared.clone();
+        self.task = Some(tokio::spawn(async move {
+            let mut timer = interval(shared.config.interval);
+            loop {
+                timer.tick().await;
+                let Some(delta) = portfolio_delta(&greeks, &shared.config.base) else {
+                    continue;
+                };
+                shared.hedge(&client, delta).await;
+            }
+        }));
+        info!(
+            "Delta hedging {} with {:?}{}",
+            self.shared.config.base,
+            self.shared.config.instruments,
+            if self.shared.config.dry_run {
+                " (dry run)"
+            } else {
+                ""
+            }
+        );
+        Ok(self)
+    }
+
+    pub fn config(&self) -> &HedgeConfig {
+        &self.shared.config
+    }
+
+    pub fn on_hedge(&self, callback: impl Fn(&HedgeReport) + Send + Sync + 'static) {
+        self.shared
+            .callbacks
+            .write()
+            .unwrap()
+            .push(Box::new(callback));
+    }
+
+    pub fn update_ticker(&self, instrument_name: impl Into<String>, ticker: Ticker) {
+        self.shared.tickers.insert(instrument_name.into(), ticker);
+    }
+
+    pub fn set_tick_spec(&self, instrument_name: impl Into<String>, spec: TickSpec) {
+        self.shared.specs.insert(instrument_name.into(), spec);
+    }
+
+    /// Cost of crossing into `instrument_name` in `direction`, in basis points of mark.
+    pub fn cost_bps(&self, instrument_name: &str, direction: DirectionEnum) -> Option<Decimal> {
+        self.shared.cost_bps(instrument_name, direction)
+    }
+
+    /// The hedge for a net `delta`, or `None` if it is inside the band, too small or no
+    /// instrument is within the slippage limit.
+    pub fn plan(&self, delta: Decimal) -> Option<PlannedHedge> {
+        self.shared.plan(delta)
+    }
+
+    /// Plans and, unless in dry-run mode, sends the hedge for `delta`. Once started, hedges
+    /// still in flight are netted out of `delta` first.
+    pub async fn hedge(&self, client: &WsClient, delta: Decimal) -> Option<HedgeReport> {
+        self.shared.hedge(client, delta).await
+    }
+}
+
+impl Drop for DeltaHedger {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+        if let Some(client) = &self.client {
+            client.release_in_background(std::mem::take(&mut self.subscriptions));
+        }
+    }
+}
+
+fn signed(direction: DirectionEnum, amount: Decimal) -> Decimal {
+    match direction {
+        DirectionEnum::Buy => amount,
+        DirectionEnum::Sell => -amount,
+    }
+}
+
+/// Net delta on `base`, or `None` while some position on it has no greeks yet.
+fn portfolio_delta(greeks: &LiveGreeks, base: &str) -> Option<Decimal> {
+    let (buckets, gaps) = greeks.by_bucket();
+    if let Some((id, reason)) = gaps.iter().find(|(id, _)| id.base() == base) {
+        debug!("Not hedging {base}: no greeks for {id} ({reason})");
+        return None;
+    }
+    let delta: f64 = buckets
+        .iter()
+        .filter(|(bucket, _)| bucket.base == base)
+        .map(|(_, greeks)| greeks.delta)
+        .sum();
+    Decimal::from_f64(delta)
+}
@@ -0,0 +1,639 @@
+//! Conditional orders on triggers the exchange doesn't offer, evaluated locally.
+//!
+//! `private/create_conditional_order` only triggers on last, mark or index price.
+//! [`ConditionalEngine`] watches tickers, index components and the clock itself and
+//! inserts a plain order through [`OrderManager::submit`] once a [`Trigger`] is met. Status
+//! changes are reported as [`ConditionalOrder`]s, like `account.conditional_orders`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use dashmap::{DashMap, DashSet};
+use log::{info, warn};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    models::{
+        ConditionalOrder, ConditionalOrderStatusEnum, Delay, IndexComponents,
+        IndexComponentsNotification, InsertParams, Ticker, TickerNotification,
+    },
+    order_manager::{OrderManager, SubmitError, SubmittedOrder},
+    types::{ClientError, Error, RequestScope},
+    ws_client::WsClient,
+};
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+/// How often time triggers, and anything else already met, are checked.
+const TIMER_INTERVAL: Duration = Duration::from_secs(1);
+/// Lookups of an insert of unknown outcome before the order is put back as pending.
+const RESOLVE_ATTEMPTS: u32 = 4;
+/// Wait before the second lookup, doubled for every further one.
+const RESOLVE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum ConditionalEngineError {
+    #[error("the engine has not been started")]
+    NotStarted,
+    #[error("no pending emulated order {0}")]
+    UnknownOrder(String),
+    #[error("could not access the trigger store: {0}")]
+    Store(#[from] std::io::Error),
+    #[error("could not read the trigger store: {0}")]
+    Format(#[from] serde_json::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    /// At or above the threshold.
+    Above,
+    /// At or below the threshold.
+    Below,
+}
+
+impl Comparison {
+    pub fn holds(self, value: Decimal, threshold: Decimal) -> bool {
+        match self {
+            Comparison::Above => value >= threshold,
+            Comparison::Below => value <= threshold,
+        }
+    }
+}
+
+/// Condition under which an emulated order is sent.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    /// Implied volatility of an option, from its ticker.
+    Iv {
+        instrument_name: String,
+        comparison: Comparison,
+        threshold: Decimal,
+    },
+    /// Mark price of `instrument_name` minus that of `other`.
+    Spread {
+        instrument_name: String,
+        other: String,
+        comparison: Comparison,
+        threshold: Decimal,
+    },
+    /// An index component, or any component if `component` is `None`, at least
+    /// `threshold_bps` away from the index price.
+    IndexDivergence {
+        underlying: String,
+        component: Option<String>,
+        threshold_bps: Decimal,
+    },
+    /// A unix timestamp in seconds.
+    Time { at: Decimal },
+}
+
+impl Trigger {
+    /// Instruments whose tickers the trigger needs.
+    fn instruments(&self) -> Vec<&str> {
+        match self {
+            Trigger::Iv {
+                instrument_name, ..
+            } => vec![instrument_name],
+            Trigger::Spread {
+                instrument_name,
+                other,
+                ..
+            } => vec![instrument_name, other],
+            Trigger::IndexDivergence { .. } | Trigger::Time { .. } => vec![],
+        }
+    }
+
+    /// The level reported as `stop_price`.
+    fn level(&self) -> Decimal {
+        match self {
+            Trigger::Iv { threshold, .. } | Trigger::Spread { threshold, .. } => *threshold,
+            Trigger::IndexDivergence { threshold_bps, .. } => *threshold_bps,
+            Trigger::Time { at } => *at,
+        }
+    }
+}
+
+/// A pending or finished emulated order, as kept in the trigger store.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EmulatedOrder {
+    pub order_id: String,
+    pub trigger: Trigger,
+    pub order: InsertParams,
+    pub status: ConditionalOrderStatusEnum,
+    pub create_time: Decimal,
+    pub update_time: Decimal,
+    pub convert_time: Option<Decimal>,
+    pub converted_order_id: Option<String>,
+    pub reject_reason: Option<String>,
+}
+
+impl EmulatedOrder {
+    /// The order in the shape of an exchange conditional order. `stop_price` carries the
+    /// trigger's threshold, or its timestamp for time triggers.
+    pub fn to_conditional_order(&self) -> ConditionalOrder {
+        ConditionalOrder {
+            order_id: self.order_id.clone(),
+            instrument_name: self.order.instrument_name.clone().unwrap_or_default(),
+            direction: self.order.direction,
+            amount: self.order.amount,
+            stop_price: self.trigger.level(),
+            limit_price: self.order.price,
+            label: self.order.label.clone(),
+            status: self.status,
+            create_time: self.create_time,
+            update_time: self.update_time,
+            convert_time: self.convert_time,
+            converted_order_id: self.converted_order_id.clone(),
+            reject_reason: self.reject_reason.clone(),
+            reduce_only: self.order.reduce_only.unwrap_or(false),
+            ..Default::default()
+        }
+    }
+
+    fn set_status(&mut self, status: ConditionalOrderStatusEnum, now: Decimal) {
+        self.status = status;
+        self.update_time = now;
+    }
+}
+
+pub type StatusCallback = Box<dyn Fn(&ConditionalOrder) + Send + Sync>;
+
+/// Local engine for [`EmulatedOrder`]s.
+///
+/// Triggers fire at most once: an order leaves the pending set, and the trigger store,
+/// before its insert is sent with a `client_order_id`. An insert whose outcome stays
+/// unknown is reported converted without an order id. With
+/// [`ConditionalEngine::with_persistence`] the pending set is rewritten on every change
+/// and reloaded by [`ConditionalEngine::start`].
+///
+/// [`ConditionalEngine::stop`] pauses the engine; `start` resumes it.
+#[derive(Clone, Default)]
+pub struct ConditionalEngine {
+    orders: Arc<DashMap<String, EmulatedOrder>>,
+    tickers: Arc<DashMap<String, Ticker>>,
+    components: Arc<DashMap<String, IndexComponents>>,
+    subscribed: Arc<DashSet<String>>,
+    subscriptions: Arc<Mutex<Vec<SubscriptionHandle>>>,
+    client: Arc<RwLock<Option<Arc<WsClient>>>>,
+    order_manager: OrderManager,
+    callbacks: Arc<RwLock<Vec<StatusCallback>>>,
+    next_id: Arc<AtomicU64>,
+    timer: Arc<Mutex<Option<JoinHandle<()>>>>,
+    store: Option<PathBuf>,
+    persisting: Arc<Mutex<()>>,
+    delay: Delay,
+}
+
+impl ConditionalEngine {
+    pub fn new() -> Self {
+        ConditionalEngine::default()
+    }
+
+    /// Keeps the pending triggers in a JSON file at `path`.
+    pub fn with_persistence(mut self, path: impl Into<PathBuf>) -> Self {
+        self.store = Some(path.into());
+        self
+    }
+
+    /// Tracks the orders sent for met triggers in `orders`.
+    pub fn with_orders(mut self, orders: OrderManager) -> Self {
+        self.order_manager = orders;
+        self
+    }
+
+    /// Ticker delay used for IV and spread triggers.
+    pub fn with_delay(mut self, delay: Delay) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Reloads persisted triggers, subscribes to their market data and starts the timer.
+    /// Does nothing while the engine is already running.
+    pub async fn start(self, client: Arc<WsClient>) -> Result<Self, Error> {
+        if self.timer.lock().unwrap().is_some() {
+            return Ok(self);
+        }
+        *self.client.write().unwrap() = Some(client.clone());
+        let restored = self.restore()?;
+        let pending: Vec<Trigger> = self.orders.iter().map(|o| o.trigger.clone()).collect();
+        for trigger in &pending {
+            self.subscribe_for(&client, trigger).await?;
+        }
+
+        let engine = self.clone();
+        let timer = tokio::spawn(async move {
+            let mut timer = tokio::time::interval(TIMER_INTERVAL);
+            loop {
+                timer.tick().await;
+                engine.dispatch(engine.take_triggered(now()));
+            }
+        });
+        if let Some(previous) = self.timer.lock().unwrap().replace(timer) {
+            previous.abort();
+        }
+        info!("Conditional engine started with {restored} pending orders");
+        Ok(self)
+    }
+
+    /// Stops the timer and unsubscribes from market data. Pending orders stay in the
+    /// store and are picked up again by [`ConditionalEngine::start`].
+    pub fn stop(&self) {
+        if let Some(timer) = self.timer.lock().unwrap().take() {
+            timer.abort();
+        }
+        let Some(client) = self.client.write().unwrap().take() else {
+            return;
+        };
+        self.subscribed.clear();
+        let handles = std::mem::take(&mut *self.subscriptions.lock().unwrap());
+        client.release_in_background(handles);
+    }
+
+    pub fn on_status(&self, callback: impl Fn(&ConditionalOrder) + Send + Sync + 'static) {
+        self.callbacks.write().unwrap().push(Box::new(callback));
+    }
+
+    /// Adds an order and subscribes to whatever its trigger watches.
+    pub async fn submit(
+        &self,
+        trigger: Trigger,
+        order: InsertParams,
+    ) -> Result<ConditionalOrder, Error> {
+        let client = self
+            .client
+            .read()
+            .unwrap()
+            .clone()
+            .ok_or(ConditionalEngineError::NotStarted)?;
+        self.subscribe_for(&client, &trigger).await?;
+        let created = self.track(trigger, order);
+        self.dispatch(self.take_triggered(now()));
+        Ok(created)
+    }
+
+    /// Adds an order without subscribing to anything, for market data fed in through
+    /// [`ConditionalEngine::apply_ticker`] and [`ConditionalEngine::apply_index_components`].
+    pub fn track(&self, trigger: Trigger, order: InsertParams) -> ConditionalOrder {
+        let now = now();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut emulated = EmulatedOrder {
+            order_id: format!("emulated-{}-{id}", now.trunc()),
+            trigger,
+            order,
+            status: ConditionalOrderStatusEnum::Created,
+            create_time: now,
+            update_time: now,
+            convert_time: None,
+            converted_order_id: None,
+            reject_reason: None,
+        };
+        self.emit(&emulated);
+        emulated.set_status(ConditionalOrderStatusEnum::Active, now);
+        self.emit(&emulated);
+        self.orders
+            .insert(emulated.order_id.clone(), emulated.clone());
+        self.persist();
+        emulated.to_conditional_order()
+    }
+
+    pub fn cancel(&self, order_id: &str) -> Result<ConditionalOrder, ConditionalEngineError> {
+        let (_, mut order) = self
+            .orders
+            .remove(order_id)
+            .ok_or_else(|| ConditionalEngineError::UnknownOrder(order_id.to_string()))?;
+        order.set_status(ConditionalOrderStatusEnum::Cancelled, now());
+        self.persist();
+        self.emit(&order);
+        Ok(order.to_conditional_order())
+    }
+
+    /// Cancels every pending order and returns how many there were.
+    pub fn cancel_all(&self) -> usize {
+        let ids: Vec<String> = self.orders.iter().map(|o| o.key().clone()).collect();
+        ids.iter().filter(|id| self.cancel(id).is_ok()).count()
+    }
+
+    pub fn get(&self, order_id: &str) -> Option<ConditionalOrder> {
+        self.orders.get(order_id).map(|o| o.to_conditional_order())
+    }
+
+    pub fn pending(&self) -> Vec<ConditionalOrder> {
+        self.orders
+            .iter()
+            .map(|o| o.to_conditional_order())
+            .collect()
+    }
+
+    /// Records a ticker and returns the orders it triggered, now removed from the
+    /// pending set.
+    pub fn apply_ticker(&self, instrument_name: &str, ticker: &Ticker) -> Vec<EmulatedOrder> {
+        self.tickers
+            .insert(instrument_name.to_string(), ticker.clone());
+        self.take_triggered(now())
+    }
+
+    pub fn apply_index_components(
+        &self,
+        underlying: &str,
+        components: &IndexComponents,
+    ) -> Vec<EmulatedOrder> {
+        self.components
+            .insert(underlying.to_string(), components.clone());
+        self.take_triggered(now())
+    }
+
+    /// Removes and returns the orders whose trigger holds at `now`.
+    pub fn take_triggered(&self, now: Decimal) -> Vec<EmulatedOrder> {
+        let met: Vec<String> = self
+            .orders
+            .iter()
+            .filter(|o| self.is_met(&o.trigger, now))
+            .map(|o| o.key().clone())
+            .collect();
+        let triggered: Vec<EmulatedOrder> = met
+            .iter()
+            .filter_map(|id| self.orders.remove(id).map(|(_, order)| order))
+            .collect();
+        if !triggered.is_empty() {
+            self.persist();
+        }
+        triggered
+    }
+
+    /// Sends a triggered order and reports it converted or rejected. An insert of unknown
+    /// outcome is looked up with [`OrderManager::resolve`]; if the exchange cannot be
+    /// asked, the order goes back to the pending set with its `client_order_id`, so firing
+    /// it again cannot place it twice.
+    pub async fn fire(&self, client: &WsClient, mut order: EmulatedOrder) -> ConditionalOrder {
+        let client_order_id = *order
+            .order
+            .client_order_id
+            .get_or_insert_with(|| client.next_client_order_id());
+        let mut result = self.order_manager.submit(client, order.order.clone()).await;
+        if let Err(SubmitError::Unresolved { source, .. }) = &result {
+            warn!(
+                "Emulated order {} sent as {client_order_id}, outcome unknown: {source}",
+                order.order_id
+            );
+            match self.resolve_unknown(client, &order.order).await {
+                Ok(Some(found)) => result = Ok(found),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(
+                        "Emulated order {} still unresolved, keeping it pending: {e}",
+                        order.order_id
+                    );
+                    self.orders.insert(order.order_id.clone(), order.clone());
+                    self.persist();
+                    return order.to_conditional_order();
+                }
+            }
+        }
+        let now = now();
+        let converted = match result {
+            Ok(SubmittedOrder::Live(inserted)) => Some(Some(inserted.order_id)),
+            Ok(SubmittedOrder::Closed(history)) => Some(Some(history.order_id)),
+            Ok(SubmittedOrder::Acknowledged(_)) => Some(None),
+            Err(e) => {
+                warn!("Emulated order {} rejected: {e}", order.order_id);
+                order.set_status(ConditionalOrderStatusEnum::Rejected, now);
+                order.reject_reason = Some(match e {
+                    SubmitError::Unresolved { source, .. } => {
+                        format!("order not found after an unanswered insert: {source}")
+                    }
+                    e => e.to_string(),
+                });
+                None
+            }
+        };
+        if let Some(order_id) = converted {
+            info!(
+                "Emulated order {} converted to {order_id:?}",
+                order.order_id
+            );
+            order.set_status(ConditionalOrderStatusEnum::Converted, now);
+            order.convert_time = Some(now);
+            order.converted_order_id = order_id;
+        }
+        self.emit(&order);
+        order.to_conditional_order()
+    }
+
+    /// Looks up an insert of unknown outcome, retrying while the exchange can't be asked.
+    async fn resolve_unknown(
+        &self,
+        client: &WsClient,
+        params: &InsertParams,
+    ) -> Result<Option<SubmittedOrder>, ClientError> {
+        let mut delay = RESOLVE_RETRY_DELAY;
+        for _ in 1..RESOLVE_ATTEMPTS {
+            match self.order_manager.resolve(client, params).await {
+                Err(e) => warn!(
+                    "Lookup of client order id {:?} failed: {e}",
+                    params.client_order_id
+                ),
+                result => return result,
+            }
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+        self.order_manager.resolve(client, params).await
+    }
+
+    /// Loads the trigger store, if there is one, into the pending set.
+    pub fn restore(&self) -> Result<usize, ConditionalEngineError> {
+        let Some(path) = &self.store else {
+            return Ok(0);
+        };
+        if !path.exists() {
+            return Ok(0);
+        }
+        let orders: Vec<EmulatedOrder> = serde_json::from_slice(&fs::read(path)?)?;
+        let count = orders.len();
+        for order in orders {
+            self.orders.insert(order.order_id.clone(), order);
+        }
+        Ok(count)
+    }
+
+    fn is_met(&self, trigger: &Trigger, now: Decimal) -> bool {
+        let mark = |name: &str| self.tickers.get(name).map(|t| t.mark_price);
+        match trigger {
+            Trigger::Iv {
+                instrument_name,
+                comparison,
+                threshold,
+            } => self
+                .tickers
+                .get(instrument_name)
+                .and_then(|t| t.iv)
+                .is_some_and(|iv| comparison.holds(iv, *threshold)),
+            Trigger::Spread {
+                instrument_name,
+                other,
+                comparison,
+                threshold,
+            } => match (mark(instrument_name), mark(other)) {
+                (Some(a), Some(b)) => comparison.holds(a - b, *threshold),
+                _ => false,
+            },
+            Trigger::IndexDivergence {
+                underlying,
+                component,
+                threshold_bps,
+            } => self
+                .components
+                .get(underlying)
+                .is_some_and(|c| diverges(&c, component.as_deref(), *threshold_bps)),
+            Trigger::Time { at } => now >= *at,
+        }
+    }
+
+    async fn subscribe_for(&self, client: &WsClient, trigger: &Trigger) -> Result<(), Error> {
+        for name in trigger.instruments() {
+            let handler = self.clone();
+            let name = name.to_string();
+            self.watch(
+                client,
+                format!("ticker.{name}.{}", self.delay),
+                move |msg: TickerNotification| {
+                    handler.dispatch(handler.apply_ticker(&name, &msg.notification));
+                },
+            )
+            .await?;
+        }
+        if let Trigger::IndexDivergence { underlying, .. } = trigger {
+            let handler = self.clone();
+            let underlying = underlying.clone();
+            self.watch(
+                client,
+                format!("index_components.{underlying}"),
+                move |msg: IndexComponentsNotification| {
+                    handler
+                        .dispatch(handler.apply_index_components(&underlying, &msg.notification));
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Subscribes `callback` to `channel` unless the engine already follows it.
+    async fn watch<P, F>(
+        &self,
+        client: &WsClient,
+        channel: String,
+        callback: F,
+    ) -> Result<(), Error>
+    where
+        P: DeserializeOwned + Send + 'static,
+        F: FnMut(P) + Send + 'static,
+    {
+        if !self.subscribed.insert(channel.clone()) {
+            return Ok(());
+        }
+        match client
+            .subscribe_shared_channel(RequestScope::Public, channel.clone(), callback)
+            .await
+        {
+            Ok(handle) => {
+                self.subscriptions.lock().unwrap().push(handle);
+                Ok(())
+            }
+            Err(e) => {
+                self.subscribed.remove(&channel);
+                Err(e.into())
+            }
+        }
+    }
+
+    fn dispatch(&self, triggered: Vec<EmulatedOrder>) {
+        if triggered.is_empty() {
+            return;
+        }
+        let Some(client) = self.client.read().unwrap().clone() else {
+            // Stopped after the orders were taken: keep them for the next start.
+            for order in triggered {
+                self.orders.insert(order.order_id.clone(), order);
+            }
+            self.persist();
+            return;
+        };
+        for order in triggered {
+            let engine = self.clone();
+            let client = client.clone();
+            tokio::spawn(async move {
+                engine.fire(&client, order).await;
+            });
+        }
+    }
+
+    fn emit(&self, order: &EmulatedOrder) {
+        let update = order.to_conditional_order();
+        for callback in self.callbacks.read().unwrap().iter() {
+            callback(&update);
+        }
+    }
+
+    /// Rewrites the trigger store. Writes are serialized and go through a temporary file
+    /// renamed over the store, so a reader never sees a partial or older file.
+    fn persist(&self) {
+        let Some(path) = &self.store else {
+            return;
+        };
+        let _writing = self.persisting.lock().unwrap();
+        let orders: Vec<EmulatedOrder> = self.orders.iter().map(|o| o.value().clone()).collect();
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        let written = serde_json::to_vec_pretty(&orders)
+            .map_err(ConditionalEngineError::from)
+            .and_then(|json| {
+                fs::write(&temporary, json)?;
+                fs::rename(&temporary, path)?;
+                Ok(())
+            });
+        if let Err(e) = written {
+            warn!(
+                "Failed to persist emulated orders to {}: {e}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Whether `component`, or any component, is `threshold_bps` or more off the index.
+fn diverges(components: &IndexComponents, component: Option<&str>, threshold_bps: Decimal) -> bool {
+    let Some(index) = components.index_price.filter(|p| !p.is_zero()) else {
+        return false;
+    };
+    let Some(prices) = components
+        .components
+        .clone()
+        .and_then(|c| serde_json::from_value::<HashMap<String, Decimal>>(c).ok())
+    else {
+        return false;
+    };
+    prices
+        .iter()
+        .filter(|(name, _)| component.is_none_or(|c| c == name.as_str()))
+        .any(|(_, price)| ((price - index) / index).abs() * BPS >= threshold_bps)
+}
+
+fn now() -> Decimal {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(Decimal::ZERO, |d| Decimal::new(d.as_millis() as i64, 3))
+}
@@ -0,0 +1,708 @@
+//! Client-side execution algorithms slicing a parent order into child orders.
+//!
+//! Children are inserted through an [`OrderManager`], whose `session.orders` feed reports
+//! their fills back to the running [`Execution`].
+
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info, warn};
+use rust_decimal::Decimal;
+use thiserror::Error;
+use tokio::{
+    sync::{Notify, watch},
+    task::JoinHandle,
+    time::timeout,
+};
+
+use crate::{
+    models::{
+        CancelParams, DirectionEnum, InsertParams, OrderTypeEnum, RecentTradesNotification,
+        TickerParams, TimeInForceEnum,
+    },
+    order_manager::{OrderManager, SubmitError},
+    ticks::{Rounding, TickSpec},
+    types::{Error, RequestScope},
+    ws_client::WsClient,
+};
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+/// How long a cancelled child may take to be reported closed.
+const CHILD_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ExecutionError {
+    #[error("amount must be positive, got {0}")]
+    NonPositiveAmount(Decimal),
+    #[error("invalid algo parameters: {0}")]
+    InvalidAlgo(&'static str),
+    #[error("iceberg orders need a limit price")]
+    MissingLimitPrice,
+}
+
+/// How a parent order is sliced.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Algo {
+    /// `slices` equal children spread evenly over `duration`.
+    Twap { slices: u32, duration: Duration },
+    /// Every `interval`, a child of `participation` times the volume printed on
+    /// `recent_trades.<instrument>.single` since the previous one, so fills follow the
+    /// market's volume profile. Whatever is left goes out in the last slice.
+    Vwap {
+        duration: Duration,
+        interval: Duration,
+        participation: Decimal,
+    },
+    /// One resting order at the limit price at a time, each showing about
+    /// `display_amount`, varied at random by up to `variance` (a fraction) either way.
+    Iceberg {
+        display_amount: Decimal,
+        variance: Decimal,
+    },
+}
+
+impl Algo {
+    fn validate(&self) -> Result<(), ExecutionError> {
+        match self {
+            Algo::Twap { slices, duration } if *slices == 0 || duration.is_zero() => Err(
+                ExecutionError::InvalidAlgo("twap needs at least one slice and a duration"),
+            ),
+            Algo::Vwap {
+                duration,
+                interval,
+                participation,
+            } if interval.is_zero() || duration < interval || *participation <= Decimal::ZERO => {
+                Err(ExecutionError::InvalidAlgo(
+                    "vwap needs an interval no longer than the duration and a positive participation",
+                ))
+            }
+            Algo::Iceberg {
+                display_amount,
+                variance,
+            } if *display_amount <= Decimal::ZERO
+                || *variance < Decimal::ZERO
+                || *variance >= Decimal::ONE =>
+            {
+                Err(ExecutionError::InvalidAlgo(
+                    "iceberg needs a positive display amount and a variance below one",
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Time between slices and the number of slices, for the scheduled algos.
+    fn schedule(&self) -> Option<(Duration, u32)> {
+        match self {
+            Algo::Twap { slices, duration } => Some((*duration / *slices, *slices)),
+            Algo::Vwap {
+                duration, interval, ..
+            } => Some((
+                *interval,
+                duration.as_millis().div_ceil(interval.as_millis()) as u32,
+            )),
+            Algo::Iceberg { .. } => None,
+        }
+    }
+}
+
+/// How TWAP and VWAP children are priced. Iceberg children always rest at the limit price.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChildStyle {
+    /// Post-only at our own side of the touch; what is left unfilled is cancelled and
+    /// rolled into the next slice.
+    #[default]
+    Passive,
+    /// Immediate-or-cancel at the far side of the touch, or a market order when neither a
+    /// touch nor a limit price is known.
+    Aggressive,
+}
+
+/// The parent order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutionRequest {
+    pub direction: DirectionEnum,
+    pub instrument_name: String,
+    pub amount: Decimal,
+    /// Worst price any child may trade at.
+    pub limit_price: Option<Decimal>,
+    pub style: ChildStyle,
+    pub label: Option<String>,
+}
+
+impl ExecutionRequest {
+    pub fn new(
+        direction: DirectionEnum,
+        instrument_name: impl Into<String>,
+        amount: Decimal,
+    ) -> Self {
+        ExecutionRequest {
+            direction,
+            instrument_name: instrument_name.into(),
+            amount,
+            limit_price: None,
+            style: ChildStyle::default(),
+            label: None,
+        }
+    }
+
+    pub fn limit_price(mut self, price: Decimal) -> Self {
+        self.limit_price = Some(price);
+        self
+    }
+
+    pub fn style(mut self, style: ChildStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ExecutionState {
+    #[default]
+    Running,
+    /// The whole amount was filled.
+    Filled,
+    /// The schedule ran out before the whole amount was filled.
+    Expired,
+    Cancelled,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutionProgress {
+    pub direction: DirectionEnum,
+    pub target: Decimal,
+    pub filled: Decimal,
+    /// Filled amount times price, summed over all fills.
+    pub filled_value: Decimal,
+    /// Mark price when the execution started.
+    pub arrival_mark: Option<Decimal>,
+    /// Child orders sent so far.
+    pub children: usize,
+    pub state: ExecutionState,
+}
+
+impl ExecutionProgress {
+    pub fn new(direction: DirectionEnum, target: Decimal, arrival_mark: Option<Decimal>) -> Self {
+        ExecutionProgress {
+            direction,
+            target,
+            filled: Decimal::ZERO,
+            filled_value: Decimal::ZERO,
+            arrival_mark,
+            children: 0,
+            state: ExecutionState::Running,
+        }
+    }
+
+    pub fn apply_fill(&mut self, price: Decimal, amount: Decimal) {
+        self.filled += amount;
+        self.filled_value += price * amount;
+    }
+
+    pub fn remaining(&self) -> Decimal {
+        (self.target - self.filled).max(Decimal::ZERO)
+    }
+
+    pub fn average_price(&self) -> Option<Decimal> {
+        (!self.filled.is_zero()).then(|| self.filled_value / self.filled)
+    }
+
+    /// Cost of the fills against the arrival mark in basis points; positive when we bought
+    /// above or sold below it.
+    pub fn slippage_bps(&self) -> Option<Decimal> {
+        let average = self.average_price()?;
+        let arrival = self.arrival_mark.filter(|m| !m.is_zero())?;
+        let difference = match self.direction {
+            DirectionEnum::Buy => average - arrival,
+            DirectionEnum::Sell => arrival - average,
+        };
+        Some(difference / arrival * BPS)
+    }
+}
+
+/// Child amount of a TWAP slice: an equal share of what is left, on the lot grid.
+pub fn twap_slice(remaining: Decimal, slices_left: u32, spec: &TickSpec) -> Decimal {
+    if slices_left <= 1 {
+        return spec.amount(remaining, Rounding::Down).into();
+    }
+    spec.amount(remaining / Decimal::from(slices_left), Rounding::Down)
+        .into()
+}
+
+/// Child amount of a VWAP slice: `participation` of the market volume, capped by what is
+/// left. The last slice takes everything that is left.
+pub fn vwap_slice(
+    remaining: Decimal,
+    volume: Decimal,
+    participation: Decimal,
+    spec: &TickSpec,
+    last: bool,
+) -> Decimal {
+    let amount = if last {
+        remaining
+    } else {
+        (volume * participation).min(remaining)
+    };
+    spec.amount(amount, Rounding::Down).into()
+}
+
+/// Random display sizes for iceberg children.
+#[derive(Clone, Debug)]
+pub struct DisplaySizer {
+    display_amount: Decimal,
+    variance: Decimal,
+    spec: TickSpec,
+    min_amount: Decimal,
+    state: u64,
+}
+
+impl DisplaySizer {
+    pub fn new(display_amount: Decimal, variance: Decimal, spec: &TickSpec) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |d| d.as_nanos() as u64);
+        DisplaySizer::with_seed(display_amount, variance, spec, seed)
+    }
+
+    pub fn with_seed(
+        display_amount: Decimal,
+        variance: Decimal,
+        spec: &TickSpec,
+        seed: u64,
+    ) -> Self {
+        DisplaySizer {
+            display_amount,
+            variance,
+            spec: *spec,
+            min_amount: spec
+                .min_order_amount()
+                .unwrap_or(spec.volume_tick_size())
+                .max(spec.volume_tick_size()),
+            state: seed.max(1),
+        }
+    }
+
+    /// Size of the next child. A remainder too small to trade on its own is folded in.
+    pub fn next(&mut self, remaining: Decimal) -> Decimal {
+        // xorshift64; statistical quality doesn't matter here.
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        let unit = Decimal::from(self.state % 2001) / Decimal::from(1000) - Decimal::ONE;
+        let size = self.display_amount * (Decimal::ONE + self.variance * unit);
+        let size = Decimal::from(self.spec.amount(size, Rounding::Down)).max(self.min_amount);
+        if remaining - size < self.min_amount {
+            remaining
+        } else {
+            size
+        }
+    }
+}
+
+struct Shared {
+    progress: watch::Sender<ExecutionProgress>,
+    children: Mutex<HashSet<Decimal>>,
+    seen_trades: Mutex<HashSet<String>>,
+    live_child: Mutex<Option<i32>>,
+    child_closed: Notify,
+    stopped: AtomicBool,
+    stop: Notify,
+}
+
+/// A running execution algo.
+///
+/// ```ignore
+/// let request = ExecutionRequest::new(DirectionEnum::Buy, "BTC-PERPETUAL", dec!(1))
+///     .limit_price(dec!(95000))
+///     .style(ChildStyle::Aggressive);
+/// let twap = Algo::Twap { slices: 10, duration: Duration::from_secs(600) };
+/// let execution = Execution::start(client, orders, request, twap).await?;
+/// let done = execution.wait().await;
+/// println!("filled {} at {:?}, {:?} bps", done.filled, done.average_price(), done.slippage_bps());
+/// ```
+pub struct Execution {
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl Execution {
+    /// Validates the request, takes the arrival mark and starts slicing. `orders` must be
+    /// started, so that fills of the children are seen.
+    pub async fn start(
+        client: Arc<WsClient>,
+        orders: OrderManager,
+        request: ExecutionRequest,
+        algo: Algo,
+    ) -> Result<Self, Error> {
+        if request.amount <= Decimal::ZERO {
+            return Err(ExecutionError::NonPositiveAmount(request.amount).into());
+        }
+        algo.validate()?;
+        if matches!(algo, Algo::Iceberg { .. }) && request.limit_price.is_none() {
+            return Err(ExecutionError::MissingLimitPrice.into());
+        }
+        let spec = client.tick_spec(&request.instrument_name)?;
+        let ticker = client
+            .rpc()
+            .market_data()
+            .ticker(TickerParams::new(request.instrument_name.clone()))
+            .await?;
+
+        let shared = Arc::new(Shared {
+            progress: watch::Sender::new(ExecutionProgress::new(
+                request.direction,
+                request.amount,
+                Some(ticker.mark_price),
+            )),
+            children: Mutex::new(HashSet::new()),
+            seen_trades: Mutex::new(HashSet::new()),
+            live_child: Mutex::new(None),
+            child_closed: Notify::new(),
+            stopped: AtomicBool::new(false),
+            stop: Notify::new(),
+        });
+        let handler = shared.clone();
+        let on_fill = orders.on_fill(move |order, fill| {
+            let Some(id) = order.client_order_id else {
+                return;
+            };
+            if !handler.children.lock().unwrap().contains(&id)
+                || !handler
+                    .seen_trades
+                    .lock()
+                    .unwrap()
+                    .insert(fill.trade_id.clone())
+            {
+                return;
+            }
+            handler
+                .progress
+                .send_modify(|p| p.apply_fill(fill.price, fill.amount));
+            if order.remaining_amount.is_zero() {
+                handler.child_closed.notify_one();
+            }
+        });
+        let handler = shared.clone();
+        let on_cancel = orders.on_cancel(move |order| {
+            if order
+                .client_order_id
+                .is_some_and(|id| handler.children.lock().unwrap().contains(&id))
+            {
+                handler.child_closed.notify_one();
+            }
+        });
+
+        let volume = Arc::new(Mutex::new(Decimal::ZERO));
+        let mut trades = None;
+        if let Algo::Vwap { .. } = algo {
+            let since = Decimal::from(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs()),
+            );
+            let volume = volume.clone();
+            let handle = client
+                .subscribe_shared_channel(
+                    RequestScope::Public,
+                    format!("recent_trades.{}.single", request.instrument_name),
+                    move |msg: RecentTradesNotification| {
+                        // Skips the snapshot of trades from before the start.
+                        let printed: Decimal = msg
+                            .notification
+                            .iter()
+                            .filter(|t| t.timestamp >= since)
+                            .map(|t| t.size)
+                            .sum();
+                        *volume.lock().unwrap() += printed;
+                    },
+                )
+                .await?;
+            trades = Some(handle);
+        }
+
+        info!(
+            "Starting {algo:?} {} {} {}",
+            request.direction, request.amount, request.instrument_name
+        );
+        let runner = Runner {
+            client,
+            orders,
+            request,
+            spec,
+            shared: shared.clone(),
+        };
+        let task = tokio::spawn(async move {
+            runner.run(algo, volume).await;
+            runner.orders.remove_callback(on_fill);
+            runner.orders.remove_callback(on_cancel);
+            if let Err(e) = runner.client.release(trades).await {
+                warn!("Failed to unsubscribe VWAP trades: {e}");
+            }
+        });
+        Ok(Execution { shared, task })
+    }
+
+    pub fn progress(&self) -> ExecutionProgress {
+        self.shared.progress.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<ExecutionProgress> {
+        self.shared.progress.subscribe()
+    }
+
+    /// Stops slicing and cancels the live child.
+    pub fn cancel(&self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        self.shared.stop.notify_one();
+    }
+
+    /// Waits for the execution to end and returns the final progress.
+    pub async fn wait(self) -> ExecutionProgress {
+        if let Err(e) = self.task.await {
+            warn!("Execution task failed: {e}");
+        }
+        self.shared.progress.borrow().clone()
+    }
+}
+
+struct Runner {
+    client: Arc<WsClient>,
+    orders: OrderManager,
+    request: ExecutionRequest,
+    spec: TickSpec,
+    shared: Arc<Shared>,
+}
+
+impl Runner {
+    async fn run(&self, algo: Algo, volume: Arc<Mutex<Decimal>>) {
+        match (&algo, algo.schedule()) {
+            (
+                Algo::Iceberg {
+                    display_amount,
+                    variance,
+                },
+                _,
+            ) => {
+                let mut sizer = DisplaySizer::new(*display_amount, *variance, &self.spec);
+                while !self.stopped() && !self.remaining().is_zero() {
+                    let amount = sizer.next(self.remaining());
+                    if !self.send_child(amount, true).await {
+                        // Rejected; don't hammer the exchange.
+                        self.pause(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                    tokio::select! {
+                        _ = self.shared.child_closed.notified() => {}
+                        _ = self.shared.stop.notified() => {}
+                    }
+                }
+            }
+            (_, Some((interval, slices))) => {
+                for slice in 0..slices {
+                    if self.stopped() || self.remaining().is_zero() {
+                        break;
+                    }
+                    self.close_live_child().await;
+                    let remaining = self.remaining();
+                    let amount = match &algo {
+                        Algo::Vwap { participation, .. } => {
+                            let printed = std::mem::take(&mut *volume.lock().unwrap());
+                            vwap_slice(
+                                remaining,
+                                printed,
+                                *participation,
+                                &self.spec,
+                                slice + 1 == slices,
+                            )
+                        }
+                        _ => twap_slice(remaining, slices - slice, &self.spec),
+                    };
+                    if self
+                        .spec
+                        .is_tradable(self.spec.amount(amount, Rounding::Down))
+                    {
+                        self.send_child(amount, false).await;
+                    } else {
+                        debug!("Skipping slice {slice} of {amount}");
+                    }
+                    self.pause(interval).await;
+                }
+            }
+            (_, None) => {}
+        }
+        self.close_live_child().await;
+
+        let stopped = self.stopped();
+        self.shared.progress.send_modify(|p| {
+            p.state = if p.remaining().is_zero() {
+                ExecutionState::Filled
+            } else if stopped {
+                ExecutionState::Cancelled
+            } else {
+                ExecutionState::Expired
+            };
+        });
+        let progress = self.shared.progress.borrow().clone();
+        info!(
+            "Execution on {} ended {:?}: filled {} of {}, average {:?}, slippage {:?} bps",
+            self.request.instrument_name,
+            progress.state,
+            progress.filled,
+            progress.target,
+            progress.average_price(),
+            progress.slippage_bps()
+        );
+    }
+
+    fn stopped(&self) -> bool {
+        self.shared.stopped.load(Ordering::SeqCst)
+    }
+
+    fn remaining(&self) -> Decimal {
+        self.shared.progress.borrow().remaining()
+    }
+
+    async fn pause(&self, duration: Duration) {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = self.shared.stop.notified() => {}
+        }
+    }
+
+    /// Price of the next TWAP or VWAP child, `None` for a market order, and whether it
+    /// rests post-only. `None` skips the child.
+    async fn child_terms(&self) -> Option<(Option<Decimal>, bool)> {
+        let request = &self.request;
+        let limit = request
+            .limit_price
+            .map(|p| self.spec.passive_price(p, request.direction).value());
+        let ticker = self
+            .client
+            .rpc()
+            .market_data()
+            .ticker(TickerParams::new(request.instrument_name.clone()))
+            .await
+            .inspect_err(|e| warn!("No ticker for {}: {e}", request.instrument_name))
+            .ok();
+        let bid = ticker.as_ref().and_then(|t| t.best_bid_price);
+        let ask = ticker.as_ref().and_then(|t| t.best_ask_price);
+        let (near, far) = match request.direction {
+            DirectionEnum::Buy => (bid, ask),
+            DirectionEnum::Sell => (ask, bid),
+        };
+        let capped = |price: Option<Decimal>| match (price, limit) {
+            (Some(price), Some(limit)) => Some(match request.direction {
+                DirectionEnum::Buy => price.min(limit),
+                DirectionEnum::Sell => price.max(limit),
+            }),
+            (price, limit) => price.or(limit),
+        };
+        match request.style {
+            ChildStyle::Passive => capped(near).map(|price| (Some(price), true)),
+            ChildStyle::Aggressive => Some((capped(far), false)),
+        }
+    }
+
+    /// Inserts one child and returns whether it was accepted.
+    async fn send_child(&self, amount: Decimal, iceberg: bool) -> bool {
+        let request = &self.request;
+        let (price, post_only) = if iceberg {
+            (request.limit_price, false)
+        } else {
+            match self.child_terms().await {
+                Some(terms) => terms,
+                None => {
+                    debug!("No price for a child on {}", request.instrument_name);
+                    return false;
+                }
+            }
+        };
+        let client_order_id = self.client.next_client_order_id();
+        let mut params = InsertParams {
+            direction: request.direction,
+            instrument_name: Some(request.instrument_name.clone()),
+            amount,
+            client_order_id: Some(client_order_id),
+            label: request.label.clone(),
+            ..Default::default()
+        };
+        match price {
+            Some(price) => {
+                params.order_type = Some(OrderTypeEnum::Limit);
+                params.price = Some(price);
+            }
+            None => params.order_type = Some(OrderTypeEnum::Market),
+        }
+        let rests = iceberg || post_only;
+        if post_only {
+            params.post_only = Some(true);
+            params.reject_post_only = Some(true);
+        } else if !iceberg {
+            params.time_in_force = Some(TimeInForceEnum::ImmediateOrCancel);
+        }
+
+        self.shared
+            .children
+            .lock()
+            .unwrap()
+            .insert(Decimal::from(client_order_id));
+        self.shared.progress.send_modify(|p| p.children += 1);
+        match self.orders.submit(&self.client, params).await {
+            Ok(_) => {
+                if rests {
+                    *self.shared.live_child.lock().unwrap() = Some(client_order_id);
+                }
+                true
+            }
+            Err(SubmitError::Rejected(e)) => {
+                warn!("Child order {client_order_id} rejected: {e:?}");
+                false
+            }
+            Err(e) => {
+                // It may still be live; make sure it is cancelled with the next one.
+                warn!("Child order {client_order_id} failed: {e}");
+                *self.shared.live_child.lock().unwrap() = Some(client_order_id);
+                false
+            }
+        }
+    }
+
+    /// Cancels the live child and waits until it is closed, so that its last fills are
+    /// counted before the next slice is sized or the execution ends.
+    async fn close_live_child(&self) {
+        let Some(client_order_id) = self.shared.live_child.lock().unwrap().take() else {
+            return;
+        };
+        let params = CancelParams {
+            client_order_id: Some(client_order_id),
+            order_id: None,
+        };
+        match self.client.rpc().trading().cancel(params).await {
+            // The final state carries every fill, including ones the feed hasn't sent yet.
+            Ok(order) => self.orders.apply(order),
+            // Fails harmlessly when the child already filled.
+            Err(e) => debug!("Cancel of child {client_order_id} failed: {e}"),
+        }
+        let id = Decimal::from(client_order_id);
+        let closed = async {
+            while self.orders.get_by_client_order_id(id).is_some() {
+                self.shared.child_closed.notified().await;
+            }
+        };
+        if timeout(CHILD_CLOSE_TIMEOUT, closed).await.is_err() {
+            warn!("Child {client_order_id} still open after cancelling, moving on");
+        }
+    }
+}
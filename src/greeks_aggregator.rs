@@ -0,0 +1,440 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::{Add, AddAssign, Mul},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use tokio::{task::JoinHandle, time::interval};
+
+use crate::{
+    channels::batch::{ChannelBatch, SubscriptionHandle},
+    manual_models::instrument_id::{Expiry, InstrumentId, InstrumentKind},
+    models::{Delay, Instrument, PortfolioMarginBreakdown, Ticker, TickerNotification},
+    portfolio_tracker::PortfolioTracker,
+    pricing,
+    types::{Error, RequestScope},
+    ws_client::WsClient,
+};
+
+const POSITION_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+/// Wait before refreshing the instruments cache again for an instrument it lacked;
+/// doubled after every further miss, up to the maximum.
+const MISSING_INSTRUMENT_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_MISSING_INSTRUMENT_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// Net sensitivities of a group of positions, in units of the underlying.
+///
+/// `delta` and `gamma` are per unit move of the forward; `vega` per 1.0 of volatility
+/// and `theta` per year, as in [`pricing::Greeks`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PositionGreeks {
+    pub delta: f64,
+    pub gamma: f64,
+    pub vega: f64,
+    pub theta: f64,
+}
+
+impl Add for PositionGreeks {
+    type Output = PositionGreeks;
+
+    fn add(self, other: PositionGreeks) -> PositionGreeks {
+        PositionGreeks {
+            delta: self.delta + other.delta,
+            gamma: self.gamma + other.gamma,
+            vega: self.vega + other.vega,
+            theta: self.theta + other.theta,
+        }
+    }
+}
+
+impl AddAssign for PositionGreeks {
+    fn add_assign(&mut self, other: PositionGreeks) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<f64> for PositionGreeks {
+    type Output = PositionGreeks;
+
+    fn mul(self, size: f64) -> PositionGreeks {
+        PositionGreeks {
+            delta: self.delta * size,
+            gamma: self.gamma * size,
+            vega: self.vega * size,
+            theta: self.theta * size,
+        }
+    }
+}
+
+/// Greeks bucket: base asset and expiry, `None` for perpetuals.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GreeksBucket {
+    pub base: String,
+    pub expiry: Option<Expiry>,
+}
+
+/// Our delta next to the one implied by the exchange's margin scenarios.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeltaCheck {
+    /// Index name, e.g. `BTCUSD`.
+    pub underlying: String,
+    pub computed: f64,
+    /// `None` if the breakdown has no symmetric zero-vol scenarios for this underlying.
+    pub exchange: Option<f64>,
+}
+
+impl DeltaCheck {
+    pub fn difference(&self) -> Option<f64> {
+        Some(self.computed - self.exchange?)
+    }
+}
+
+/// Positions whose greeks could not be computed, with the reason.
+pub type GreeksGaps = Vec<(InstrumentId, String)>;
+
+#[derive(Debug, Default)]
+struct MarketState {
+    instruments: HashMap<InstrumentId, Instrument>,
+    tickers: HashMap<InstrumentId, Ticker>,
+}
+
+/// Net greeks of our positions per underlying and per expiry bucket.
+///
+/// Perpetuals and futures count as delta one. Options take delta from the ticker, as
+/// marked by the exchange, and gamma, vega and theta from Black-76 at the mark IV.
+/// Futures rolls and other combinations are not included.
+#[derive(Clone, Default)]
+pub struct GreeksAggregator {
+    positions: HashMap<InstrumentId, Decimal>,
+    market: Arc<RwLock<MarketState>>,
+}
+
+impl GreeksAggregator {
+    pub fn new() -> Self {
+        GreeksAggregator::default()
+    }
+
+    pub fn set_positions(&mut self, positions: HashMap<InstrumentId, Decimal>) {
+        self.positions = positions;
+    }
+
+    pub fn set_instrument(&self, instrument: Instrument) {
+        let Some(Ok(id)) = instrument.instrument_name.as_deref().map(str::parse) else {
+            return;
+        };
+        self.market
+            .write()
+            .unwrap()
+            .instruments
+            .insert(id, instrument);
+    }
+
+    pub fn update_ticker(&self, instrument_name: &str, ticker: Ticker) {
+        if let Ok(id) = instrument_name.parse::<InstrumentId>() {
+            self.market.write().unwrap().tickers.insert(id, ticker);
+        }
+    }
+
+    /// Greeks of one contract of `id`, or why they can't be computed.
+    fn unit_greeks(market: &MarketState, id: &InstrumentId) -> Result<PositionGreeks, String> {
+        match id.kind() {
+            InstrumentKind::Perpetual { .. } | InstrumentKind::Future { .. } => {
+                Ok(PositionGreeks {
+                    delta: 1.0,
+                    ..Default::default()
+                })
+            }
+            InstrumentKind::Option { .. } => {
+                let ticker = market.tickers.get(id).ok_or("no ticker")?;
+                let instrument = market.instruments.get(id).ok_or("no instrument")?;
+                let model = pricing::greeks(instrument, ticker).map_err(|e| e.to_string())?;
+                Ok(PositionGreeks {
+                    delta: ticker.delta.to_f64().unwrap_or(model.delta),
+                    gamma: model.gamma,
+                    vega: model.vega,
+                    theta: model.theta,
+                })
+            }
+            InstrumentKind::Roll { .. } | InstrumentKind::Combination { .. } => {
+                Err("combinations are not supported".to_string())
+            }
+        }
+    }
+
+    /// Net greeks per base asset and expiry, plus the positions that were left out.
+    pub fn by_bucket(&self) -> (BTreeMap<GreeksBucket, PositionGreeks>, GreeksGaps) {
+        let market = self.market.read().unwrap();
+        let mut buckets: BTreeMap<GreeksBucket, PositionGreeks> = BTreeMap::new();
+        let mut gaps = Vec::new();
+        for (id, size) in &self.positions {
+            let Some(size) = size.to_f64().filter(|s| *s != 0.0) else {
+                continue;
+            };
+            match Self::unit_greeks(&market, id) {
+                Ok(unit) => {
+                    let bucket = GreeksBucket {
+                        base: id.base().to_string(),
+                        expiry: id.expiry(),
+                    };
+                    *buckets.entry(bucket).or_default() += unit * size;
+                }
+                Err(reason) => gaps.push((id.clone(), reason)),
+            }
+        }
+        (buckets, gaps)
+    }
+
+    /// Net greeks per base asset, e.g. `BTC`.
+    pub fn by_underlying(&self) -> BTreeMap<String, PositionGreeks> {
+        let mut totals: BTreeMap<String, PositionGreeks> = BTreeMap::new();
+        for (bucket, greeks) in self.by_bucket().0 {
+            *totals.entry(bucket.base).or_default() += greeks;
+        }
+        totals
+    }
+
+    /// Compares our delta with the one implied by the margin scenarios of
+    /// `private/required_margin_breakdown`.
+    ///
+    /// The exchange figure is the central difference of the P&L of the smallest symmetric
+    /// pair of underlying moves without a vol change, divided by the index price.
+    pub fn compare_delta(&self, breakdown: &PortfolioMarginBreakdown) -> Vec<DeltaCheck> {
+        let computed = self.by_underlying();
+        let market = self.market.read().unwrap();
+        let underlyings = breakdown
+            .portfolio
+            .as_ref()
+            .and_then(|p| p.underlyings.as_ref())
+            .into_iter()
+            .flatten();
+        let mut checks = Vec::new();
+        for entry in underlyings {
+            let Some(underlying) = entry.underlying.clone() else {
+                continue;
+            };
+            // Instruments carry the index name; greeks are keyed by base asset.
+            let on_underlying = |id: &&InstrumentId| {
+                market
+                    .instruments
+                    .get(*id)
+                    .and_then(|i| i.underlying.as_deref())
+                    == Some(underlying.as_str())
+            };
+            let base = market
+                .instruments
+                .keys()
+                .find(on_underlying)
+                .map(|id| id.base().to_string());
+            let index = market
+                .tickers
+                .iter()
+                .find(|(id, _)| on_underlying(id))
+                .and_then(|(_, t)| t.index.to_f64());
+
+            let mut moves: BTreeMap<Decimal, Decimal> = BTreeMap::new();
+            for scenario in entry.scenarios.iter().flatten() {
+                if scenario.vol_change_pct_point.is_some_and(|v| !v.is_zero()) {
+                    continue;
+                }
+                if let (Some(change), Some(pnl)) = (scenario.underlying_change_pct, scenario.pnl) {
+                    moves.insert(change, pnl);
+                }
+            }
+            let exchange = moves
+                .iter()
+                .filter(|(change, _)| change.is_sign_positive() && !change.is_zero())
+                .find_map(|(change, up)| Some((*change, *up, *moves.get(&-*change)?)))
+                .and_then(|(change, up, down)| {
+                    let cash_delta = (up - down) / (Decimal::TWO * change / Decimal::ONE_HUNDRED);
+                    Some(cash_delta.to_f64()? / index?)
+                });
+            checks.push(DeltaCheck {
+                computed: base.and_then(|b| computed.get(&b)).map_or(0.0, |g| g.delta),
+                underlying,
+                exchange,
+            });
+        }
+        checks
+    }
+}
+
+/// A [`GreeksAggregator`] following a [`PortfolioTracker`], subscribed to the ticker of
+/// every instrument we hold a position in.
+///
+/// Tickers that fail to subscribe are retried on the next position scan. An instrument
+/// missing from the client's cache triggers a cache refresh, retried with backoff;
+/// combinations and expired instruments, which the cache never holds, are not looked up.
+pub struct LiveGreeks {
+    client: Arc<WsClient>,
+    portfolio: PortfolioTracker,
+    aggregator: GreeksAggregator,
+    subscriptions: Arc<Mutex<Vec<SubscriptionHandle>>>,
+    task: JoinHandle<()>,
+}
+
+impl LiveGreeks {
+    pub fn start(client: Arc<WsClient>, portfolio: PortfolioTracker, delay: Delay) -> Self {
+        let aggregator = GreeksAggregator::new();
+        let subscriptions = Arc::new(Mutex::new(Vec::new()));
+        let worker = aggregator.clone();
+        let tracker = portfolio.clone();
+        let handles = subscriptions.clone();
+        let scanner = client.clone();
+        let task = tokio::spawn(async move {
+            let mut subscribed: HashSet<InstrumentId> = HashSet::new();
+            let mut described: HashSet<InstrumentId> = HashSet::new();
+            let mut missing: HashMap<InstrumentId, (Instant, Duration)> = HashMap::new();
+            let mut ticker = interval(POSITION_SCAN_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let positions: Vec<InstrumentId> = tracker.positions().into_keys().collect();
+                missing.retain(|id, _| positions.contains(id));
+                let today = Expiry::from_unix(unix_now());
+                let now = Instant::now();
+                let due: Vec<InstrumentId> =
+                    describe(&scanner, &worker, &positions, &mut described)
+                        .into_iter()
+                        .filter(|id| is_cached(id, today))
+                        .filter(|id| missing.get(id).is_none_or(|(retry_at, _)| *retry_at <= now))
+                        .collect();
+                if !due.is_empty() {
+                    if let Err(e) = scanner.cache_instruments().await {
+                        warn!("Failed to refresh instruments for greeks: {e}");
+                    }
+                    let still_missing = describe(&scanner, &worker, &due, &mut described);
+                    for id in due {
+                        if !still_missing.contains(&id) {
+                            missing.remove(&id);
+                            continue;
+                        }
+                        let backoff = missing
+                            .get(&id)
+                            .map_or(MISSING_INSTRUMENT_BACKOFF, |(_, b)| {
+                                (*b * 2).min(MAX_MISSING_INSTRUMENT_BACKOFF)
+                            });
+                        warn!("Greeks instrument {id} not found, retrying in {backoff:?}");
+                        missing.insert(id, (now + backoff, backoff));
+                    }
+                }
+
+                let mut batch = ChannelBatch::new();
+                let mut added: HashMap<String, InstrumentId> = HashMap::new();
+                for id in positions {
+                    if subscribed.contains(&id) {
+                        continue;
+                    }
+                    let target = worker.clone();
+                    let name = id.to_string();
+                    let channel = format!("ticker.{id}.{delay}");
+                    batch.add(
+                        RequestScope::Public,
+                        channel.clone(),
+                        move |msg: TickerNotification| {
+                            target.update_ticker(&name, msg.notification)
+                        },
+                    );
+                    added.insert(channel, id);
+                }
+                if batch.is_empty() {
+                    continue;
+                }
+                match scanner.subscribe_shared(batch).await {
+                    Ok(report) => {
+                        for (channel, error) in &report.failed {
+                            warn!("Greeks ticker {channel} not subscribed, retrying: {error:?}");
+                        }
+                        for handle in report.subscribed {
+                            if let Some(id) = added.remove(handle.channel()) {
+                                subscribed.insert(id);
+                            }
+                            handles.lock().unwrap().push(handle);
+                        }
+                    }
+                    Err(e) => warn!("Failed to subscribe greeks tickers: {e}"),
+                }
+            }
+        });
+        LiveGreeks {
+            client,
+            portfolio,
+            aggregator,
+            subscriptions,
+            task,
+        }
+    }
+
+    /// Aggregator over the current positions, including fills not yet in the portfolio feed.
+    pub fn aggregator(&self) -> GreeksAggregator {
+        let mut aggregator = self.aggregator.clone();
+        aggregator.set_positions(self.portfolio.positions());
+        aggregator
+    }
+
+    pub fn by_bucket(&self) -> (BTreeMap<GreeksBucket, PositionGreeks>, GreeksGaps) {
+        self.aggregator().by_bucket()
+    }
+
+    pub fn by_underlying(&self) -> BTreeMap<String, PositionGreeks> {
+        self.aggregator().by_underlying()
+    }
+
+    /// Fetches `private/required_margin_breakdown` and compares its delta with ours.
+    pub async fn compare_delta(&self, client: &WsClient) -> Result<Vec<DeltaCheck>, Error> {
+        let breakdown = client
+            .rpc()
+            .accounting()
+            .required_margin_breakdown()
+            .await?;
+        Ok(self.aggregator().compare_delta(&breakdown))
+    }
+}
+
+/// Hands the cached instrument of each of `ids` not yet `described` to `aggregator`.
+/// Returns the ids missing from the cache.
+fn describe(
+    client: &WsClient,
+    aggregator: &GreeksAggregator,
+    ids: &[InstrumentId],
+    described: &mut HashSet<InstrumentId>,
+) -> Vec<InstrumentId> {
+    let mut missing = Vec::new();
+    for id in ids {
+        if described.contains(id) {
+            continue;
+        }
+        match client.instruments_cache.get(id.as_str()) {
+            Some(instrument) => {
+                aggregator.set_instrument(instrument.clone());
+                described.insert(id.clone());
+            }
+            None => missing.push(id.clone()),
+        }
+    }
+    missing
+}
+
+/// Whether the instruments cache can hold `id`: combinations and expired instruments are
+/// not listed by `public/instruments`.
+fn is_cached(id: &InstrumentId, today: Expiry) -> bool {
+    match id.kind() {
+        InstrumentKind::Roll { .. } | InstrumentKind::Combination { .. } => false,
+        _ => id.expiry().is_none_or(|expiry| expiry >= today),
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+impl Drop for LiveGreeks {
+    fn drop(&mut self) {
+        self.task.abort();
+        let handles = std::mem::take(&mut *self.subscriptions.lock().unwrap());
+        self.client.release_in_background(handles);
+    }
+}
@@ -0,0 +1,343 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use dashmap::DashMap;
+use log::{debug, info, warn};
+use rust_decimal::Decimal;
+use tokio::{sync::broadcast, task::JoinHandle, time::interval};
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    models::{
+        Instrument, InstrumentDelta, InstrumentsNotification, InstrumentsParams, OptionTypeEnum,
+        TypeEnum,
+    },
+    types::{Error, ExternalEvent, RequestScope},
+    ws_client::WsClient,
+};
+
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+pub(crate) const INSTRUMENT_EVENT_BUFFER: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InstrumentEvent {
+    /// Newly listed instrument, or one seen for the first time after a refresh.
+    Added(Instrument),
+    /// Delisted by the exchange.
+    Removed(Instrument),
+    /// `expiration_timestamp` has passed; the instrument is dropped from the registry.
+    Expired(Instrument),
+}
+
+/// Filter for [`InstrumentRegistry::query`]. Unset fields match everything.
+#[derive(Clone, Debug, Default)]
+pub struct InstrumentQuery {
+    pub underlying: Option<String>,
+    pub instrument_type: Option<TypeEnum>,
+    pub option_type: Option<OptionTypeEnum>,
+    /// Expiry date in ISO format (YYYY-mm-dd).
+    pub expiry_date: Option<String>,
+    pub min_strike: Option<Decimal>,
+    pub max_strike: Option<Decimal>,
+}
+
+impl InstrumentQuery {
+    pub fn matches(&self, instrument: &Instrument) -> bool {
+        if self.underlying.is_some() && instrument.underlying != self.underlying {
+            return false;
+        }
+        if self.instrument_type.is_some() && instrument.r#type != self.instrument_type {
+            return false;
+        }
+        if self.option_type.is_some() && instrument.option_type != self.option_type {
+            return false;
+        }
+        if self.expiry_date.is_some() && instrument.expiry_date != self.expiry_date {
+            return false;
+        }
+        if self.min_strike.is_some() || self.max_strike.is_some() {
+            let Some(strike) = instrument.strike_price else {
+                return false;
+            };
+            if self.min_strike.is_some_and(|min| strike < min)
+                || self.max_strike.is_some_and(|max| strike > max)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Instrument cache kept current by the `instruments` channel.
+///
+/// Shares its map and its events with [`WsClient::instruments_cache`], so tick rounding
+/// on the client sees intraday listings and instruments the client adds to the cache are
+/// reported as added. Expired instruments are dropped by a periodic sweep, and the full
+/// list is refetched after every reconnect.
+pub struct InstrumentRegistry {
+    instruments: Arc<DashMap<String, Instrument>>,
+    events_tx: broadcast::Sender<InstrumentEvent>,
+    subscription: Option<SubscriptionHandle>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl InstrumentRegistry {
+    /// Creates a registry over `instruments` without subscribing to anything.
+    pub fn new(instruments: Arc<DashMap<String, Instrument>>) -> Self {
+        let (events_tx, _) = broadcast::channel(INSTRUMENT_EVENT_BUFFER);
+        InstrumentRegistry {
+            instruments,
+            events_tx,
+            subscription: None,
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Subscribes to the `instruments` channel and starts the expiry sweep and reconnect refresh.
+    pub async fn start(client: Arc<WsClient>) -> Result<Self, Error> {
+        let mut registry = InstrumentRegistry {
+            instruments: client.instruments_cache.clone(),
+            events_tx: client.instrument_events.clone(),
+            subscription: None,
+            tasks: Vec::new(),
+        };
+        if registry.instruments.is_empty() {
+            registry.refresh(&client).await?;
+        }
+
+        let handler = registry.handle();
+        let subscription = client
+            .subscribe_shared_channel(
+                RequestScope::Public,
+                "instruments".to_string(),
+                move |msg: InstrumentsNotification| {
+                    for delta in msg.notification {
+                        handler.apply(delta);
+                    }
+                },
+            )
+            .await?;
+        registry.subscription = Some(subscription);
+
+        let sweeper = registry.handle();
+        registry.tasks.push(tokio::spawn(async move {
+            let mut ticker = interval(EXPIRY_SWEEP_INTERVAL);
+            loop {
+                ticker.tick().await;
+                sweeper.sweep_expired(unix_now());
+            }
+        }));
+
+        let refresher = registry.handle();
+        let mut state_rx = client.connection_state();
+        state_rx.mark_unchanged();
+        registry.tasks.push(tokio::spawn(async move {
+            while state_rx.changed().await.is_ok() {
+                let state = *state_rx.borrow_and_update();
+                match state {
+                    ExternalEvent::Connected => {
+                        if let Err(e) = refresher.refresh(&client).await {
+                            warn!("Failed to refresh instruments after reconnect: {e}");
+                        }
+                    }
+                    ExternalEvent::Disconnected => continue,
+                    ExternalEvent::Exited => break,
+                }
+            }
+        }));
+        info!(
+            "Instrument registry started with {} instruments",
+            registry.len()
+        );
+        Ok(registry)
+    }
+
+    /// Stops the expiry sweep and reconnect refresh and unsubscribes from `instruments`.
+    /// The cache itself is left as it is.
+    pub async fn stop(&mut self, client: &WsClient) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+        if let Err(e) = client.release(self.subscription.take()).await {
+            warn!("Failed to unsubscribe instruments: {e}");
+        }
+    }
+
+    fn handle(&self) -> InstrumentRegistry {
+        InstrumentRegistry {
+            instruments: self.instruments.clone(),
+            events_tx: self.events_tx.clone(),
+            subscription: None,
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Receiver for add, remove and expiry events.
+    pub fn events(&self) -> broadcast::Receiver<InstrumentEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Applies one delta from the `instruments` channel.
+    pub fn apply(&self, delta: InstrumentDelta) {
+        match delta {
+            InstrumentDelta::Added(added) => {
+                let instrument = added.added;
+                let Some(name) = instrument.instrument_name.clone() else {
+                    return;
+                };
+                if self.instruments.insert(name, instrument.clone()).is_none() {
+                    debug!("Instrument added: {:?}", instrument.instrument_name);
+                    let _ = self.events_tx.send(InstrumentEvent::Added(instrument));
+                }
+            }
+            InstrumentDelta::Removed(removed) => {
+                let Some(name) = removed.removed.instrument_name.as_deref() else {
+                    return;
+                };
+                if let Some((_, instrument)) = self.instruments.remove(name) {
+                    debug!("Instrument removed: {name}");
+                    let _ = self.events_tx.send(InstrumentEvent::Removed(instrument));
+                }
+            }
+        }
+    }
+
+    /// Drops instruments whose `expiration_timestamp` is at or before `now` (Unix seconds).
+    pub fn sweep_expired(&self, now: i64) {
+        let expired: Vec<String> = self
+            .instruments
+            .iter()
+            .filter(|e| {
+                e.value()
+                    .expiration_timestamp
+                    .is_some_and(|ts| i64::from(ts) <= now)
+            })
+            .map(|e| e.key().clone())
+            .collect();
+        for name in expired {
+            if let Some((_, instrument)) = self.instruments.remove(&name) {
+                debug!("Instrument expired: {name}");
+                let _ = self.events_tx.send(InstrumentEvent::Expired(instrument));
+            }
+        }
+    }
+
+    /// Refetches all active instruments and emits events for the differences.
+    pub async fn refresh(&self, client: &WsClient) -> Result<(), Error> {
+        let instruments = client
+            .rpc()
+            .market_data()
+            .instruments(InstrumentsParams::default())
+            .await?;
+        self.replace_all(instruments);
+        Ok(())
+    }
+
+    /// Replaces the registry contents with `instruments`, emitting events for the differences.
+    pub fn replace_all(&self, instruments: Vec<Instrument>) {
+        let names: std::collections::HashSet<&str> = instruments
+            .iter()
+            .filter_map(|i| i.instrument_name.as_deref())
+            .collect();
+        let stale: Vec<String> = self
+            .instruments
+            .iter()
+            .filter(|e| !names.contains(e.key().as_str()))
+            .map(|e| e.key().clone())
+            .collect();
+        for name in stale {
+            if let Some((_, instrument)) = self.instruments.remove(&name) {
+                let _ = self.events_tx.send(InstrumentEvent::Removed(instrument));
+            }
+        }
+        for instrument in instruments {
+            self.apply(InstrumentDelta::Added(crate::models::Added::new(
+                instrument,
+            )));
+        }
+    }
+
+    pub fn get(&self, instrument_name: &str) -> Option<Instrument> {
+        self.instruments.get(instrument_name).map(|e| e.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.instruments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instruments.is_empty()
+    }
+
+    pub fn query(&self, query: &InstrumentQuery) -> Vec<Instrument> {
+        let mut found: Vec<Instrument> = self
+            .instruments
+            .iter()
+            .filter(|e| query.matches(e.value()))
+            .map(|e| e.value().clone())
+            .collect();
+        found.sort_by(|a, b| a.instrument_name.cmp(&b.instrument_name));
+        found
+    }
+
+    pub fn by_underlying(&self, underlying: &str) -> Vec<Instrument> {
+        self.query(&InstrumentQuery {
+            underlying: Some(underlying.to_string()),
+            ..Default::default()
+        })
+    }
+
+    pub fn by_type(&self, instrument_type: TypeEnum) -> Vec<Instrument> {
+        self.query(&InstrumentQuery {
+            instrument_type: Some(instrument_type),
+            ..Default::default()
+        })
+    }
+
+    /// Distinct expiry dates (ISO) listed for `underlying`, earliest first.
+    pub fn expiries(&self, underlying: &str) -> Vec<String> {
+        let mut expiries: Vec<String> = self
+            .by_underlying(underlying)
+            .into_iter()
+            .filter_map(|i| i.expiry_date)
+            .collect();
+        expiries.sort();
+        expiries.dedup();
+        expiries
+    }
+
+    /// Options of `underlying` expiring on `expiry_date` with strikes in `[min_strike, max_strike]`.
+    pub fn options_in_strike_range(
+        &self,
+        underlying: &str,
+        expiry_date: &str,
+        min_strike: Decimal,
+        max_strike: Decimal,
+    ) -> Vec<Instrument> {
+        self.query(&InstrumentQuery {
+            underlying: Some(underlying.to_string()),
+            instrument_type: Some(TypeEnum::Option),
+            expiry_date: Some(expiry_date.to_string()),
+            min_strike: Some(min_strike),
+            max_strike: Some(max_strike),
+            ..Default::default()
+        })
+    }
+}
+
+impl Drop for InstrumentRegistry {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
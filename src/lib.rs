@@ -1,9 +1,28 @@
 mod auth_utils;
+pub mod bots;
 pub mod channels;
+pub mod combo;
+pub mod delta_hedger;
+pub mod emulated_orders;
+pub mod execution;
+pub mod greeks_aggregator;
+pub mod instrument_registry;
 pub mod manual_models;
+pub mod mm_protection;
 pub mod models;
+pub mod order_book;
+pub mod order_builder;
+pub mod order_manager;
+pub mod pegged_order;
+pub mod portfolio_tracker;
+pub mod pricing;
+pub mod quote_engine;
+pub mod risk_guard;
 mod routing;
 pub mod rpc;
+pub mod strategy;
+pub mod ticks;
 pub mod types;
 pub mod utils;
+pub mod vol_surface;
 pub mod ws_client;
@@ -0,0 +1,146 @@
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeTuple,
+};
+
+use crate::models::DirectionEnum;
+
+/// Order book price level, sent on the wire as `[price, amount, outright_amount]`.
+///
+/// An `amount` of zero in a book update means the level is now empty.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BookLevel {
+    pub price: Decimal,
+    pub amount: Decimal,
+    /// Part of `amount` that is not the result of implied matching. Absent in some feeds.
+    pub outright_amount: Option<Decimal>,
+}
+
+impl BookLevel {
+    pub fn new(price: Decimal, amount: Decimal) -> BookLevel {
+        BookLevel {
+            price,
+            amount,
+            outright_amount: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.amount.is_zero()
+    }
+}
+
+impl Serialize for BookLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = if self.outright_amount.is_some() { 3 } else { 2 };
+        let mut tup = serializer.serialize_tuple(len)?;
+        tup.serialize_element(&self.price)?;
+        tup.serialize_element(&self.amount)?;
+        if let Some(outright) = &self.outright_amount {
+            tup.serialize_element(outright)?;
+        }
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for BookLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BookLevelVisitor;
+
+        impl<'de> Visitor<'de> for BookLevelVisitor {
+            type Value = BookLevel;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an array of [price, amount, outright_amount]")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BookLevel, A::Error> {
+                let price = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let amount = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let outright_amount = seq.next_element()?;
+                // Tolerate trailing fields added to the feed later on.
+                while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+                Ok(BookLevel {
+                    price,
+                    amount,
+                    outright_amount,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(BookLevelVisitor)
+    }
+}
+
+/// Trade from the `book` channel, sent on the wire as
+/// `[price, amount, direction, timestamp, implied_taker]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BookTrade {
+    pub price: Decimal,
+    pub amount: Decimal,
+    pub direction: DirectionEnum,
+    /// Unix timestamp of the trade.
+    pub time: Decimal,
+    /// `true` when the taker trade happened on another book and this one was filled by implied matching.
+    pub implied_taker: bool,
+}
+
+impl Serialize for BookTrade {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tup = serializer.serialize_tuple(5)?;
+        tup.serialize_element(&self.price)?;
+        tup.serialize_element(&self.amount)?;
+        tup.serialize_element(&self.direction)?;
+        tup.serialize_element(&self.time)?;
+        tup.serialize_element(&self.implied_taker)?;
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for BookTrade {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BookTradeVisitor;
+
+        impl<'de> Visitor<'de> for BookTradeVisitor {
+            type Value = BookTrade;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an array of [price, amount, direction, timestamp, implied_taker]")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BookTrade, A::Error> {
+                let price = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let amount = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let direction = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                let time = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+                let implied_taker = seq.next_element()?.unwrap_or(false);
+                while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+                Ok(BookTrade {
+                    price,
+                    amount,
+                    direction,
+                    time,
+                    implied_taker,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(BookTradeVisitor)
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    str::FromStr,
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::models::{Instrument, OptionTypeEnum, TypeEnum};
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum InstrumentIdError {
+    #[error("invalid instrument name: {0}")]
+    InvalidName(String),
+    #[error("invalid expiry date: {0}")]
+    InvalidExpiry(String),
+    #[error("invalid strike: {0}")]
+    InvalidStrike(String),
+    #[error("instrument has no name")]
+    MissingName,
+    #[error("instrument {name} disagrees with its name on {field}")]
+    Mismatch { name: String, field: &'static str },
+}
+
+/// Expiry date as it appears in instrument names, e.g. `27DEC24`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Expiry {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Expiry {
+    pub fn new(year: u16, month: u8, day: u8) -> Result<Self, InstrumentIdError> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err(InstrumentIdError::InvalidExpiry(format!(
+                "{year:04}-{month:02}-{day:02}"
+            )));
+        }
+        Ok(Expiry { year, month, day })
+    }
+
+    /// Parses the ISO format used by `Instrument::expiry_date` (`YYYY-mm-dd`).
+    pub fn from_iso(date: &str) -> Result<Self, InstrumentIdError> {
+        let err = || InstrumentIdError::InvalidExpiry(date.to_string());
+        let mut parts = date.splitn(3, '-');
+        let year = parts.next().and_then(|p| p.parse().ok()).ok_or_else(err)?;
+        let month = parts.next().and_then(|p| p.parse().ok()).ok_or_else(err)?;
+        let day = parts.next().and_then(|p| p.parse().ok()).ok_or_else(err)?;
+        Expiry::new(year, month, day)
+    }
+
+    pub fn to_iso(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// The UTC date of a Unix timestamp.
+    pub(crate) fn from_unix(secs: i64) -> Expiry {
+        // Civil date from days since 1970-01-01, in 400-year eras starting in March.
+        let days = secs.div_euclid(86_400) + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u8;
+        let year = (year_of_era + era * 400 + i64::from(month <= 2)) as u16;
+        Expiry { year, month, day }
+    }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl fmt::Display for Expiry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}{}{:02}",
+            self.day,
+            MONTHS[self.month as usize - 1],
+            self.year % 100
+        )
+    }
+}
+
+impl FromStr for Expiry {
+    type Err = InstrumentIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || InstrumentIdError::InvalidExpiry(s.to_string());
+        let digits = s.chars().take_while(char::is_ascii_digit).count();
+        if !s.is_ascii() || !(1..=2).contains(&digits) || s.len() != digits + 5 {
+            return Err(err());
+        }
+        let day: u8 = s[..digits].parse().map_err(|_| err())?;
+        let month = MONTHS
+            .iter()
+            .position(|m| *m == &s[digits..digits + 3])
+            .ok_or_else(err)? as u8
+            + 1;
+        let year: u16 = s[digits + 3..].parse().map_err(|_| err())?;
+        Expiry::new(2000 + year, month, day)
+    }
+}
+
+/// Structured form of an instrument name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InstrumentKind {
+    /// `BTC-PERPETUAL`
+    Perpetual { base: String },
+    /// `BTC-27DEC24`
+    Future { base: String, expiry: Expiry },
+    /// `BTC-27DEC24-100000-C`
+    Option {
+        base: String,
+        expiry: Expiry,
+        strike: Decimal,
+        option_type: OptionTypeEnum,
+    },
+    /// Futures roll, e.g. `BTC-25DEC26-25SEP26`. Expiries are in the order of the name.
+    Roll {
+        base: String,
+        first: Expiry,
+        second: Expiry,
+    },
+    /// Any other combination; its legs are only known from the `Instrument`.
+    Combination { base: String },
+}
+
+/// A parsed instrument name such as `BTC-PERPETUAL`, `BTC-27DEC24` or `BTC-27DEC24-100000-C`.
+///
+/// Keeps the exchange name alongside its parsed form and derefs to `str`, so it can be
+/// passed wherever the subscription and RPC APIs take an instrument name. Equality,
+/// ordering and hashing go by name, so a `HashMap<InstrumentId, _>` can be queried with `&str`.
+#[derive(Clone, Debug)]
+pub struct InstrumentId {
+    name: String,
+    kind: InstrumentKind,
+}
+
+impl InstrumentId {
+    pub fn perpetual(base: &str) -> Self {
+        Self::from_kind(InstrumentKind::Perpetual {
+            base: base.to_string(),
+        })
+    }
+
+    pub fn future(base: &str, expiry: Expiry) -> Self {
+        Self::from_kind(InstrumentKind::Future {
+            base: base.to_string(),
+            expiry,
+        })
+    }
+
+    pub fn roll(base: &str, first: Expiry, second: Expiry) -> Self {
+        Self::from_kind(InstrumentKind::Roll {
+            base: base.to_string(),
+            first,
+            second,
+        })
+    }
+
+    pub fn option(
+        base: &str,
+        expiry: Expiry,
+        strike: Decimal,
+        option_type: OptionTypeEnum,
+    ) -> Self {
+        Self::from_kind(InstrumentKind::Option {
+            base: base.to_string(),
+            expiry,
+            strike,
+            option_type,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> &InstrumentKind {
+        &self.kind
+    }
+
+    /// Base asset from the name, e.g. `BTC`. The index (`BTCUSD`) is `Instrument::underlying`.
+    pub fn base(&self) -> &str {
+        match &self.kind {
+            InstrumentKind::Perpetual { base }
+            | InstrumentKind::Future { base, .. }
+            | InstrumentKind::Option { base, .. }
+            | InstrumentKind::Roll { base, .. }
+            | InstrumentKind::Combination { base } => base,
+        }
+    }
+
+    /// Expiry of futures and options, and the first expiry of rolls.
+    pub fn expiry(&self) -> Option<Expiry> {
+        match &self.kind {
+            InstrumentKind::Future { expiry, .. } | InstrumentKind::Option { expiry, .. } => {
+                Some(*expiry)
+            }
+            InstrumentKind::Roll { first, .. } => Some(*first),
+            _ => None,
+        }
+    }
+
+    pub fn strike(&self) -> Option<Decimal> {
+        match &self.kind {
+            InstrumentKind::Option { strike, .. } => Some(*strike),
+            _ => None,
+        }
+    }
+
+    pub fn option_type(&self) -> Option<OptionTypeEnum> {
+        match &self.kind {
+            InstrumentKind::Option { option_type, .. } => Some(*option_type),
+            _ => None,
+        }
+    }
+
+    pub fn instrument_type(&self) -> TypeEnum {
+        match &self.kind {
+            InstrumentKind::Perpetual { .. } => TypeEnum::Perpetual,
+            InstrumentKind::Future { .. } => TypeEnum::Future,
+            InstrumentKind::Option { .. } => TypeEnum::Option,
+            InstrumentKind::Roll { .. } | InstrumentKind::Combination { .. } => {
+                TypeEnum::Combination
+            }
+        }
+    }
+
+    /// Builds the exchange name for a structured kind. Not used for combinations,
+    /// whose names can't be derived from the base alone.
+    fn from_kind(kind: InstrumentKind) -> Self {
+        let name = match &kind {
+            InstrumentKind::Perpetual { base } => format!("{base}-PERPETUAL"),
+            InstrumentKind::Future { base, expiry } => format!("{base}-{expiry}"),
+            InstrumentKind::Option {
+                base,
+                expiry,
+                strike,
+                option_type,
+            } => {
+                let suffix = match option_type {
+                    OptionTypeEnum::Call => "C",
+                    OptionTypeEnum::Put => "P",
+                };
+                format!("{base}-{expiry}-{}-{suffix}", strike.normalize())
+            }
+            InstrumentKind::Roll {
+                base,
+                first,
+                second,
+            } => format!("{base}-{first}-{second}"),
+            InstrumentKind::Combination { base } => base.clone(),
+        };
+        InstrumentId { name, kind }
+    }
+}
+
+impl FromStr for InstrumentId {
+    type Err = InstrumentIdError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let invalid = || InstrumentIdError::InvalidName(name.to_string());
+        let parts: Vec<&str> = name.split('-').collect();
+        let base = parts[0];
+        if parts.len() < 2
+            || base.is_empty()
+            || !base
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let base = base.to_string();
+        let kind = match parts[1..] {
+            ["PERPETUAL"] => InstrumentKind::Perpetual { base },
+            [expiry] => InstrumentKind::Future {
+                base,
+                expiry: expiry.parse()?,
+            },
+            [first, second] => match (first.parse(), second.parse()) {
+                (Ok(first), Ok(second)) => InstrumentKind::Roll {
+                    base,
+                    first,
+                    second,
+                },
+                _ => InstrumentKind::Combination { base },
+            },
+            [expiry, strike, option_type @ ("C" | "P")] => InstrumentKind::Option {
+                base,
+                expiry: expiry.parse()?,
+                strike: Decimal::from_str(strike)
+                    .map_err(|_| InstrumentIdError::InvalidStrike(strike.to_string()))?,
+                option_type: if option_type == "C" {
+                    OptionTypeEnum::Call
+                } else {
+                    OptionTypeEnum::Put
+                },
+            },
+            _ => InstrumentKind::Combination { base },
+        };
+        Ok(InstrumentId {
+            name: name.to_string(),
+            kind,
+        })
+    }
+}
+
+impl TryFrom<&Instrument> for InstrumentId {
+    type Error = InstrumentIdError;
+
+    /// Parses `instrument_name` and checks it against the structured fields of the instrument.
+    fn try_from(instrument: &Instrument) -> Result<Self, Self::Error> {
+        let name = instrument
+            .instrument_name
+            .as_deref()
+            .ok_or(InstrumentIdError::MissingName)?;
+        let id: InstrumentId = name.parse()?;
+        let mismatch = |field| InstrumentIdError::Mismatch {
+            name: name.to_string(),
+            field,
+        };
+        if let Some(kind) = instrument.r#type
+            && kind != id.instrument_type()
+        {
+            return Err(mismatch("type"));
+        }
+        if let (Some(expiry), Some(date)) = (id.expiry(), instrument.expiry_date.as_deref())
+            && id.instrument_type() != TypeEnum::Combination
+            && expiry != Expiry::from_iso(date)?
+        {
+            return Err(mismatch("expiry_date"));
+        }
+        if let (Some(strike), Some(expected)) = (id.strike(), instrument.strike_price)
+            && strike != expected
+        {
+            return Err(mismatch("strike_price"));
+        }
+        if let (Some(option_type), Some(expected)) = (id.option_type(), instrument.option_type)
+            && option_type != expected
+        {
+            return Err(mismatch("option_type"));
+        }
+        Ok(id)
+    }
+}
+
+impl TryFrom<String> for InstrumentId {
+    type Error = InstrumentIdError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+impl From<InstrumentId> for String {
+    fn from(id: InstrumentId) -> Self {
+        id.name
+    }
+}
+
+impl fmt::Display for InstrumentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl Deref for InstrumentId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl AsRef<str> for InstrumentId {
+    fn as_ref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Borrow<str> for InstrumentId {
+    fn borrow(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PartialEq for InstrumentId {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for InstrumentId {}
+
+impl PartialEq<str> for InstrumentId {
+    fn eq(&self, other: &str) -> bool {
+        self.name == other
+    }
+}
+
+impl PartialEq<&str> for InstrumentId {
+    fn eq(&self, other: &&str) -> bool {
+        self.name == *other
+    }
+}
+
+impl Hash for InstrumentId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+impl PartialOrd for InstrumentId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InstrumentId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+impl Serialize for InstrumentId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name)
+    }
+}
+
+impl<'de> Deserialize<'de> for InstrumentId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
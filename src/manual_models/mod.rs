@@ -1,8 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+pub mod book;
 pub mod error_code;
 pub mod historic_data_index;
 pub mod historic_data_mark;
+pub mod instrument_id;
+pub mod recent_trade;
 
 #[derive(
     Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Default,
@@ -0,0 +1,84 @@
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeTuple,
+};
+
+use crate::models::DirectionEnum;
+
+/// Trade from the `recent_trades` channel, sent on the wire as
+/// `[price, size, side, timestamp, instrument_name, implied_taker]`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RecentTrade {
+    pub price: Decimal,
+    pub size: Decimal,
+    /// Side of the taker.
+    pub side: DirectionEnum,
+    /// Unix timestamp of the trade.
+    pub timestamp: Decimal,
+    pub instrument_name: String,
+    /// `true` when the taker trade happened on another book and this one was filled by implied matching.
+    pub implied_taker: bool,
+}
+
+impl Serialize for RecentTrade {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tup = serializer.serialize_tuple(6)?;
+        tup.serialize_element(&self.price)?;
+        tup.serialize_element(&self.size)?;
+        tup.serialize_element(&self.side)?;
+        tup.serialize_element(&self.timestamp)?;
+        tup.serialize_element(&self.instrument_name)?;
+        tup.serialize_element(&self.implied_taker)?;
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for RecentTrade {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RecentTradeVisitor;
+
+        impl<'de> Visitor<'de> for RecentTradeVisitor {
+            type Value = RecentTrade;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(
+                    "an array of [price, size, side, timestamp, instrument_name, implied_taker]",
+                )
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<RecentTrade, A::Error> {
+                let price = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let size = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let side = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                let timestamp = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+                let instrument_name = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(4, &self))?;
+                let implied_taker = seq.next_element()?.unwrap_or(false);
+                while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+                Ok(RecentTrade {
+                    price,
+                    size,
+                    side,
+                    timestamp,
+                    instrument_name,
+                    implied_taker,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(RecentTradeVisitor)
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use log::{info, warn};
+use rust_decimal::Decimal;
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    models::{
+        SessionMmProtectionNotification, SessionMmProtectionPayloadInner, SetMmProtectionParams,
+        session_mm_protection_payload_inner::Reason,
+    },
+    types::{Error, RequestScope},
+    ws_client::WsClient,
+};
+
+pub type ProtectionCallback = Box<dyn Fn(&ProtectionEvent) + Send + Sync>;
+
+/// Limits of one protection group, as sent with `private/set_mm_protection`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtectionConfig {
+    /// Amount that may execute before the remaining mass quotes are cancelled.
+    pub trade_amount: Decimal,
+    /// Largest single quote.
+    pub quote_amount: Decimal,
+}
+
+impl ProtectionConfig {
+    pub fn new(trade_amount: Decimal, quote_amount: Decimal) -> Self {
+        ProtectionConfig {
+            trade_amount,
+            quote_amount,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProtectionEvent {
+    /// The group's mass quotes were pulled, by executions or because the group was deleted.
+    Tripped {
+        product: String,
+        remaining_amount: Option<Decimal>,
+        reason: Option<Reason>,
+    },
+    /// The group was refilled after a trip.
+    Rearmed {
+        product: String,
+        remaining_amount: Option<Decimal>,
+    },
+}
+
+/// Last known state of a protection group.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProtectionStatus {
+    pub remaining_amount: Option<Decimal>,
+    pub last_reason: Option<Reason>,
+    pub tripped: bool,
+}
+
+/// Keeps market maker protection configured and reports when it trips.
+///
+/// Every configured product (`F` or `O` plus the underlying, e.g. `FBTCUSD`) is set up
+/// at start and again after every login and every handover to a standby connection,
+/// since protection groups end with the session.
+/// A group trips when its remaining amount runs out or it is deleted; it stays tripped,
+/// and [`MmProtectionManager::paused`] stays `true`, until a refill arrives. With a
+/// cool-down set, the manager refills a tripped group itself once the cool-down has passed.
+#[derive(Clone, Default)]
+pub struct MmProtectionManager {
+    configs: Arc<RwLock<HashMap<String, ProtectionConfig>>>,
+    status: Arc<RwLock<HashMap<String, ProtectionStatus>>>,
+    callbacks: Arc<RwLock<Vec<ProtectionCallback>>>,
+    paused: Arc<watch::Sender<bool>>,
+    cooldown: Option<Duration>,
+    subscription: Arc<Mutex<Option<SubscriptionHandle>>>,
+    /// Login watch and pending re-arms; `None` while the manager is not running.
+    tasks: Arc<Mutex<Option<Vec<JoinHandle<()>>>>>,
+}
+
+impl MmProtectionManager {
+    /// Creates a manager for `configs` without contacting the exchange.
+    pub fn new(configs: HashMap<String, ProtectionConfig>) -> Self {
+        MmProtectionManager {
+            configs: Arc::new(RwLock::new(configs)),
+            ..Default::default()
+        }
+    }
+
+    /// Refill tripped groups after `cooldown` instead of waiting for a manual
+    /// [`MmProtectionManager::configure`].
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    /// Subscribes to `session.mm_protection`, configures every product and does so again
+    /// for every new session. The client must already be logged in.
+    pub async fn start(self, client: Arc<WsClient>) -> Result<Self, Error> {
+        *self.tasks.lock().unwrap() = Some(Vec::new());
+        let handler = self.clone();
+        let rearm_client = client.clone();
+        let subscription = client
+            .subscribe_shared_channel(
+                RequestScope::Private,
+                "session.mm_protection".to_string(),
+                move |msg: SessionMmProtectionNotification| {
+                    for product in handler.apply(msg.notification) {
+                        handler.schedule_rearm(rearm_client.clone(), product);
+                    }
+                },
+            )
+            .await?;
+        *self.subscription.lock().unwrap() = Some(subscription);
+        self.configure_all(&client).await?;
+
+        let worker = self.clone();
+        let mut login_rx = client.login_status();
+        login_rx.mark_unchanged();
+        let login_task = tokio::spawn(async move {
+            // Fires on every login, and again without a logout when a standby connection
+            // takes over with its own session.
+            while login_rx.changed().await.is_ok() {
+                if !*login_rx.borrow_and_update() {
+                    continue;
+                }
+                if let Err(e) = worker.configure_all(&client).await {
+                    warn!("Failed to configure mm protection for the new session: {e}");
+                }
+            }
+        });
+        self.track(login_task);
+        info!(
+            "MM protection configured for {} products",
+            self.configs.read().unwrap().len()
+        );
+        Ok(self)
+    }
+
+    /// Stops configuring new sessions, cancels pending re-arms and unsubscribes from
+    /// `session.mm_protection`. The protection groups on the exchange are left as they are.
+    pub async fn stop(&self, client: &WsClient) {
+        for task in self.tasks.lock().unwrap().take().into_iter().flatten() {
+            task.abort();
+        }
+        let subscription = self.subscription.lock().unwrap().take();
+        if let Err(e) = client.release(subscription).await {
+            warn!("Failed to unsubscribe session.mm_protection: {e}");
+        }
+    }
+
+    /// Keeps `task` for [`MmProtectionManager::stop`], or aborts it if already stopped.
+    fn track(&self, task: JoinHandle<()>) {
+        match self.tasks.lock().unwrap().as_mut() {
+            Some(tasks) => {
+                tasks.retain(|t| !t.is_finished());
+                tasks.push(task);
+            }
+            None => task.abort(),
+        }
+    }
+
+    /// Adds or changes the limits of `product`. Takes effect with the next configure.
+    pub fn set_config(&self, product: impl Into<String>, config: ProtectionConfig) {
+        self.configs.write().unwrap().insert(product.into(), config);
+    }
+
+    pub fn config(&self, product: &str) -> Option<ProtectionConfig> {
+        self.configs.read().unwrap().get(product).copied()
+    }
+
+    /// Sends `private/set_mm_protection` for `product`, which also refills a tripped group.
+    pub async fn configure(&self, client: &WsClient, product: &str) -> Result<(), Error> {
+        let Some(config) = self.config(product) else {
+            return Err(format!("No mm protection config for {product}").into());
+        };
+        let params = SetMmProtectionParams::new(
+            product.to_string(),
+            config.trade_amount,
+            config.quote_amount,
+        );
+        client.rpc().mm().set_mm_protection(params).await?;
+        Ok(())
+    }
+
+    pub async fn configure_all(&self, client: &WsClient) -> Result<(), Error> {
+        let products: Vec<String> = self.configs.read().unwrap().keys().cloned().collect();
+        for product in products {
+            self.configure(client, &product).await?;
+        }
+        Ok(())
+    }
+
+    /// Called for every trip and re-arm.
+    pub fn on_event(&self, callback: impl Fn(&ProtectionEvent) + Send + Sync + 'static) {
+        self.callbacks.write().unwrap().push(Box::new(callback));
+    }
+
+    /// Applies a `session.mm_protection` update and returns the products that just tripped.
+    pub fn apply(&self, updates: Vec<SessionMmProtectionPayloadInner>) -> Vec<String> {
+        let mut events = Vec::new();
+        {
+            let mut status = self.status.write().unwrap();
+            for update in updates {
+                let Some(product) = update.product else {
+                    continue;
+                };
+                let entry = status.entry(product.clone()).or_default();
+                let deleted = matches!(
+                    update.reason,
+                    Some(Reason::ClientCancel | Reason::SessionEnd | Reason::Failover)
+                );
+                let exhausted = update.remaining_amount.is_some_and(|r| r <= Decimal::ZERO);
+                let tripped = deleted || exhausted;
+                entry.remaining_amount = update.remaining_amount;
+                entry.last_reason = update.reason;
+                if tripped && !entry.tripped {
+                    warn!(
+                        "MM protection on {product} tripped: {:?}, remaining {:?}",
+                        update.reason, update.remaining_amount
+                    );
+                    events.push(ProtectionEvent::Tripped {
+                        product,
+                        remaining_amount: update.remaining_amount,
+                        reason: update.reason,
+                    });
+                } else if !tripped && entry.tripped && update.reason == Some(Reason::Refill) {
+                    info!("MM protection on {product} re-armed");
+                    events.push(ProtectionEvent::Rearmed {
+                        product,
+                        remaining_amount: update.remaining_amount,
+                    });
+                } else {
+                    continue;
+                }
+                entry.tripped = tripped;
+            }
+            let any_tripped = status.values().any(|s| s.tripped);
+            self.paused
+                .send_if_modified(|p| std::mem::replace(p, any_tripped) != any_tripped);
+        }
+
+        let callbacks = self.callbacks.read().unwrap();
+        for event in &events {
+            for callback in callbacks.iter() {
+                callback(event);
+            }
+        }
+        events
+            .into_iter()
+            .filter_map(|e| match e {
+                ProtectionEvent::Tripped { product, .. } => Some(product),
+                ProtectionEvent::Rearmed { .. } => None,
+            })
+            .collect()
+    }
+
+    fn schedule_rearm(&self, client: Arc<WsClient>, product: String) {
+        let Some(cooldown) = self.cooldown else {
+            return;
+        };
+        if self.config(&product).is_none() {
+            return;
+        }
+        let manager = self.clone();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(cooldown).await;
+            // A reconnect configures every product on login anyway.
+            if !manager.is_paused(&product) || !*client.login_status().borrow() {
+                return;
+            }
+            if let Err(e) = manager.configure(&client, &product).await {
+                warn!("Failed to re-arm mm protection on {product}: {e}");
+            }
+        });
+        self.track(task);
+    }
+
+    pub fn status(&self, product: &str) -> Option<ProtectionStatus> {
+        self.status.read().unwrap().get(product).cloned()
+    }
+
+    pub fn remaining_amount(&self, product: &str) -> Option<Decimal> {
+        self.status(product)?.remaining_amount
+    }
+
+    /// Whether `product` is tripped and waiting to be refilled.
+    pub fn is_paused(&self, product: &str) -> bool {
+        self.status(product).is_some_and(|s| s.tripped)
+    }
+
+    /// `true` while any product is tripped.
+    pub fn paused(&self) -> watch::Receiver<bool> {
+        self.paused.subscribe()
+    }
+}
@@ -8,20 +8,23 @@
  * Generated by: https://openapi-generator.tech
  */
 
-use crate::models;
+use crate::{
+    manual_models::book::{BookLevel, BookTrade},
+    models,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Book {
     /// List of `[price, amount, direction, timestamp, implied_taker]`. Note that the snapshot of this feed may contain older trades that happened since the last restart of the gateway.  The `implied_taker` is a boolean flag, set to `true` when the actual taker trade happened on another order book, and the maker trade on this book is the result of implied matching.  Trades are not sent for combination order books.
     #[serde(rename = "trades", skip_serializing_if = "Option::is_none")]
-    pub trades: Option<Vec<serde_json::Value>>,
+    pub trades: Option<Vec<BookTrade>>,
     /// List of price level updates (price, amount, outright amount) for buy orders, amount 0 means level is now empty.  For combination order books, price and amount refer to the price and amount per unit of the combination.
     #[serde(rename = "bid_changes", skip_serializing_if = "Option::is_none")]
-    pub bid_changes: Option<Vec<serde_json::Value>>,
+    pub bid_changes: Option<Vec<BookLevel>>,
     /// List of price level updates (price, amount, outright amount) for sell orders, amount 0 means level is now empty.  For combination order books, price and amount refer to the price and amount per unit of the combination.
     #[serde(rename = "ask_changes", skip_serializing_if = "Option::is_none")]
-    pub ask_changes: Option<Vec<serde_json::Value>>,
+    pub ask_changes: Option<Vec<BookLevel>>,
     /// The total amount of bid orders across all levels.
     #[serde(rename = "total_bid_amount", skip_serializing_if = "Option::is_none")]
     pub total_bid_amount: Option<rust_decimal::Decimal>,
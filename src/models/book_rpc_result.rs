@@ -8,17 +8,17 @@
  * Generated by: https://openapi-generator.tech
  */
 
-use crate::models;
+use crate::{manual_models::book::BookLevel, models};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct BookRpcResult {
     /// Bids (list of price, amount, outright-amount)
     #[serde(rename = "bids")]
-    pub bids: Vec<Vec<rust_decimal::Decimal>>,
+    pub bids: Vec<BookLevel>,
     /// Asks (list of price, amount, outright-amount)
     #[serde(rename = "asks")]
-    pub asks: Vec<Vec<rust_decimal::Decimal>>,
+    pub asks: Vec<BookLevel>,
     /// Last traded price
     #[serde(rename = "last", skip_serializing_if = "Option::is_none")]
     pub last: Option<rust_decimal::Decimal>,
@@ -29,8 +29,8 @@ pub struct BookRpcResult {
 
 impl BookRpcResult {
     pub fn new(
-        bids: Vec<Vec<rust_decimal::Decimal>>,
-        asks: Vec<Vec<rust_decimal::Decimal>>,
+        bids: Vec<BookLevel>,
+        asks: Vec<BookLevel>,
         time: rust_decimal::Decimal,
     ) -> BookRpcResult {
         BookRpcResult {
@@ -8,17 +8,17 @@
  * Generated by: https://openapi-generator.tech
  */
 
-use crate::models;
+use crate::{manual_models::book::BookLevel, models};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Lwt {
     /// Best bid (if any).
     #[serde(rename = "b", skip_serializing_if = "Option::is_none")]
-    pub b: Option<Vec<serde_json::Value>>,
+    pub b: Option<BookLevel>,
     /// Best ask (if any).
     #[serde(rename = "a", skip_serializing_if = "Option::is_none")]
-    pub a: Option<Vec<serde_json::Value>>,
+    pub a: Option<BookLevel>,
     /// Mark price.
     #[serde(rename = "m", skip_serializing_if = "Option::is_none")]
     pub m: Option<rust_decimal::Decimal>,
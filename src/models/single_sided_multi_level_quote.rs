@@ -8,14 +8,7 @@
  * Generated by: https://openapi-generator.tech
  */
 
-use crate::models;
-use serde::{Deserialize, Serialize};
+use crate::manual_models::book::BookLevel;
 
-#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
-pub struct SingleSidedMultiLevelQuote {}
-
-impl SingleSidedMultiLevelQuote {
-    pub fn new() -> SingleSidedMultiLevelQuote {
-        SingleSidedMultiLevelQuote {}
-    }
-}
+/// SingleSidedMultiLevelQuote : Up to 10 `[price, amount]` levels; an empty array removes the side.
+pub type SingleSidedMultiLevelQuote = Vec<BookLevel>;
@@ -0,0 +1,410 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use log::{info, warn};
+use rust_decimal::Decimal;
+use thiserror::Error;
+use tokio::{sync::Notify, task::JoinHandle};
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    manual_models::book::{BookLevel, BookTrade},
+    models::{Book, BookNotification, BookParams, BookRpcResult, Delay},
+    types::{Error, ExternalEvent, RequestScope},
+    ws_client::WsClient,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BookSide {
+    Bid,
+    Ask,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum OrderBookError {
+    #[error("{side:?} total mismatch: exchange {expected}, local {actual}")]
+    TotalsMismatch {
+        side: BookSide,
+        expected: Decimal,
+        actual: Decimal,
+    },
+}
+
+/// Local copy of an instrument's order book, maintained from `book.*` updates.
+///
+/// Book updates carry absolute amounts per level, so a snapshot followed by any
+/// number of updates yields the current book. Levels with a zero amount are removed.
+#[derive(Clone, Debug, Default)]
+pub struct OrderBook {
+    instrument_name: String,
+    bids: BTreeMap<Decimal, BookLevel>,
+    asks: BTreeMap<Decimal, BookLevel>,
+    last_trade: Option<BookTrade>,
+    time: Option<Decimal>,
+    check_totals: bool,
+}
+
+impl OrderBook {
+    pub fn new(instrument_name: &str) -> Self {
+        OrderBook {
+            instrument_name: instrument_name.to_string(),
+            check_totals: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_snapshot(instrument_name: &str, snapshot: &BookRpcResult) -> Self {
+        let mut book = OrderBook::new(instrument_name);
+        book.apply_snapshot(snapshot);
+        book
+    }
+
+    /// Compare `total_bid_amount`/`total_ask_amount` against the local sum after each update.
+    /// Disable this for depth-limited subscriptions, where the totals cover levels we never see.
+    pub fn with_totals_check(mut self, enabled: bool) -> Self {
+        self.check_totals = enabled;
+        self
+    }
+
+    pub fn instrument_name(&self) -> &str {
+        &self.instrument_name
+    }
+
+    /// Time of the last applied update (Unix timestamp).
+    pub fn time(&self) -> Option<Decimal> {
+        self.time
+    }
+
+    pub fn last_trade(&self) -> Option<&BookTrade> {
+        self.last_trade.as_ref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.last_trade = None;
+        self.time = None;
+    }
+
+    /// Replaces the whole book with a `public/book` snapshot.
+    pub fn apply_snapshot(&mut self, snapshot: &BookRpcResult) {
+        self.clear();
+        for level in &snapshot.bids {
+            Self::set_level(&mut self.bids, level);
+        }
+        for level in &snapshot.asks {
+            Self::set_level(&mut self.asks, level);
+        }
+        self.time = Some(snapshot.time);
+    }
+
+    /// Applies a `book.*` notification. On a totals mismatch the update is still applied,
+    /// but the book should be resynced.
+    pub fn apply(&mut self, update: &Book) -> Result<(), OrderBookError> {
+        for level in update.bid_changes.iter().flatten() {
+            Self::set_level(&mut self.bids, level);
+        }
+        for level in update.ask_changes.iter().flatten() {
+            Self::set_level(&mut self.asks, level);
+        }
+        if let Some(trade) = update.trades.as_ref().and_then(|t| t.last()) {
+            self.last_trade = Some(*trade);
+        }
+        if update.time.is_some() {
+            self.time = update.time;
+        }
+        if self.check_totals {
+            Self::check_total(BookSide::Bid, update.total_bid_amount, &self.bids)?;
+            Self::check_total(BookSide::Ask, update.total_ask_amount, &self.asks)?;
+        }
+        Ok(())
+    }
+
+    fn set_level(levels: &mut BTreeMap<Decimal, BookLevel>, level: &BookLevel) {
+        if level.is_empty() {
+            levels.remove(&level.price);
+        } else {
+            levels.insert(level.price, *level);
+        }
+    }
+
+    fn check_total(
+        side: BookSide,
+        expected: Option<Decimal>,
+        levels: &BTreeMap<Decimal, BookLevel>,
+    ) -> Result<(), OrderBookError> {
+        let Some(expected) = expected else {
+            return Ok(());
+        };
+        let actual: Decimal = levels.values().map(|l| l.amount).sum();
+        if actual != expected {
+            return Err(OrderBookError::TotalsMismatch {
+                side,
+                expected,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Levels of one side, best price first.
+    pub fn levels(&self, side: BookSide) -> Box<dyn Iterator<Item = &BookLevel> + '_> {
+        match side {
+            BookSide::Bid => Box::new(self.bids.values().rev()),
+            BookSide::Ask => Box::new(self.asks.values()),
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<BookLevel> {
+        self.bids.last_key_value().map(|(_, l)| *l)
+    }
+
+    pub fn best_ask(&self) -> Option<BookLevel> {
+        self.asks.first_key_value().map(|(_, l)| *l)
+    }
+
+    pub fn spread(&self) -> Option<Decimal> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    pub fn mid(&self) -> Option<Decimal> {
+        Some((self.best_ask()?.price + self.best_bid()?.price) / Decimal::TWO)
+    }
+
+    /// Top-of-book price weighted by the opposite side's size.
+    pub fn microprice(&self) -> Option<Decimal> {
+        let bid = self.best_bid()?;
+        let ask = self.best_ask()?;
+        let size = bid.amount + ask.amount;
+        if size.is_zero() {
+            return None;
+        }
+        Some((bid.price * ask.amount + ask.price * bid.amount) / size)
+    }
+
+    /// Amount resting at exactly `price`.
+    pub fn depth_at(&self, side: BookSide, price: Decimal) -> Decimal {
+        let levels = match side {
+            BookSide::Bid => &self.bids,
+            BookSide::Ask => &self.asks,
+        };
+        levels.get(&price).map_or(Decimal::ZERO, |l| l.amount)
+    }
+
+    /// Amount resting at `price` or better.
+    pub fn cumulative_depth(&self, side: BookSide, price: Decimal) -> Decimal {
+        match side {
+            BookSide::Bid => self.bids.range(price..).map(|(_, l)| l.amount).sum(),
+            BookSide::Ask => self.asks.range(..=price).map(|(_, l)| l.amount).sum(),
+        }
+    }
+
+    pub fn total(&self, side: BookSide) -> Decimal {
+        self.levels(side).map(|l| l.amount).sum()
+    }
+
+    /// Average price of sweeping `size` from `side`, best level first.
+    /// Returns `None` if the side doesn't hold enough.
+    pub fn vwap_to_size(&self, side: BookSide, size: Decimal) -> Option<Decimal> {
+        if size <= Decimal::ZERO {
+            return None;
+        }
+        let mut remaining = size;
+        let mut notional = Decimal::ZERO;
+        for level in self.levels(side) {
+            let take = remaining.min(level.amount);
+            notional += take * level.price;
+            remaining -= take;
+            if remaining.is_zero() {
+                return Some(notional / size);
+            }
+        }
+        None
+    }
+}
+
+/// An [`OrderBook`] kept up to date from a `book.<instrument>.<grouping>.<nlevels>.<delay>`
+/// subscription.
+///
+/// The book is seeded from a `public/book` snapshot, grouped and cut to the channel's
+/// depth, since the channel only sends its own snapshot to its first subscriber. It is
+/// reseeded when the exchange totals disagree with the local book and after every
+/// reconnect. Updates older than the snapshot are skipped.
+#[derive(Clone)]
+pub struct LiveOrderBook {
+    book: Arc<RwLock<OrderBook>>,
+    channel: String,
+    grouping: Option<Decimal>,
+    depth: Option<usize>,
+    resync_needed: Arc<Notify>,
+    subscription: Arc<Mutex<Option<SubscriptionHandle>>>,
+    task: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl LiveOrderBook {
+    pub async fn subscribe(
+        client: Arc<WsClient>,
+        instrument: &str,
+        grouping: &str,
+        nlevels: &str,
+        delay: Delay,
+    ) -> Result<Self, Error> {
+        let channel = format!("book.{instrument}.{grouping}.{nlevels}.{delay}");
+        let depth = nlevels.parse::<usize>().ok();
+        let live = LiveOrderBook {
+            // Depth-limited books can't be checked against the totals of the full book.
+            book: Arc::new(RwLock::new(
+                OrderBook::new(instrument).with_totals_check(depth.is_none()),
+            )),
+            channel,
+            grouping: grouping
+                .parse::<Decimal>()
+                .ok()
+                .filter(|g| *g > Decimal::ZERO),
+            depth,
+            resync_needed: Arc::new(Notify::new()),
+            subscription: Arc::new(Mutex::new(None)),
+            task: Arc::new(Mutex::new(None)),
+        };
+        live.subscribe_channel(&client).await?;
+        if let Err(e) = live.resync(&client).await {
+            warn!("Failed to seed {}: {e}", live.channel);
+        }
+
+        let worker = live.clone();
+        let mut state_rx = client.connection_state();
+        state_rx.mark_unchanged();
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = worker.resync_needed.notified() => {}
+                    changed = state_rx.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        match *state_rx.borrow_and_update() {
+                            ExternalEvent::Connected => {}
+                            ExternalEvent::Disconnected => continue,
+                            ExternalEvent::Exited => break,
+                        }
+                    }
+                }
+                if let Err(e) = worker.resync(&client).await {
+                    warn!("Failed to resync {}: {e}", worker.channel);
+                }
+            }
+        });
+        *live.task.lock().unwrap() = Some(task);
+        Ok(live)
+    }
+
+    /// Stops resyncing and unsubscribes the book's handler.
+    pub async fn stop(&self, client: &WsClient) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+        let subscription = self.subscription.lock().unwrap().take();
+        if let Err(e) = client.release(subscription).await {
+            warn!("Failed to unsubscribe {}: {e}", self.channel);
+        }
+    }
+
+    async fn subscribe_channel(&self, client: &WsClient) -> Result<(), Error> {
+        let book = self.book.clone();
+        let resync_needed = self.resync_needed.clone();
+        let channel = self.channel.clone();
+        let handle = client
+            .subscribe_shared_channel(
+                RequestScope::Public,
+                self.channel.clone(),
+                move |msg: BookNotification| {
+                    let mut book = book.write().unwrap();
+                    let stale = matches!(
+                        (msg.notification.time, book.time()),
+                        (Some(update), Some(seeded)) if update < seeded
+                    );
+                    if stale {
+                        return;
+                    }
+                    if let Err(e) = book.apply(&msg.notification) {
+                        warn!("Order book {channel} out of sync ({e}), resyncing");
+                        resync_needed.notify_one();
+                    }
+                },
+            )
+            .await?;
+        *self.subscription.lock().unwrap() = Some(handle);
+        Ok(())
+    }
+
+    /// Replaces the local book with a `public/book` snapshot shaped like the channel.
+    async fn resync(&self, client: &WsClient) -> Result<(), Error> {
+        let instrument_name = self.book.read().unwrap().instrument_name().to_string();
+        let snapshot = client
+            .rpc()
+            .market_data()
+            .book(BookParams::new(instrument_name))
+            .await?;
+        let snapshot = BookRpcResult {
+            bids: self.shape(&snapshot.bids, BookSide::Bid),
+            asks: self.shape(&snapshot.asks, BookSide::Ask),
+            ..snapshot
+        };
+        self.book.write().unwrap().apply_snapshot(&snapshot);
+        info!("Resynced order book {}", self.channel);
+        Ok(())
+    }
+
+    /// Groups snapshot levels into the channel's price buckets and keeps as many, best
+    /// first, as the channel sends.
+    fn shape(&self, levels: &[BookLevel], side: BookSide) -> Vec<BookLevel> {
+        let mut grouped: BTreeMap<Decimal, BookLevel> = BTreeMap::new();
+        for level in levels {
+            let price = match (self.grouping, side) {
+                (None, _) => level.price,
+                (Some(g), BookSide::Bid) => (level.price / g).floor() * g,
+                (Some(g), BookSide::Ask) => (level.price / g).ceil() * g,
+            };
+            grouped
+                .entry(price)
+                .and_modify(|l| {
+                    l.amount += level.amount;
+                    l.outright_amount = l
+                        .outright_amount
+                        .zip(level.outright_amount)
+                        .map(|(a, b)| a + b);
+                })
+                .or_insert(BookLevel { price, ..*level });
+        }
+        let depth = self.depth.unwrap_or(usize::MAX);
+        match side {
+            BookSide::Bid => grouped.into_values().rev().take(depth).collect(),
+            BookSide::Ask => grouped.into_values().take(depth).collect(),
+        }
+    }
+
+    /// Schedules a reseed of the book from a fresh snapshot.
+    pub fn request_resync(&self) {
+        self.resync_needed.notify_one();
+    }
+
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// Runs `f` against the current book.
+    pub fn read<R>(&self, f: impl FnOnce(&OrderBook) -> R) -> R {
+        f(&self.book.read().unwrap())
+    }
+
+    /// Copy of the current book.
+    pub fn snapshot(&self) -> OrderBook {
+        self.book.read().unwrap().clone()
+    }
+}
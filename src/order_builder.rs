@@ -0,0 +1,329 @@
+use dashmap::DashMap;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    models::{
+        AmendParams, CollarEnum, DirectionEnum, InsertParams, Instrument, OrderTypeEnum,
+        StpActionEnum, StpLevelEnum, TimeInForceEnum,
+    },
+    ticks::{Rounding, TickSpec, is_on_step, round_to_step},
+    ws_client::WsClient,
+};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum OrderValidationError {
+    #[error("instrument {0} is not in the instrument cache")]
+    UnknownInstrument(String),
+    #[error("{0} has no positive tick size and volume tick size")]
+    MissingTickSize(String),
+    #[error("limit orders need a price")]
+    MissingPrice,
+    #[error("market orders take no price")]
+    UnexpectedPrice,
+    #[error("amount must be positive, got {0}")]
+    NonPositiveAmount(Decimal),
+    #[error("price {price} is not a multiple of the tick size {tick_size}")]
+    PriceNotAligned { price: Decimal, tick_size: Decimal },
+    #[error("amount {amount} is not a multiple of the volume tick size {volume_tick_size}")]
+    AmountNotAligned {
+        amount: Decimal,
+        volume_tick_size: Decimal,
+    },
+    #[error("amount {amount} is below the minimum order amount {min_order_amount}")]
+    BelowMinimumAmount {
+        amount: Decimal,
+        min_order_amount: Decimal,
+    },
+    #[error("post-only orders must be good till cancelled limit orders")]
+    PostOnlyNotResting,
+    #[error("reject_post_only requires post_only")]
+    RejectWithoutPostOnly,
+    #[error("exactly one of order_id and client_order_id must be given")]
+    AmbiguousOrderReference,
+}
+
+/// Fluent builder for `private/insert`, checked against the instrument's ticks before
+/// anything is sent.
+///
+/// ```ignore
+/// let params = OrderBuilder::limit(DirectionEnum::Buy, "BTC-PERPETUAL", dec!(0.1), dec!(90000))
+///     .post_only()
+///     .label("mm")
+///     .build_for(&client)?;
+/// client.rpc().trading().insert(params).await?;
+/// ```
+///
+/// With [`OrderBuilder::auto_round`] prices move to the tick away from the touch (bids
+/// down, asks up) and amounts down to the volume tick, instead of failing validation.
+#[derive(Clone, Debug)]
+pub struct OrderBuilder {
+    params: InsertParams,
+    auto_round: bool,
+}
+
+impl OrderBuilder {
+    pub fn limit(
+        direction: DirectionEnum,
+        instrument_name: impl Into<String>,
+        amount: Decimal,
+        price: Decimal,
+    ) -> Self {
+        let mut builder = OrderBuilder::new(direction, instrument_name.into(), amount);
+        builder.params.order_type = Some(OrderTypeEnum::Limit);
+        builder.params.price = Some(price);
+        builder
+    }
+
+    pub fn market(
+        direction: DirectionEnum,
+        instrument_name: impl Into<String>,
+        amount: Decimal,
+    ) -> Self {
+        let mut builder = OrderBuilder::new(direction, instrument_name.into(), amount);
+        builder.params.order_type = Some(OrderTypeEnum::Market);
+        builder
+    }
+
+    fn new(direction: DirectionEnum, instrument_name: String, amount: Decimal) -> Self {
+        OrderBuilder {
+            params: InsertParams {
+                direction,
+                instrument_name: Some(instrument_name),
+                amount,
+                ..Default::default()
+            },
+            auto_round: false,
+        }
+    }
+
+    /// Never trade on insert: a crossing price is moved one tick away from the touch.
+    pub fn post_only(mut self) -> Self {
+        self.params.post_only = Some(true);
+        self
+    }
+
+    /// Post-only, but cancel instead of moving a crossing price (book or cancel).
+    pub fn book_or_cancel(mut self) -> Self {
+        self.params.post_only = Some(true);
+        self.params.reject_post_only = Some(true);
+        self
+    }
+
+    /// Cancel whatever doesn't fill immediately. The exchange has no fill-or-kill.
+    pub fn immediate_or_cancel(mut self) -> Self {
+        self.params.time_in_force = Some(TimeInForceEnum::ImmediateOrCancel);
+        self
+    }
+
+    pub fn reduce_only(mut self) -> Self {
+        self.params.reduce_only = Some(true);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.params.label = Some(label.into());
+        self
+    }
+
+    pub fn client_order_id(mut self, client_order_id: i32) -> Self {
+        self.params.client_order_id = Some(client_order_id);
+        self
+    }
+
+    pub fn collar(mut self, collar: CollarEnum) -> Self {
+        self.params.collar = Some(collar);
+        self
+    }
+
+    /// Self-trade prevention scope and what to do on a match.
+    pub fn self_trade_prevention(mut self, level: StpLevelEnum, action: StpActionEnum) -> Self {
+        self.params.stp_level = Some(level);
+        self.params.stp_action = Some(action);
+        self
+    }
+
+    /// Round price and amount to the instrument's ticks instead of rejecting them.
+    pub fn auto_round(mut self) -> Self {
+        self.auto_round = true;
+        self
+    }
+
+    /// Validates against `WsClient::instruments_cache`.
+    pub fn build_for(self, client: &WsClient) -> Result<InsertParams, OrderValidationError> {
+        self.build(&client.instruments_cache)
+    }
+
+    pub fn build(
+        self,
+        instruments: &DashMap<String, Instrument>,
+    ) -> Result<InsertParams, OrderValidationError> {
+        let mut params = self.params;
+        let market = params.order_type == Some(OrderTypeEnum::Market);
+        match (market, params.price) {
+            (false, None) => return Err(OrderValidationError::MissingPrice),
+            (true, Some(_)) => return Err(OrderValidationError::UnexpectedPrice),
+            _ => {}
+        }
+        if params.post_only == Some(true)
+            && (market || params.time_in_force == Some(TimeInForceEnum::ImmediateOrCancel))
+        {
+            return Err(OrderValidationError::PostOnlyNotResting);
+        }
+        if params.reject_post_only == Some(true) && params.post_only != Some(true) {
+            return Err(OrderValidationError::RejectWithoutPostOnly);
+        }
+
+        let name = params.instrument_name.clone().unwrap_or_default();
+        let instrument = instruments
+            .get(&name)
+            .ok_or_else(|| OrderValidationError::UnknownInstrument(name.clone()))?;
+        let spec = TickSpec::from_instrument(&instrument)
+            .map_err(|_| OrderValidationError::MissingTickSize(name))?;
+        let (tick_size, volume_tick_size) = (spec.tick_size(), spec.volume_tick_size());
+
+        if let Some(price) = params.price {
+            let passive = Rounding::passive(params.direction);
+            params.price = Some(
+                align(price, tick_size, passive, self.auto_round)
+                    .ok_or(OrderValidationError::PriceNotAligned { price, tick_size })?,
+            );
+        }
+        let amount = params.amount;
+        if amount <= Decimal::ZERO {
+            return Err(OrderValidationError::NonPositiveAmount(amount));
+        }
+        params.amount = align(amount, volume_tick_size, Rounding::Down, self.auto_round).ok_or(
+            OrderValidationError::AmountNotAligned {
+                amount,
+                volume_tick_size,
+            },
+        )?;
+        if let Some(min_order_amount) = spec.min_order_amount()
+            && params.amount < min_order_amount
+        {
+            return Err(OrderValidationError::BelowMinimumAmount {
+                amount: params.amount,
+                min_order_amount,
+            });
+        }
+        Ok(params)
+    }
+}
+
+/// Fluent builder for `private/amend`, validated like [`OrderBuilder`].
+#[derive(Clone, Debug)]
+pub struct AmendBuilder {
+    params: AmendParams,
+    instrument_name: String,
+    direction: DirectionEnum,
+    auto_round: bool,
+}
+
+impl AmendBuilder {
+    /// Amends the order with `order_id`, a `direction` order on `instrument_name`; the
+    /// direction decides which way prices are rounded.
+    pub fn order_id(
+        order_id: impl Into<String>,
+        instrument_name: impl Into<String>,
+        direction: DirectionEnum,
+    ) -> Self {
+        AmendBuilder::new(
+            Some(order_id.into()),
+            None,
+            instrument_name.into(),
+            direction,
+        )
+    }
+
+    pub fn client_order_id(
+        client_order_id: i32,
+        instrument_name: impl Into<String>,
+        direction: DirectionEnum,
+    ) -> Self {
+        AmendBuilder::new(
+            None,
+            Some(client_order_id),
+            instrument_name.into(),
+            direction,
+        )
+    }
+
+    fn new(
+        order_id: Option<String>,
+        client_order_id: Option<i32>,
+        instrument_name: String,
+        direction: DirectionEnum,
+    ) -> Self {
+        AmendBuilder {
+            params: AmendParams {
+                order_id,
+                client_order_id,
+                ..Default::default()
+            },
+            instrument_name,
+            direction,
+            auto_round: false,
+        }
+    }
+
+    pub fn price(mut self, price: Decimal) -> Self {
+        self.params.price = price;
+        self
+    }
+
+    /// New total amount of the order, including what has already been filled.
+    pub fn amount(mut self, amount: Decimal) -> Self {
+        self.params.amount = amount;
+        self
+    }
+
+    pub fn collar(mut self, collar: CollarEnum) -> Self {
+        self.params.collar = Some(collar);
+        self
+    }
+
+    pub fn auto_round(mut self) -> Self {
+        self.auto_round = true;
+        self
+    }
+
+    pub fn build_for(self, client: &WsClient) -> Result<AmendParams, OrderValidationError> {
+        self.build(&client.instruments_cache)
+    }
+
+    pub fn build(
+        self,
+        instruments: &DashMap<String, Instrument>,
+    ) -> Result<AmendParams, OrderValidationError> {
+        if self.params.order_id.is_some() == self.params.client_order_id.is_some() {
+            return Err(OrderValidationError::AmbiguousOrderReference);
+        }
+        let order = OrderBuilder::limit(
+            self.direction,
+            self.instrument_name,
+            self.params.amount,
+            self.params.price,
+        );
+        let order = if self.auto_round {
+            order.auto_round()
+        } else {
+            order
+        };
+        let checked = order.build(instruments)?;
+        Ok(AmendParams {
+            price: checked.price.unwrap_or(self.params.price),
+            amount: checked.amount,
+            ..self.params
+        })
+    }
+}
+
+/// `value` on a multiple of `step`: rounded if `round`, else only if it already is one.
+/// `step` comes from a [`TickSpec`], so it is positive.
+fn align(value: Decimal, step: Decimal, rounding: Rounding, round: bool) -> Option<Decimal> {
+    if is_on_step(value, step).ok()? {
+        return Some(value);
+    }
+    round.then(|| round_to_step(value, step, rounding).ok())?
+}
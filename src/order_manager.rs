@@ -0,0 +1,501 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use dashmap::DashMap;
+use log::{debug, info, warn};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    manual_models::error_code::ErrorCode,
+    models::{
+        InsertParams, OrderFill, OrderHistory, OrderHistoryParams, OrderStatus, RpcErrorResponse,
+        SessionOrdersNotification, StatusEnum,
+    },
+    types::{ClientError, Error, RequestScope},
+    ws_client::WsClient,
+};
+
+/// Time given to `session.orders` to report an order before asking the exchange.
+const RESOLVE_GRACE: Duration = Duration::from_millis(500);
+/// How far back `private/order_history` is searched for an order of unknown outcome.
+const RESOLVE_HISTORY_SECS: i64 = 300;
+
+pub type FillCallback = Box<dyn Fn(&OrderStatus, &OrderFill) + Send + Sync>;
+pub type CancelCallback = Box<dyn Fn(&OrderStatus) + Send + Sync>;
+
+/// Identifies a callback registered with [`OrderManager::on_fill`] or
+/// [`OrderManager::on_cancel`], for [`OrderManager::remove_callback`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CallbackId(u64);
+
+/// Private channel an [`OrderManager`] follows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OrderFeed {
+    /// `session.orders`: orders of this connection only.
+    #[default]
+    Session,
+    /// `account.orders`: every order of the account, including other sessions and bots.
+    Account,
+}
+
+impl OrderFeed {
+    pub fn channel(&self) -> &'static str {
+        match self {
+            OrderFeed::Session => "session.orders",
+            OrderFeed::Account => "account.orders",
+        }
+    }
+}
+
+/// Where a submitted order was found.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubmittedOrder {
+    /// Order state from the insert response, the orders feed or `private/open_orders`.
+    Live(OrderStatus),
+    /// The order was already closed when it was looked up in `private/order_history`.
+    Closed(OrderHistory),
+    /// The exchange reported the `client_order_id` as taken by our own earlier attempt,
+    /// but the order could not be looked up yet.
+    Acknowledged(i32),
+}
+
+#[derive(Debug, Error)]
+pub enum SubmitError {
+    /// The exchange refused the order; it does not exist.
+    #[error("order rejected: {0:?}")]
+    Rejected(RpcErrorResponse),
+    /// The order may or may not exist. Call [`OrderManager::resolve`] once the connection
+    /// is back.
+    #[error("outcome of order {client_order_id} unknown: {source}")]
+    Unresolved {
+        client_order_id: i32,
+        source: ClientError,
+    },
+}
+
+/// In-memory book of our open orders, keyed by `order_id` and `client_order_id`.
+///
+/// Each tracked [`OrderStatus`] carries every fill seen for the order, not just the
+/// ones from the last update. Orders leave the book once they are filled or cancelled;
+/// register [`OrderManager::on_fill`] and [`OrderManager::on_cancel`] to observe that.
+/// After every login the book is reconciled against `private/open_orders`.
+#[derive(Clone, Default)]
+pub struct OrderManager {
+    orders: Arc<DashMap<String, OrderStatus>>,
+    client_order_ids: Arc<DashMap<Decimal, String>>,
+    fill_callbacks: Arc<RwLock<Vec<(CallbackId, FillCallback)>>>,
+    cancel_callbacks: Arc<RwLock<Vec<(CallbackId, CancelCallback)>>>,
+    next_callback_id: Arc<AtomicU64>,
+    subscription: Arc<Mutex<Option<SubscriptionHandle>>>,
+    login_task: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl OrderManager {
+    /// Creates an empty manager without subscribing to anything.
+    pub fn new() -> Self {
+        OrderManager::default()
+    }
+
+    /// Subscribes to `feed`, loads the open orders and reconciles again after every login.
+    /// The client must already be logged in.
+    pub async fn start(client: Arc<WsClient>, feed: OrderFeed) -> Result<Self, Error> {
+        let manager = OrderManager::new();
+        let handler = manager.clone();
+        // account.orders carries the same payload as session.orders.
+        let subscription = client
+            .subscribe_shared_channel(
+                RequestScope::Private,
+                feed.channel().to_string(),
+                move |msg: SessionOrdersNotification| {
+                    for order in msg.notification {
+                        handler.apply(order);
+                    }
+                },
+            )
+            .await?;
+        *manager.subscription.lock().unwrap() = Some(subscription);
+        manager.refresh(&client).await?;
+
+        let worker = manager.clone();
+        let mut login_rx = client.login_status();
+        login_rx.mark_unchanged();
+        let login_task = tokio::spawn(async move {
+            while login_rx.changed().await.is_ok() {
+                if !*login_rx.borrow_and_update() {
+                    continue;
+                }
+                if let Err(e) = worker.refresh(&client).await {
+                    warn!("Failed to reconcile orders after login: {e}");
+                }
+            }
+        });
+        *manager.login_task.lock().unwrap() = Some(login_task);
+        info!("Order manager started with {} open orders", manager.len());
+        Ok(manager)
+    }
+
+    /// Stops reconciling after logins and unsubscribes from the orders feed. The book and
+    /// callbacks are kept; orders can still be applied by hand.
+    pub async fn stop(&self, client: &WsClient) {
+        if let Some(task) = self.login_task.lock().unwrap().take() {
+            task.abort();
+        }
+        let subscription = self.subscription.lock().unwrap().take();
+        if let Err(e) = client.release(subscription).await {
+            warn!("Failed to unsubscribe orders feed: {e}");
+        }
+    }
+
+    /// Fetches `private/open_orders` and reconciles the book against it, looking up the
+    /// orders that closed meanwhile in `private/order_history`.
+    pub async fn refresh(&self, client: &WsClient) -> Result<(), Error> {
+        let open_orders = client.rpc().accounting().open_orders().await?;
+        let closed = self.closed_history(client, &open_orders).await?;
+        self.reconcile(open_orders, &closed);
+        Ok(())
+    }
+
+    /// History of the tracked orders missing from `open_orders`.
+    async fn closed_history(
+        &self,
+        client: &WsClient,
+        open_orders: &[OrderStatus],
+    ) -> Result<Vec<OrderHistory>, ClientError> {
+        let vanished = self.vanished(open_orders);
+        let Some(since) = vanished.iter().map(|o| o.create_time).min() else {
+            return Ok(Vec::new());
+        };
+        let mut missing: HashSet<String> = vanished.into_iter().map(|o| o.order_id).collect();
+        let mut closed = Vec::new();
+        let mut bookmark = None;
+        loop {
+            let page = client
+                .rpc()
+                .accounting()
+                .order_history(OrderHistoryParams {
+                    time_low: since.floor().to_i64(),
+                    bookmark,
+                    ..Default::default()
+                })
+                .await?;
+            let orders = page.orders.unwrap_or_default();
+            let exhausted = orders.is_empty();
+            closed.extend(orders.into_iter().filter(|o| missing.remove(&o.order_id)));
+            bookmark = page.bookmark;
+            if missing.is_empty() || exhausted || bookmark.is_none() {
+                return Ok(closed);
+            }
+        }
+    }
+
+    /// Inserts an order so that it is placed at most once, even if the response is lost.
+    ///
+    /// A `client_order_id` is assigned if `params` has none. When the insert fails without
+    /// an answer from the exchange the order is looked up by that id, and inserted again
+    /// with the same id if it can't be found; a [`ErrorCode::DuplicateOrderId`] on that
+    /// second attempt confirms the first one went through.
+    pub async fn submit(
+        &self,
+        client: &WsClient,
+        mut params: InsertParams,
+    ) -> Result<SubmittedOrder, SubmitError> {
+        let client_order_id = *params
+            .client_order_id
+            .get_or_insert_with(|| client.next_client_order_id());
+        let mut retried = false;
+        loop {
+            let error = match client.rpc().trading().insert(params.clone()).await {
+                Ok(order) => {
+                    self.apply(order.clone());
+                    return Ok(SubmittedOrder::Live(order));
+                }
+                Err(error) => error,
+            };
+            let duplicate = is_duplicate_order_id(&error);
+            match error {
+                ClientError::Rpc(rejection) if !(duplicate && retried) => {
+                    return Err(SubmitError::Rejected(rejection));
+                }
+                source => {
+                    warn!("Insert of order {client_order_id} ended ambiguously: {source}");
+                    let unresolved = |source| SubmitError::Unresolved {
+                        client_order_id,
+                        source,
+                    };
+                    match self.resolve(client, &params).await {
+                        Ok(Some(found)) => return Ok(found),
+                        Ok(None) if duplicate => {
+                            return Ok(SubmittedOrder::Acknowledged(client_order_id));
+                        }
+                        Ok(None) if !retried => retried = true,
+                        Ok(None) => return Err(unresolved(source)),
+                        Err(e) => return Err(unresolved(e)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Looks for the order inserted with `params` by its `client_order_id`, in the book,
+    /// then with `private/open_orders` and `private/order_history`. An order under that id
+    /// with another instrument, direction or amount is an older one and is skipped.
+    pub async fn resolve(
+        &self,
+        client: &WsClient,
+        params: &InsertParams,
+    ) -> Result<Option<SubmittedOrder>, ClientError> {
+        let Some(id) = params.client_order_id.map(Decimal::from) else {
+            return Ok(None);
+        };
+        let matches = |instrument_name: &Option<String>, direction, amount| {
+            *instrument_name == params.instrument_name
+                && direction == params.direction
+                && amount == params.amount
+        };
+        tokio::time::sleep(RESOLVE_GRACE).await;
+        if let Some(order) = self
+            .get_by_client_order_id(id)
+            .filter(|o| matches(&o.instrument_name, o.direction, o.amount))
+        {
+            return Ok(Some(SubmittedOrder::Live(order)));
+        }
+        let open_orders = client.rpc().accounting().open_orders().await?;
+        if let Some(order) = open_orders.into_iter().find(|o| {
+            o.client_order_id == Some(id) && matches(&o.instrument_name, o.direction, o.amount)
+        }) {
+            self.apply(order.clone());
+            return Ok(Some(SubmittedOrder::Live(order)));
+        }
+        let since = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64)
+            - RESOLVE_HISTORY_SECS;
+        let mut bookmark = None;
+        loop {
+            let page = client
+                .rpc()
+                .accounting()
+                .order_history(OrderHistoryParams {
+                    time_low: Some(since),
+                    bookmark,
+                    ..Default::default()
+                })
+                .await?;
+            let orders = page.orders.unwrap_or_default();
+            let exhausted = orders.is_empty();
+            if let Some(order) = orders.into_iter().find(|o| {
+                o.client_order_id == Some(id) && matches(&o.instrument_name, o.direction, o.amount)
+            }) {
+                return Ok(Some(SubmittedOrder::Closed(order)));
+            }
+            bookmark = page.bookmark;
+            if exhausted || bookmark.is_none() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Called with the order state after the update and the new fill.
+    pub fn on_fill(
+        &self,
+        callback: impl Fn(&OrderStatus, &OrderFill) + Send + Sync + 'static,
+    ) -> CallbackId {
+        let id = self.next_callback_id();
+        self.fill_callbacks
+            .write()
+            .unwrap()
+            .push((id, Box::new(callback)));
+        id
+    }
+
+    /// Called with the final state of an order cancelled by us, the exchange or a reconcile.
+    pub fn on_cancel(&self, callback: impl Fn(&OrderStatus) + Send + Sync + 'static) -> CallbackId {
+        let id = self.next_callback_id();
+        self.cancel_callbacks
+            .write()
+            .unwrap()
+            .push((id, Box::new(callback)));
+        id
+    }
+
+    /// Unregisters a fill or cancel callback. Unknown ids are ignored.
+    pub fn remove_callback(&self, id: CallbackId) {
+        self.fill_callbacks
+            .write()
+            .unwrap()
+            .retain(|(existing, _)| *existing != id);
+        self.cancel_callbacks
+            .write()
+            .unwrap()
+            .retain(|(existing, _)| *existing != id);
+    }
+
+    fn next_callback_id(&self) -> CallbackId {
+        CallbackId(self.next_callback_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Applies one order update from `session.orders`, `account.orders` or an RPC result.
+    pub fn apply(&self, mut update: OrderStatus) {
+        let previous = self.orders.get(&update.order_id).map(|e| e.clone());
+        if let Some(previous) = &previous
+            && update.filled_amount < previous.filled_amount
+        {
+            debug!("Ignoring stale update for order {}", update.order_id);
+            return;
+        }
+
+        let mut fills = previous.map(|p| p.fills).unwrap_or_default();
+        let known: HashSet<String> = fills.iter().map(|f| f.trade_id.clone()).collect();
+        let new_fills: Vec<OrderFill> = update
+            .fills
+            .drain(..)
+            .filter(|f| !known.contains(&f.trade_id))
+            .collect();
+        fills.extend(new_fills.iter().cloned());
+        update.fills = fills;
+
+        if is_closed(&update) {
+            self.forget(&update);
+        } else {
+            if let Some(client_order_id) = update.client_order_id {
+                self.client_order_ids
+                    .insert(client_order_id, update.order_id.clone());
+            }
+            self.orders.insert(update.order_id.clone(), update.clone());
+        }
+
+        if !new_fills.is_empty() {
+            let callbacks = self.fill_callbacks.read().unwrap();
+            for fill in &new_fills {
+                for (_, callback) in callbacks.iter() {
+                    callback(&update, fill);
+                }
+            }
+        }
+        if matches!(
+            update.status,
+            StatusEnum::Cancelled | StatusEnum::CancelledPartiallyFilled
+        ) {
+            self.notify_cancel(&update);
+        }
+    }
+
+    /// Replaces the book with `open_orders`. Tracked orders missing from it closed while we
+    /// weren't listening. Those found in `closed` end with their recorded status and fills,
+    /// reported like live updates; the rest are reported as cancelled.
+    pub fn reconcile(&self, open_orders: Vec<OrderStatus>, closed: &[OrderHistory]) {
+        for mut order in self.vanished(&open_orders) {
+            if let Some(history) = closed.iter().find(|h| h.order_id == order.order_id) {
+                debug!(
+                    "Order {} closed as {:?} while not listening",
+                    order.order_id, history.status
+                );
+                order.status = history.status;
+                order.filled_amount = history.filled_amount;
+                order.remaining_amount = Decimal::ZERO;
+                order.fills = history.fills.clone();
+                order.close_time = Some(history.close_time);
+                self.apply(order);
+                continue;
+            }
+            warn!("Order {} no longer open after reconcile", order.order_id);
+            self.forget(&order);
+            order.status = if order.filled_amount.is_zero() {
+                StatusEnum::Cancelled
+            } else {
+                StatusEnum::CancelledPartiallyFilled
+            };
+            order.remaining_amount = Decimal::ZERO;
+            self.notify_cancel(&order);
+        }
+        for order in open_orders {
+            self.apply(order);
+        }
+    }
+
+    /// Tracked orders that are not in `open_orders`.
+    fn vanished(&self, open_orders: &[OrderStatus]) -> Vec<OrderStatus> {
+        let live: HashSet<&str> = open_orders.iter().map(|o| o.order_id.as_str()).collect();
+        self.orders
+            .iter()
+            .filter(|e| !live.contains(e.key().as_str()))
+            .map(|e| e.value().clone())
+            .collect()
+    }
+
+    fn forget(&self, order: &OrderStatus) {
+        self.orders.remove(&order.order_id);
+        if let Some(client_order_id) = order.client_order_id {
+            self.client_order_ids.remove(&client_order_id);
+        }
+    }
+
+    fn notify_cancel(&self, order: &OrderStatus) {
+        for (_, callback) in self.cancel_callbacks.read().unwrap().iter() {
+            callback(order);
+        }
+    }
+
+    pub fn get(&self, order_id: &str) -> Option<OrderStatus> {
+        self.orders.get(order_id).map(|e| e.clone())
+    }
+
+    pub fn get_by_client_order_id(&self, client_order_id: Decimal) -> Option<OrderStatus> {
+        let order_id = self.client_order_ids.get(&client_order_id)?.clone();
+        self.get(&order_id)
+    }
+
+    pub fn open_orders(&self) -> Vec<OrderStatus> {
+        self.filter(|_| true)
+    }
+
+    pub fn by_instrument(&self, instrument_name: &str) -> Vec<OrderStatus> {
+        self.filter(|o| o.instrument_name.as_deref() == Some(instrument_name))
+    }
+
+    pub fn by_label(&self, label: &str) -> Vec<OrderStatus> {
+        self.filter(|o| o.label.as_deref() == Some(label))
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    fn filter(&self, predicate: impl Fn(&OrderStatus) -> bool) -> Vec<OrderStatus> {
+        let mut found: Vec<OrderStatus> = self
+            .orders
+            .iter()
+            .filter(|e| predicate(e.value()))
+            .map(|e| e.value().clone())
+            .collect();
+        found.sort_by_key(|o| o.create_time);
+        found
+    }
+}
+
+pub fn is_duplicate_order_id(error: &ClientError) -> bool {
+    matches!(
+        error,
+        ClientError::Rpc(RpcErrorResponse { error: Some(e), .. }) if e.code == ErrorCode::DuplicateOrderId
+    )
+}
+
+pub(crate) fn is_closed(order: &OrderStatus) -> bool {
+    match order.status {
+        StatusEnum::Open | StatusEnum::PartiallyFilled => order.remaining_amount.is_zero(),
+        StatusEnum::Cancelled | StatusEnum::CancelledPartiallyFilled | StatusEnum::Filled => true,
+    }
+}
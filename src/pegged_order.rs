@@ -0,0 +1,450 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use rust_decimal::Decimal;
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    models::{
+        AmendParams, CancelParams, Delay, DirectionEnum, InsertParams, OrderStatus, OrderTypeEnum,
+        Ticker, TickerNotification,
+    },
+    order_book::OrderBook,
+    order_manager::{OrderManager, SubmitError, SubmittedOrder},
+    portfolio_tracker::PortfolioTracker,
+    ticks::{Rounding, TickSpec},
+    types::{Error, RequestScope},
+    ws_client::WsClient,
+};
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+/// Price a [`PeggedOrder`] follows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PegReference {
+    /// Our own side of the touch: best bid for buys, best ask for sells.
+    #[default]
+    Best,
+    Mid,
+    Mark,
+}
+
+/// Market prices a peg is computed from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PegQuote {
+    pub best_bid: Option<Decimal>,
+    pub best_ask: Option<Decimal>,
+    pub mark: Option<Decimal>,
+}
+
+impl PegQuote {
+    pub fn from_ticker(ticker: &Ticker) -> Self {
+        PegQuote {
+            best_bid: ticker.best_bid_price,
+            best_ask: ticker.best_ask_price,
+            mark: Some(ticker.mark_price),
+        }
+    }
+
+    /// Touch of `book`; books carry no mark price.
+    pub fn from_book(book: &OrderBook) -> Self {
+        PegQuote {
+            best_bid: book.best_bid().map(|l| l.price),
+            best_ask: book.best_ask().map(|l| l.price),
+            mark: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PegConfig {
+    pub direction: DirectionEnum,
+    pub instrument_name: String,
+    pub amount: Decimal,
+    pub reference: PegReference,
+    /// Distance from the reference price, away from the touch for positive values.
+    pub offset_bps: Decimal,
+    /// How far the live price may drift from the pegged price before it is amended.
+    pub tolerance_bps: Decimal,
+    /// Least time between two amends or inserts.
+    pub min_amend_interval: Duration,
+    /// Largest absolute position the order may take us to; the order shrinks, and is
+    /// pulled, as the position approaches it.
+    pub max_position: Option<Decimal>,
+    /// Keeps the order post-only and never prices it through the far side of the touch.
+    pub post_only: bool,
+    pub label: Option<String>,
+}
+
+impl PegConfig {
+    pub fn new(
+        direction: DirectionEnum,
+        instrument_name: impl Into<String>,
+        amount: Decimal,
+        reference: PegReference,
+    ) -> Self {
+        PegConfig {
+            direction,
+            instrument_name: instrument_name.into(),
+            amount,
+            reference,
+            offset_bps: Decimal::ZERO,
+            tolerance_bps: Decimal::ZERO,
+            min_amend_interval: Duration::ZERO,
+            max_position: None,
+            post_only: true,
+            label: None,
+        }
+    }
+
+    pub fn offset_bps(mut self, bps: Decimal) -> Self {
+        self.offset_bps = bps;
+        self
+    }
+
+    pub fn tolerance_bps(mut self, bps: Decimal) -> Self {
+        self.tolerance_bps = bps;
+        self
+    }
+
+    pub fn min_amend_interval(mut self, interval: Duration) -> Self {
+        self.min_amend_interval = interval;
+        self
+    }
+
+    pub fn max_position(mut self, limit: Decimal) -> Self {
+        self.max_position = Some(limit);
+        self
+    }
+
+    /// Allow the order to take liquidity when the peg crosses the book.
+    pub fn allow_taking(mut self) -> Self {
+        self.post_only = false;
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+}
+
+/// What a [`PeggedOrder`] does about one market update.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PegAction {
+    Hold,
+    Insert {
+        price: Decimal,
+        amount: Decimal,
+    },
+    /// `amount` is the new total of the order, including what has already filled.
+    Amend {
+        price: Decimal,
+        amount: Decimal,
+    },
+    Cancel,
+}
+
+#[derive(Debug, Default)]
+struct PegState {
+    client_order_id: Option<i32>,
+    /// The insert of `client_order_id` ended without an answer; it may or may not exist.
+    unresolved: Option<InsertParams>,
+    last_action: Option<Instant>,
+}
+
+/// An order kept at a distance from the best price, mid or mark.
+///
+/// Each ticker or book update computes the pegged price and the size the position still
+/// allows; the live order is amended when its price drifts out of the tolerance band or
+/// its size no longer matches, at most once per `min_amend_interval`. An amend the
+/// exchange refuses falls back to cancelling and inserting a fresh order. Order state
+/// comes from the [`OrderManager`]'s `session.orders` feed, so a filled order is replaced
+/// by the next update. An order whose insert went unanswered is only replaced once the
+/// exchange confirms it isn't live.
+#[derive(Clone)]
+pub struct PeggedOrder {
+    config: Arc<PegConfig>,
+    spec: TickSpec,
+    orders: OrderManager,
+    portfolio: Option<PortfolioTracker>,
+    state: Arc<tokio::sync::Mutex<PegState>>,
+    last_quote: Arc<Mutex<Option<PegQuote>>>,
+    stopped: Arc<AtomicBool>,
+    subscription: Arc<Mutex<Option<SubscriptionHandle>>>,
+}
+
+impl PeggedOrder {
+    pub fn new(config: PegConfig, spec: TickSpec, orders: OrderManager) -> Self {
+        PeggedOrder {
+            config: Arc::new(config),
+            spec,
+            orders,
+            portfolio: None,
+            state: Arc::new(tokio::sync::Mutex::new(PegState::default())),
+            last_quote: Arc::new(Mutex::new(None)),
+            stopped: Arc::new(AtomicBool::new(false)),
+            subscription: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Takes the position for `max_position` from `portfolio`.
+    pub fn with_portfolio(mut self, portfolio: PortfolioTracker) -> Self {
+        self.portfolio = Some(portfolio);
+        self
+    }
+
+    /// Follows `ticker.<instrument>.<delay>`. `orders` must be started.
+    pub async fn start(self, client: Arc<WsClient>, delay: Delay) -> Result<Self, Error> {
+        let handler = self.clone();
+        let peg_client = client.clone();
+        let channel = format!("ticker.{}.{delay}", self.config.instrument_name);
+        let handle = client
+            .subscribe_shared_channel(
+                RequestScope::Public,
+                channel,
+                move |msg: TickerNotification| {
+                    let peg = handler.clone();
+                    let client = peg_client.clone();
+                    tokio::spawn(async move {
+                        peg.update(&client, PegQuote::from_ticker(&msg.notification))
+                            .await;
+                    });
+                },
+            )
+            .await?;
+        *self.subscription.lock().unwrap() = Some(handle);
+        info!(
+            "Pegging {} {} to {:?}",
+            self.config.direction, self.config.instrument_name, self.config.reference
+        );
+        Ok(self)
+    }
+
+    pub fn config(&self) -> &PegConfig {
+        &self.config
+    }
+
+    /// The pegged price for `quote`, on the tick grid away from the touch.
+    pub fn target_price(&self, quote: &PegQuote) -> Option<Decimal> {
+        let config = &self.config;
+        let buy = config.direction == DirectionEnum::Buy;
+        let reference = match config.reference {
+            PegReference::Best if buy => quote.best_bid?,
+            PegReference::Best => quote.best_ask?,
+            PegReference::Mid => (quote.best_bid? + quote.best_ask?) / Decimal::TWO,
+            PegReference::Mark => quote.mark?,
+        };
+        let offset = reference * config.offset_bps / BPS;
+        let mut price = if buy {
+            reference - offset
+        } else {
+            reference + offset
+        };
+        if config.post_only {
+            let tick = self.spec.tick_size();
+            match (buy, quote.best_ask, quote.best_bid) {
+                (true, Some(ask), _) => price = price.min(ask - tick),
+                (false, _, Some(bid)) => price = price.max(bid + tick),
+                _ => {}
+            }
+        }
+        let price = self.spec.passive_price(price, config.direction).value();
+        (price > Decimal::ZERO).then_some(price)
+    }
+
+    /// Amount the order should have open, given the position and `max_position`.
+    pub fn target_amount(&self) -> Decimal {
+        let config = &self.config;
+        let mut amount = config.amount;
+        if let Some(limit) = config.max_position {
+            let position = self
+                .portfolio
+                .as_ref()
+                .map_or(Decimal::ZERO, |p| p.position(&config.instrument_name));
+            let room = match config.direction {
+                DirectionEnum::Buy => limit - position,
+                DirectionEnum::Sell => limit + position,
+            };
+            amount = amount.min(room.max(Decimal::ZERO));
+        }
+        let amount = self.spec.amount(amount, Rounding::Down);
+        if self.spec.is_tradable(amount) {
+            amount.value()
+        } else {
+            Decimal::ZERO
+        }
+    }
+
+    /// What to do about `live`, the order currently working, given `quote`.
+    pub fn decide(&self, live: Option<&OrderStatus>, quote: &PegQuote) -> PegAction {
+        let amount = self.target_amount();
+        let Some(price) = self.target_price(quote).filter(|_| !amount.is_zero()) else {
+            return match live {
+                Some(_) => PegAction::Cancel,
+                None => PegAction::Hold,
+            };
+        };
+        let Some(live) = live else {
+            return PegAction::Insert { price, amount };
+        };
+        let live_price = live.price.unwrap_or_default();
+        let drift_bps = (live_price - price).abs() * BPS / price;
+        let price_ok = drift_bps <= self.config.tolerance_bps;
+        if price_ok && live.remaining_amount == amount {
+            return PegAction::Hold;
+        }
+        PegAction::Amend {
+            price: if price_ok { live_price } else { price },
+            amount: live.filled_amount + amount,
+        }
+    }
+
+    /// Re-pegs against `quote`. Updates arriving while a previous one is still being sent,
+    /// or within `min_amend_interval` of the last change, are only remembered; the next
+    /// update after that acts on the latest prices.
+    pub async fn update(&self, client: &WsClient, quote: PegQuote) {
+        *self.last_quote.lock().unwrap() = Some(quote);
+        if self.stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let Ok(mut state) = self.state.try_lock() else {
+            return;
+        };
+        if state
+            .last_action
+            .is_some_and(|t| t.elapsed() < self.config.min_amend_interval)
+        {
+            return;
+        }
+        let Some(quote) = *self.last_quote.lock().unwrap() else {
+            return;
+        };
+        let mut live = state
+            .client_order_id
+            .and_then(|id| self.orders.get_by_client_order_id(Decimal::from(id)));
+        if live.is_none()
+            && let Some(params) = &state.unresolved
+        {
+            // Only forget an unresolved order once the exchange confirms it doesn't exist.
+            let id = params.client_order_id.unwrap_or_default();
+            match self.orders.resolve(client, params).await {
+                Ok(Some(SubmittedOrder::Live(order))) => live = Some(order),
+                Ok(_) => debug!("Unresolved pegged order {id} is not live"),
+                Err(e) => {
+                    warn!("Pegged order {id} still unresolved: {e}");
+                    return;
+                }
+            }
+            state.unresolved = None;
+        }
+        if live.is_none() {
+            state.client_order_id = None;
+        }
+        let action = self.decide(live.as_ref(), &quote);
+        match action {
+            PegAction::Hold => return,
+            PegAction::Insert { price, amount } => {
+                self.insert(client, &mut state, price, amount).await
+            }
+            PegAction::Amend { price, amount } => {
+                let id = state.client_order_id.unwrap_or_default();
+                let params = AmendParams {
+                    client_order_id: Some(id),
+                    price,
+                    amount,
+                    ..Default::default()
+                };
+                match client.rpc().trading().amend(params).await {
+                    Ok(order) => {
+                        debug!("Amended pegged order {id} to {amount} @ {price}");
+                        self.orders.apply(order);
+                    }
+                    Err(e) => {
+                        warn!("Amend of pegged order {id} failed ({e}), replacing it");
+                        self.cancel_live(client, &mut state).await;
+                        let remaining = amount - live.map_or(Decimal::ZERO, |o| o.filled_amount);
+                        self.insert(client, &mut state, price, remaining).await;
+                    }
+                }
+            }
+            PegAction::Cancel => self.cancel_live(client, &mut state).await,
+        }
+        state.last_action = Some(Instant::now());
+    }
+
+    /// Stops pegging, unsubscribes from the ticker and cancels the live order.
+    pub async fn stop(&self, client: &WsClient) {
+        self.stopped.store(true, Ordering::SeqCst);
+        let subscription = self.subscription.lock().unwrap().take();
+        if let Err(e) = client.release(subscription).await {
+            warn!("Failed to unsubscribe pegged order ticker: {e}");
+        }
+        let mut state = self.state.lock().await;
+        self.cancel_live(client, &mut state).await;
+    }
+
+    /// The order currently working, if any.
+    pub fn live_order(&self) -> Option<OrderStatus> {
+        let id = self.state.try_lock().ok()?.client_order_id?;
+        self.orders.get_by_client_order_id(Decimal::from(id))
+    }
+
+    async fn insert(
+        &self,
+        client: &WsClient,
+        state: &mut PegState,
+        price: Decimal,
+        amount: Decimal,
+    ) {
+        let config = &self.config;
+        let client_order_id = client.next_client_order_id();
+        let params = InsertParams {
+            direction: config.direction,
+            instrument_name: Some(config.instrument_name.clone()),
+            amount,
+            price: Some(price),
+            order_type: Some(OrderTypeEnum::Limit),
+            post_only: config.post_only.then_some(true),
+            label: config.label.clone(),
+            client_order_id: Some(client_order_id),
+            ..Default::default()
+        };
+        match self.orders.submit(client, params.clone()).await {
+            Ok(_) => {
+                state.client_order_id = Some(client_order_id);
+                state.unresolved = None;
+                debug!("Pegged order {client_order_id} placed: {amount} @ {price}");
+            }
+            Err(SubmitError::Rejected(e)) => {
+                warn!("Pegged order on {} rejected: {e:?}", config.instrument_name);
+            }
+            Err(e) => {
+                // It may be live; track it so the next update amends or cancels it.
+                warn!("Pegged order on {} unresolved: {e}", config.instrument_name);
+                state.client_order_id = Some(client_order_id);
+                state.unresolved = Some(params);
+            }
+        }
+    }
+
+    async fn cancel_live(&self, client: &WsClient, state: &mut PegState) {
+        let Some(id) = state.client_order_id.take() else {
+            return;
+        };
+        state.unresolved = None;
+        let params = CancelParams {
+            client_order_id: Some(id),
+            order_id: None,
+        };
+        if let Err(e) = client.rpc().trading().cancel(params).await {
+            debug!("Cancel of pegged order {id} failed: {e}");
+        }
+    }
+}
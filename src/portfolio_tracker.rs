@@ -0,0 +1,377 @@
+use std::{
+    collections::HashMap,
+    ops::{Add, AddAssign},
+    sync::{Arc, Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info, warn};
+use rust_decimal::Decimal;
+use tokio::task::JoinHandle;
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    manual_models::instrument_id::InstrumentId,
+    models::{
+        AccountPortfolioNotification, DirectionEnum, IndexParams, OrderFill, OrderStatus,
+        PortfolioEntry,
+    },
+    order_manager::{CallbackId, OrderManager},
+    types::{Error, RequestScope},
+    ws_client::WsClient,
+};
+
+/// Index whose timestamp is read as the exchange clock when the portfolio is loaded.
+const CLOCK_INDEX: &str = "BTCUSD";
+
+/// Session P&L of a position or a group of positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pnl {
+    pub realised: Decimal,
+    pub unrealised: Decimal,
+    /// Unrealised perpetual funding.
+    pub funding: Decimal,
+}
+
+impl Pnl {
+    pub fn total(&self) -> Decimal {
+        self.realised + self.unrealised + self.funding
+    }
+}
+
+impl Add for Pnl {
+    type Output = Pnl;
+
+    fn add(self, other: Pnl) -> Pnl {
+        Pnl {
+            realised: self.realised + other.realised,
+            unrealised: self.unrealised + other.unrealised,
+            funding: self.funding + other.funding,
+        }
+    }
+}
+
+impl AddAssign for Pnl {
+    fn add_assign(&mut self, other: Pnl) {
+        *self = *self + other;
+    }
+}
+
+/// Fills seen on the orders feed that `account.portfolio` hasn't reported yet.
+#[derive(Clone, Copy, Debug, Default)]
+struct FillOverlay {
+    /// Signed amount, negative for sells.
+    amount: Decimal,
+    /// Signed `price * amount` of the fills.
+    cost: Decimal,
+}
+
+#[derive(Debug, Default)]
+struct PortfolioState {
+    entries: HashMap<InstrumentId, PortfolioEntry>,
+    overlay: HashMap<InstrumentId, FillOverlay>,
+    /// When the last full snapshot arrived, in exchange seconds since the epoch.
+    snapshot_at: Decimal,
+    /// When each instrument's last portfolio line arrived, in exchange time.
+    reported_at: HashMap<InstrumentId, Decimal>,
+    /// Exchange clock minus local clock, in seconds.
+    clock_offset: Decimal,
+}
+
+impl PortfolioState {
+    /// Current time on the exchange clock, as far as it is known.
+    fn exchange_now(&self) -> Decimal {
+        now_secs() + self.clock_offset
+    }
+
+    /// Moves the clock estimate forward to `time`, an exchange timestamp that has passed.
+    fn observe(&mut self, time: Decimal) {
+        self.clock_offset = self.clock_offset.max(time - now_secs());
+    }
+
+    /// Fills up to this time are already part of the instrument's portfolio line.
+    fn cutoff(&self, id: &InstrumentId) -> Decimal {
+        self.reported_at
+            .get(id)
+            .map_or(self.snapshot_at, |at| (*at).max(self.snapshot_at))
+    }
+}
+
+/// Positions kept current by `account.portfolio`, with live fills layered on top.
+///
+/// The channel sends a full snapshot first and then only the lines that changed.
+/// Fills reported by an [`OrderManager`] move the position immediately; they are
+/// dropped from the overlay once the next portfolio line for that instrument arrives.
+/// A fill that arrives after a line but was executed before it is already in that
+/// line and is ignored. Portfolio lines carry no timestamp, so their arrival is stamped
+/// on an estimate of the exchange clock: the `BTCUSD` index timestamp read when the
+/// portfolio is loaded, moved forward by the execution time of every fill seen. After
+/// every login the portfolio is reloaded with `private/portfolio`.
+#[derive(Clone, Default)]
+pub struct PortfolioTracker {
+    state: Arc<RwLock<PortfolioState>>,
+    subscription: Arc<Mutex<Option<SubscriptionHandle>>>,
+    login_task: Arc<Mutex<Option<JoinHandle<()>>>>,
+    fill_callback: Arc<Mutex<Option<(OrderManager, CallbackId)>>>,
+}
+
+impl PortfolioTracker {
+    /// Creates an empty tracker without subscribing to anything.
+    pub fn new() -> Self {
+        PortfolioTracker::default()
+    }
+
+    /// Loads the portfolio, subscribes to `account.portfolio` and, if given, follows the
+    /// fills of `orders`. The client must already be logged in.
+    pub async fn start(
+        client: Arc<WsClient>,
+        orders: Option<&OrderManager>,
+    ) -> Result<Self, Error> {
+        let tracker = PortfolioTracker::new();
+        tracker.refresh(&client).await?;
+
+        let handler = tracker.clone();
+        let subscription = client
+            .subscribe_shared_channel(
+                RequestScope::Private,
+                "account.portfolio".to_string(),
+                move |msg: AccountPortfolioNotification| handler.apply(msg.notification),
+            )
+            .await?;
+        *tracker.subscription.lock().unwrap() = Some(subscription);
+
+        if let Some(orders) = orders {
+            let handler = tracker.clone();
+            let id = orders.on_fill(move |order, fill| handler.apply_fill(order, fill));
+            *tracker.fill_callback.lock().unwrap() = Some((orders.clone(), id));
+        }
+
+        let worker = tracker.clone();
+        let mut login_rx = client.login_status();
+        login_rx.mark_unchanged();
+        let login_task = tokio::spawn(async move {
+            while login_rx.changed().await.is_ok() {
+                if !*login_rx.borrow_and_update() {
+                    continue;
+                }
+                if let Err(e) = worker.refresh(&client).await {
+                    warn!("Failed to resync portfolio after login: {e}");
+                }
+            }
+        });
+        *tracker.login_task.lock().unwrap() = Some(login_task);
+        info!(
+            "Portfolio tracker started with {} positions",
+            tracker.state.read().unwrap().entries.len()
+        );
+        Ok(tracker)
+    }
+
+    /// Stops reloading after logins, unsubscribes from `account.portfolio` and stops
+    /// following fills. The positions are kept.
+    pub async fn stop(&self, client: &WsClient) {
+        if let Some(task) = self.login_task.lock().unwrap().take() {
+            task.abort();
+        }
+        if let Some((orders, id)) = self.fill_callback.lock().unwrap().take() {
+            orders.remove_callback(id);
+        }
+        let subscription = self.subscription.lock().unwrap().take();
+        if let Err(e) = client.release(subscription).await {
+            warn!("Failed to unsubscribe account.portfolio: {e}");
+        }
+    }
+
+    /// Reloads the whole portfolio with `private/portfolio` and reads the exchange clock.
+    pub async fn refresh(&self, client: &WsClient) -> Result<(), Error> {
+        let entries = client.rpc().accounting().portfolio().await?;
+        let index = client
+            .rpc()
+            .market_data()
+            .index(IndexParams::new(CLOCK_INDEX.to_string()))
+            .await;
+        match index {
+            Ok(index) => self.state.write().unwrap().clock_offset = index.timestamp - now_secs(),
+            Err(e) => warn!("Failed to read the exchange clock, keeping the last estimate: {e}"),
+        }
+        self.replace_all(entries);
+        Ok(())
+    }
+
+    /// Replaces every position with `entries` and drops the fill overlay.
+    pub fn replace_all(&self, entries: Vec<PortfolioEntry>) {
+        let mut state = self.state.write().unwrap();
+        state.entries.clear();
+        state.overlay.clear();
+        state.reported_at.clear();
+        state.snapshot_at = state.exchange_now();
+        for (id, entry) in entries.into_iter().filter_map(keyed) {
+            state.entries.insert(id, entry);
+        }
+    }
+
+    /// Merges changed lines from `account.portfolio`.
+    pub fn apply(&self, entries: Vec<PortfolioEntry>) {
+        let mut state = self.state.write().unwrap();
+        let now = state.exchange_now();
+        for (id, entry) in entries.into_iter().filter_map(keyed) {
+            state.overlay.remove(&id);
+            state.reported_at.insert(id.clone(), now);
+            state.entries.insert(id, entry);
+        }
+    }
+
+    /// Moves the position by one fill of `order`, unless the portfolio line of its
+    /// instrument arrived after the fill was executed.
+    pub fn apply_fill(&self, order: &OrderStatus, fill: &OrderFill) {
+        let sign = match order.direction {
+            DirectionEnum::Buy => Decimal::ONE,
+            DirectionEnum::Sell => Decimal::NEGATIVE_ONE,
+        };
+        let (instrument_name, sign) = match (&order.instrument_name, &order.legs) {
+            (Some(name), _) => (name.as_str(), sign),
+            (None, Some(legs)) => {
+                let Some(leg) = usize::try_from(fill.leg_index)
+                    .ok()
+                    .and_then(|i| legs.get(i))
+                else {
+                    warn!("Fill {} has no matching leg", fill.trade_id);
+                    return;
+                };
+                let leg_sign = if leg.quantity.is_sign_negative() {
+                    -sign
+                } else {
+                    sign
+                };
+                (leg.instrument_name.as_str(), leg_sign)
+            }
+            (None, None) => return,
+        };
+        let Ok(id) = instrument_name.parse::<InstrumentId>() else {
+            warn!("Ignoring fill on unrecognised instrument {instrument_name}");
+            return;
+        };
+        let mut state = self.state.write().unwrap();
+        if let Some(time) = fill.time {
+            state.observe(time);
+            if time <= state.cutoff(&id) {
+                debug!("Fill {} is already in the portfolio", fill.trade_id);
+                return;
+            }
+        }
+        let overlay = state.overlay.entry(id).or_default();
+        overlay.amount += sign * fill.amount;
+        overlay.cost += sign * fill.amount * fill.price;
+    }
+
+    /// Portfolio lines as last reported by the exchange, without the fill overlay.
+    pub fn entries(&self) -> HashMap<InstrumentId, PortfolioEntry> {
+        self.state.read().unwrap().entries.clone()
+    }
+
+    pub fn entry(&self, instrument_name: &str) -> Option<PortfolioEntry> {
+        self.state
+            .read()
+            .unwrap()
+            .entries
+            .get(instrument_name)
+            .cloned()
+    }
+
+    /// Current position including fills not yet reported by `account.portfolio`.
+    pub fn position(&self, instrument_name: &str) -> Decimal {
+        let state = self.state.read().unwrap();
+        let reported = state
+            .entries
+            .get(instrument_name)
+            .and_then(|e| e.position)
+            .unwrap_or_default();
+        let pending = state
+            .overlay
+            .get(instrument_name)
+            .map_or(Decimal::ZERO, |o| o.amount);
+        reported + pending
+    }
+
+    /// All non-zero positions including pending fills.
+    pub fn positions(&self) -> HashMap<InstrumentId, Decimal> {
+        let state = self.state.read().unwrap();
+        let mut positions: HashMap<InstrumentId, Decimal> = state
+            .entries
+            .iter()
+            .map(|(id, e)| (id.clone(), e.position.unwrap_or_default()))
+            .collect();
+        for (id, overlay) in &state.overlay {
+            *positions.entry(id.clone()).or_default() += overlay.amount;
+        }
+        positions.retain(|_, p| !p.is_zero());
+        positions
+    }
+
+    /// P&L of one instrument, including pending fills as [`PortfolioTracker::position`]
+    /// does.
+    pub fn pnl(&self, instrument_name: &str) -> Pnl {
+        self.sum_pnl(|id| id.as_str() == instrument_name)
+    }
+
+    /// Summed P&L of all instruments on `base`, e.g. `"BTC"`.
+    pub fn pnl_by_underlying(&self, base: &str) -> Pnl {
+        self.sum_pnl(|id| id.base() == base)
+    }
+
+    pub fn total_pnl(&self) -> Pnl {
+        self.sum_pnl(|_| true)
+    }
+
+    /// Sums over the same instruments as [`PortfolioTracker::positions`]: every portfolio
+    /// line and every instrument with pending fills.
+    fn sum_pnl(&self, include: impl Fn(&InstrumentId) -> bool) -> Pnl {
+        let state = self.state.read().unwrap();
+        let pending_only = state
+            .overlay
+            .keys()
+            .filter(|id| !state.entries.contains_key(*id));
+        state
+            .entries
+            .keys()
+            .chain(pending_only)
+            .filter(|id| include(id))
+            .map(|id| Self::entry_pnl(state.entries.get(id), state.overlay.get(id)))
+            .fold(Pnl::default(), Add::add)
+    }
+
+    /// Exchange-reported P&L, with pending fills marked to the last mark price. Without
+    /// a portfolio line there is no mark, and pending fills count at their own price.
+    fn entry_pnl(entry: Option<&PortfolioEntry>, overlay: Option<&FillOverlay>) -> Pnl {
+        let Some(entry) = entry else {
+            return Pnl::default();
+        };
+        let pending = match (overlay, entry.mark_price) {
+            (Some(o), Some(mark)) => mark * o.amount - o.cost,
+            _ => Decimal::ZERO,
+        };
+        Pnl {
+            realised: entry.realised_pnl.unwrap_or_default(),
+            unrealised: entry.unrealised_pnl.unwrap_or_default() + pending,
+            funding: entry.unrealised_perpetual_funding.unwrap_or_default(),
+        }
+    }
+}
+
+fn now_secs() -> Decimal {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Decimal::from_i128_with_scale(now.as_millis() as i128, 3)
+}
+
+fn keyed(entry: PortfolioEntry) -> Option<(InstrumentId, PortfolioEntry)> {
+    let name = entry.instrument_name.as_deref()?;
+    match name.parse::<InstrumentId>() {
+        Ok(id) => Some((id, entry)),
+        Err(e) => {
+            warn!("Skipping portfolio line for {name}: {e}");
+            None
+        }
+    }
+}
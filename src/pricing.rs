@@ -0,0 +1,265 @@
+//! Black-76 option pricing, implied volatility and greeks.
+//!
+//! Thalex options are priced off the forward, so the model takes the forward price
+//! directly and does not discount. Volatilities are fractions (`0.5` is 50%) and times
+//! are in years of 365 days.
+
+use std::f64::consts::PI;
+
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use thiserror::Error;
+
+use crate::models::{Instrument, OptionTypeEnum, Ticker};
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 3600.0;
+const IV_TOLERANCE: f64 = 1e-10;
+const IV_MAX_ITERATIONS: usize = 100;
+const MIN_VOL: f64 = 1e-6;
+const MAX_VOL: f64 = 20.0;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum PricingError {
+    #[error("instrument is not an option")]
+    NotAnOption,
+    #[error("missing {0}")]
+    MissingField(&'static str),
+    #[error("option has expired")]
+    Expired,
+    #[error("price {0} is outside the no-arbitrage bounds")]
+    PriceOutOfBounds(f64),
+    #[error("implied volatility did not converge")]
+    NoConvergence,
+}
+
+/// Sensitivities of a single option, as plain partial derivatives of the Black-76 price.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Greeks {
+    /// d price / d forward.
+    pub delta: f64,
+    /// d delta / d forward.
+    pub gamma: f64,
+    /// d price / d vol, per 1.0 of volatility (divide by 100 for one vol point).
+    pub vega: f64,
+    /// Price change per year of elapsed time (divide by 365 for one day). Usually negative.
+    pub theta: f64,
+    /// d delta / d vol.
+    pub vanna: f64,
+}
+
+/// Inputs of the Black-76 model.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Black76 {
+    pub option_type: OptionTypeEnum,
+    pub forward: f64,
+    pub strike: f64,
+    /// Time to expiry in years.
+    pub time: f64,
+    pub vol: f64,
+}
+
+impl Black76 {
+    pub fn new(
+        option_type: OptionTypeEnum,
+        forward: f64,
+        strike: f64,
+        time: f64,
+        vol: f64,
+    ) -> Self {
+        Black76 {
+            option_type,
+            forward,
+            strike,
+            time,
+            vol,
+        }
+    }
+
+    fn d1_d2(&self) -> (f64, f64) {
+        let stdev = self.vol * self.time.sqrt();
+        let d1 = ((self.forward / self.strike).ln() + 0.5 * stdev * stdev) / stdev;
+        (d1, d1 - stdev)
+    }
+
+    fn intrinsic(&self) -> f64 {
+        match self.option_type {
+            OptionTypeEnum::Call => (self.forward - self.strike).max(0.0),
+            OptionTypeEnum::Put => (self.strike - self.forward).max(0.0),
+        }
+    }
+
+    fn expired(&self) -> bool {
+        self.time <= 0.0 || self.vol <= 0.0
+    }
+
+    pub fn price(&self) -> f64 {
+        if self.expired() {
+            return self.intrinsic();
+        }
+        let (d1, d2) = self.d1_d2();
+        match self.option_type {
+            OptionTypeEnum::Call => self.forward * norm_cdf(d1) - self.strike * norm_cdf(d2),
+            OptionTypeEnum::Put => self.strike * norm_cdf(-d2) - self.forward * norm_cdf(-d1),
+        }
+    }
+
+    pub fn greeks(&self) -> Greeks {
+        if self.expired() {
+            let itm = self.intrinsic() > 0.0;
+            let delta = match self.option_type {
+                OptionTypeEnum::Call if itm => 1.0,
+                OptionTypeEnum::Put if itm => -1.0,
+                _ => 0.0,
+            };
+            return Greeks {
+                delta,
+                ..Default::default()
+            };
+        }
+        let (d1, d2) = self.d1_d2();
+        let sqrt_t = self.time.sqrt();
+        let pdf = norm_pdf(d1);
+        let delta = match self.option_type {
+            OptionTypeEnum::Call => norm_cdf(d1),
+            OptionTypeEnum::Put => norm_cdf(d1) - 1.0,
+        };
+        Greeks {
+            delta,
+            gamma: pdf / (self.forward * self.vol * sqrt_t),
+            vega: self.forward * pdf * sqrt_t,
+            theta: -self.forward * pdf * self.vol / (2.0 * sqrt_t),
+            vanna: -pdf * d2 / self.vol,
+        }
+    }
+
+    /// Volatility at which the model reproduces `price`, holding the other inputs fixed.
+    ///
+    /// Newton steps on vega, falling back to bisection when a step leaves the bracket.
+    pub fn implied_vol(&self, price: f64) -> Result<f64, PricingError> {
+        if self.time <= 0.0 {
+            return Err(PricingError::Expired);
+        }
+        let upper_bound = match self.option_type {
+            OptionTypeEnum::Call => self.forward,
+            OptionTypeEnum::Put => self.strike,
+        };
+        if !price.is_finite() || price <= self.intrinsic() || price >= upper_bound {
+            return Err(PricingError::PriceOutOfBounds(price));
+        }
+
+        let mut model = *self;
+        let (mut low, mut high) = (MIN_VOL, MAX_VOL);
+        // Brenner-Subrahmanyam estimate for an at-the-money option.
+        model.vol = ((2.0 * PI / self.time).sqrt() * price / self.forward).clamp(low, high);
+        for _ in 0..IV_MAX_ITERATIONS {
+            let diff = model.price() - price;
+            if diff.abs() < IV_TOLERANCE {
+                return Ok(model.vol);
+            }
+            if diff > 0.0 {
+                high = model.vol;
+            } else {
+                low = model.vol;
+            }
+            let vega = model.greeks().vega;
+            let newton = model.vol - diff / vega;
+            model.vol = if vega > 0.0 && newton > low && newton < high {
+                newton
+            } else {
+                0.5 * (low + high)
+            };
+            if high - low < IV_TOLERANCE {
+                return Ok(model.vol);
+            }
+        }
+        Err(PricingError::NoConvergence)
+    }
+}
+
+/// Years between `now` and `expiration_timestamp`, both Unix seconds.
+pub fn year_fraction(now: f64, expiration_timestamp: f64) -> f64 {
+    (expiration_timestamp - now) / SECONDS_PER_YEAR
+}
+
+/// Black-76 model for `instrument` at the ticker's mark time, forward and mark IV.
+pub fn model_from_ticker(
+    instrument: &Instrument,
+    ticker: &Ticker,
+) -> Result<Black76, PricingError> {
+    let option_type = instrument.option_type.ok_or(PricingError::NotAnOption)?;
+    let strike =
+        to_f64(instrument.strike_price).ok_or(PricingError::MissingField("strike_price"))?;
+    let expiry = instrument
+        .expiration_timestamp
+        .ok_or(PricingError::MissingField("expiration_timestamp"))?;
+    let forward = to_f64(ticker.forward).ok_or(PricingError::MissingField("forward"))?;
+    let vol = to_f64(ticker.iv).ok_or(PricingError::MissingField("iv"))?;
+    let now =
+        to_f64(Some(ticker.mark_timestamp)).ok_or(PricingError::MissingField("mark_timestamp"))?;
+    let time = year_fraction(now, f64::from(expiry));
+    if time <= 0.0 {
+        return Err(PricingError::Expired);
+    }
+    Ok(Black76::new(option_type, forward, strike, time, vol))
+}
+
+/// Model price at the ticker's mark IV; should match `ticker.mark_price`.
+pub fn price(instrument: &Instrument, ticker: &Ticker) -> Result<f64, PricingError> {
+    Ok(model_from_ticker(instrument, ticker)?.price())
+}
+
+pub fn greeks(instrument: &Instrument, ticker: &Ticker) -> Result<Greeks, PricingError> {
+    Ok(model_from_ticker(instrument, ticker)?.greeks())
+}
+
+/// Implied volatility of `price` (e.g. a bid or ask) against the ticker's forward and mark time.
+pub fn implied_vol(
+    instrument: &Instrument,
+    ticker: &Ticker,
+    price: Decimal,
+) -> Result<f64, PricingError> {
+    let price = to_f64(Some(price)).ok_or(PricingError::MissingField("price"))?;
+    model_from_ticker(instrument, ticker)?.implied_vol(price)
+}
+
+fn to_f64(value: Option<Decimal>) -> Option<f64> {
+    value?.to_f64()
+}
+
+pub fn norm_pdf(x: f64) -> f64 {
+    (-0.5 * x * x).exp() / (2.0 * PI).sqrt()
+}
+
+/// Standard normal CDF, accurate to double precision (Hart's algorithm as given by
+/// West, "Better approximations to cumulative normal functions", 2005).
+pub fn norm_cdf(x: f64) -> f64 {
+    let xabs = x.abs();
+    let tail = if xabs > 37.0 {
+        0.0
+    } else {
+        let e = (-xabs * xabs / 2.0).exp();
+        if xabs < 7.071_067_811_865_47 {
+            let mut n = 3.526_249_659_989_11e-2 * xabs + 0.700_383_064_443_688;
+            n = n * xabs + 6.373_962_203_531_65;
+            n = n * xabs + 33.912_866_078_383;
+            n = n * xabs + 112.079_291_497_871;
+            n = n * xabs + 221.213_596_169_931;
+            n = n * xabs + 220.206_867_912_376;
+            let mut d = 8.838_834_764_831_84e-2 * xabs + 1.755_667_163_182_64;
+            d = d * xabs + 16.064_177_579_207;
+            d = d * xabs + 86.780_732_202_946_1;
+            d = d * xabs + 296.564_248_779_674;
+            d = d * xabs + 637.333_633_378_831;
+            d = d * xabs + 793.826_512_519_948;
+            d = d * xabs + 440.413_735_824_752;
+            e * n / d
+        } else {
+            let mut b = xabs + 0.65;
+            b = xabs + 4.0 / b;
+            b = xabs + 3.0 / b;
+            b = xabs + 2.0 / b;
+            b = xabs + 1.0 / b;
+            e / b / 2.506_628_274_631
+        }
+    };
+    if x > 0.0 { 1.0 - tail } else { tail }
+}
@@ -0,0 +1,458 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use dashmap::DashMap;
+use log::warn;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    manual_models::book::BookLevel,
+    models::{
+        DirectionEnum, DoubleSidedQuote, DoubleSidedQuoteA, DoubleSidedQuoteB,
+        DoubleSidedQuoteResult, Instrument, MassQuoteParams,
+    },
+    ticks::{Rounding, TickSpec},
+    types::ClientError,
+    ws_client::WsClient,
+};
+
+/// Most instruments `private/mass_quote` accepts in one call.
+pub const MAX_QUOTE_INSTRUMENTS: usize = 100;
+/// Most levels per side and instrument.
+pub const MAX_QUOTE_LEVELS: usize = 10;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum QuoteError {
+    #[error("instrument {0} is not in the instrument cache")]
+    UnknownInstrument(String),
+    #[error("{0} has no positive tick size and volume tick size")]
+    MissingTickSize(String),
+    #[error("{instrument_name}: {levels} {side} levels, at most {MAX_QUOTE_LEVELS} are allowed")]
+    TooManyLevels {
+        instrument_name: String,
+        side: DirectionEnum,
+        levels: usize,
+    },
+    #[error("{instrument_name}: {side} price {price} is quoted twice")]
+    DuplicatePrice {
+        instrument_name: String,
+        side: DirectionEnum,
+        price: Decimal,
+    },
+    #[error("{instrument_name}: amount {amount} exceeds the protection quote amount {limit}")]
+    AboveProtectionAmount {
+        instrument_name: String,
+        amount: Decimal,
+        limit: Decimal,
+    },
+}
+
+/// A quote side the exchange refused, attributed to the instrument it was sent for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuoteRejection {
+    /// `None` if no level of the batch matches the error's side and price, or several do.
+    pub instrument_name: Option<String>,
+    pub side: Option<DirectionEnum>,
+    pub price: Option<Decimal>,
+    pub code: Option<Decimal>,
+    pub message: Option<String>,
+}
+
+/// Outcome of a [`QuoteEngine::flush`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuoteReport {
+    /// Number of `private/mass_quote` calls made.
+    pub batches: usize,
+    /// Instruments with at least one side sent.
+    pub instruments: usize,
+    pub n_success: Decimal,
+    pub n_fail: Decimal,
+    pub rejections: Vec<QuoteRejection>,
+}
+
+/// Both sides of what we quote on an instrument, best level first.
+/// An empty side means no quotes on that side.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InstrumentQuotes {
+    pub bids: Vec<BookLevel>,
+    pub asks: Vec<BookLevel>,
+}
+
+/// What we believe the exchange has; `None` when a side's state is unknown.
+#[derive(Clone, Debug, Default)]
+struct LiveQuotes {
+    bids: Option<Vec<BookLevel>>,
+    asks: Option<Vec<BookLevel>>,
+}
+
+#[derive(Debug, Default)]
+struct QuoteState {
+    desired: BTreeMap<String, InstrumentQuotes>,
+    live: HashMap<String, LiveQuotes>,
+}
+
+/// Turns the quotes a strategy wants into the fewest `private/mass_quote` calls.
+///
+/// Strategies declare the full set of levels per instrument with
+/// [`QuoteEngine::set_quotes`]; prices are rounded away from the touch and amounts down
+/// to the instrument's ticks. [`QuoteEngine::flush`] then sends only the sides that differ
+/// from what was last sent, in batches of [`MAX_QUOTE_INSTRUMENTS`]. A resent side keeps
+/// the priority of every level whose price and amount are unchanged.
+///
+/// The engine does not see fills or protection trips; call [`QuoteEngine::reset_live`]
+/// when quotes may have been pulled by the exchange so that every side is sent again.
+#[derive(Clone, Default)]
+pub struct QuoteEngine {
+    instruments: Arc<DashMap<String, Instrument>>,
+    state: Arc<Mutex<QuoteState>>,
+    /// Largest quote amount per product, from the market maker protection config.
+    quote_limits: Arc<DashMap<String, Decimal>>,
+    flush_lock: Arc<tokio::sync::Mutex<()>>,
+    label: Option<String>,
+    post_only: Option<bool>,
+}
+
+impl QuoteEngine {
+    /// Creates an engine rounding against `instruments`, e.g. `WsClient::instruments_cache`.
+    pub fn new(instruments: Arc<DashMap<String, Instrument>>) -> Self {
+        QuoteEngine {
+            instruments,
+            ..Default::default()
+        }
+    }
+
+    pub fn for_client(client: &WsClient) -> Self {
+        QuoteEngine::new(client.instruments_cache.clone())
+    }
+
+    /// Label applied to every quote side.
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_post_only(mut self, post_only: bool) -> Self {
+        self.post_only = Some(post_only);
+        self
+    }
+
+    /// Caps every level on `product` at `quote_amount`, as configured with
+    /// `private/set_mm_protection`.
+    pub fn set_quote_limit(&self, product: impl Into<String>, quote_amount: Decimal) {
+        self.quote_limits.insert(product.into(), quote_amount);
+    }
+
+    /// Replaces the desired quotes on `instrument_name`. Levels don't need to be sorted;
+    /// levels rounding to a zero amount are dropped.
+    pub fn set_quotes(
+        &self,
+        instrument_name: &str,
+        bids: Vec<BookLevel>,
+        asks: Vec<BookLevel>,
+    ) -> Result<(), QuoteError> {
+        let quotes = InstrumentQuotes {
+            bids: self.normalise(instrument_name, DirectionEnum::Buy, bids)?,
+            asks: self.normalise(instrument_name, DirectionEnum::Sell, asks)?,
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .desired
+            .insert(instrument_name.to_string(), quotes);
+        Ok(())
+    }
+
+    /// Pulls all quotes on `instrument_name` with the next flush.
+    pub fn clear_quotes(&self, instrument_name: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .desired
+            .insert(instrument_name.to_string(), InstrumentQuotes::default());
+    }
+
+    /// Pulls every quote with the next flush.
+    pub fn clear_all(&self) {
+        let mut state = self.state.lock().unwrap();
+        let names: Vec<String> = state.live.keys().cloned().collect();
+        for quotes in state.desired.values_mut() {
+            *quotes = InstrumentQuotes::default();
+        }
+        for name in names {
+            state.desired.entry(name).or_default();
+        }
+    }
+
+    /// Desired quotes after rounding.
+    pub fn desired(&self, instrument_name: &str) -> Option<InstrumentQuotes> {
+        self.state
+            .lock()
+            .unwrap()
+            .desired
+            .get(instrument_name)
+            .cloned()
+    }
+
+    /// Forgets what was sent, so the next flush sends every side quoted so far again.
+    pub fn reset_live(&self) {
+        for live in self.state.lock().unwrap().live.values_mut() {
+            *live = LiveQuotes::default();
+        }
+    }
+
+    fn normalise(
+        &self,
+        instrument_name: &str,
+        side: DirectionEnum,
+        levels: Vec<BookLevel>,
+    ) -> Result<Vec<BookLevel>, QuoteError> {
+        let instrument = self
+            .instruments
+            .get(instrument_name)
+            .ok_or_else(|| QuoteError::UnknownInstrument(instrument_name.to_string()))?;
+        let spec = TickSpec::from_instrument(&instrument)
+            .map_err(|_| QuoteError::MissingTickSize(instrument_name.to_string()))?;
+        let limit = instrument
+            .product
+            .as_deref()
+            .and_then(|p| self.quote_limits.get(p).map(|l| *l));
+
+        // Bids round down and asks up, so rounding never makes a quote more aggressive.
+        let price_rounding = Rounding::passive(side);
+        let mut rounded: Vec<BookLevel> = Vec::with_capacity(levels.len());
+        for level in levels {
+            let price: Decimal = spec.price(level.price, price_rounding).into();
+            let amount: Decimal = spec.amount(level.amount, Rounding::Down).into();
+            if amount <= Decimal::ZERO {
+                continue;
+            }
+            if let Some(limit) = limit
+                && amount > limit
+            {
+                return Err(QuoteError::AboveProtectionAmount {
+                    instrument_name: instrument_name.to_string(),
+                    amount,
+                    limit,
+                });
+            }
+            if rounded.iter().any(|l| l.price == price) {
+                return Err(QuoteError::DuplicatePrice {
+                    instrument_name: instrument_name.to_string(),
+                    side,
+                    price,
+                });
+            }
+            rounded.push(BookLevel::new(price, amount));
+        }
+        if rounded.len() > MAX_QUOTE_LEVELS {
+            return Err(QuoteError::TooManyLevels {
+                instrument_name: instrument_name.to_string(),
+                side,
+                levels: rounded.len(),
+            });
+        }
+        match side {
+            DirectionEnum::Buy => rounded.sort_by_key(|l| std::cmp::Reverse(l.price)),
+            DirectionEnum::Sell => rounded.sort_by_key(|l| l.price),
+        }
+        Ok(rounded)
+    }
+
+    /// The `private/mass_quote` calls that would bring the exchange to the desired state.
+    pub fn pending_batches(&self) -> Vec<MassQuoteParams> {
+        let state = self.state.lock().unwrap();
+        let mut quotes = Vec::new();
+        for (name, desired) in &state.desired {
+            let live = state.live.get(name);
+            // Sides never quoted need no removal; sides in an unknown state are resent.
+            let send = |wanted: &Vec<BookLevel>, live: Option<&Option<Vec<BookLevel>>>| match live {
+                None => !wanted.is_empty(),
+                Some(Some(live)) => live != wanted,
+                Some(None) => true,
+            };
+            let send_bids = send(&desired.bids, live.map(|l| &l.bids));
+            let send_asks = send(&desired.asks, live.map(|l| &l.asks));
+            if !send_bids && !send_asks {
+                continue;
+            }
+            let mut quote = DoubleSidedQuote::new(name.clone());
+            if send_bids {
+                quote.b = Some(DoubleSidedQuoteB::SingleSidedMultiLevelQuote(
+                    desired.bids.clone(),
+                ));
+            }
+            if send_asks {
+                quote.a = Some(DoubleSidedQuoteA::SingleSidedMultiLevelQuote(
+                    desired.asks.clone(),
+                ));
+            }
+            quotes.push(quote);
+        }
+        quotes
+            .chunks(MAX_QUOTE_INSTRUMENTS)
+            .map(|chunk| MassQuoteParams {
+                label: self.label.clone(),
+                post_only: self.post_only,
+                ..MassQuoteParams::new(chunk.to_vec())
+            })
+            .collect()
+    }
+
+    /// Records the sides of `batch` as live. [`QuoteEngine::flush`] does this for every
+    /// batch the exchange accepted.
+    pub fn mark_live(&self, batch: &MassQuoteParams) {
+        self.update_live(batch, |sent| Some(sent.clone()));
+    }
+
+    /// Records the sides of `batch` as unknown, so they are sent again.
+    fn mark_unknown(&self, batch: &MassQuoteParams) {
+        self.update_live(batch, |_| None);
+    }
+
+    fn update_live(
+        &self,
+        batch: &MassQuoteParams,
+        update: impl Fn(&Vec<BookLevel>) -> Option<Vec<BookLevel>>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        for quote in &batch.quotes {
+            let live = state.live.entry(quote.i.clone()).or_default();
+            if let Some(bids) = quote.b.as_ref().and_then(bid_levels) {
+                live.bids = update(bids);
+            }
+            if let Some(asks) = quote.a.as_ref().and_then(ask_levels) {
+                live.asks = update(asks);
+            }
+        }
+        state.live.retain(|_, l| {
+            !(l.bids.as_ref().is_some_and(Vec::is_empty)
+                && l.asks.as_ref().is_some_and(Vec::is_empty))
+        });
+    }
+
+    /// Sends every pending batch. A batch that fails outright leaves its sides unknown,
+    /// so the next flush sends them again; the error is returned after the remaining
+    /// batches have been tried. Sides the exchange rejected are left unknown as well.
+    pub async fn flush(&self, client: &WsClient) -> Result<QuoteReport, ClientError> {
+        let _guard = self.flush_lock.lock().await;
+        let mut report = QuoteReport::default();
+        let mut failure = None;
+        for batch in self.pending_batches() {
+            report.batches += 1;
+            report.instruments += batch.quotes.len();
+            match client.rpc().mm().mass_quote(batch.clone()).await {
+                Ok(result) => {
+                    let rejections = attribute_errors(&batch, &result);
+                    self.mark_live(&batch);
+                    self.mark_unknown(&rejected_sides(&batch, &rejections));
+                    report.n_success += result.n_success;
+                    report.n_fail += result.n_fail;
+                    report.rejections.extend(rejections);
+                }
+                Err(e) => {
+                    warn!(
+                        "mass_quote for {} instruments failed: {e}",
+                        batch.quotes.len()
+                    );
+                    self.mark_unknown(&batch);
+                    failure = Some(e);
+                }
+            }
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(report),
+        }
+    }
+}
+
+fn bid_levels(side: &DoubleSidedQuoteB) -> Option<&Vec<BookLevel>> {
+    match side {
+        DoubleSidedQuoteB::SingleSidedMultiLevelQuote(levels) => Some(levels),
+        DoubleSidedQuoteB::SingleSidedSingleLevelQuote(_) => None,
+    }
+}
+
+fn ask_levels(side: &DoubleSidedQuoteA) -> Option<&Vec<BookLevel>> {
+    match side {
+        DoubleSidedQuoteA::SingleSidedMultiLevelQuote(levels) => Some(levels),
+        DoubleSidedQuoteA::SingleSidedSingleLevelQuote(_) => None,
+    }
+}
+
+/// The sides of `batch` named by `rejections`, or the whole batch if a rejection could
+/// not be attributed to a single instrument.
+fn rejected_sides(batch: &MassQuoteParams, rejections: &[QuoteRejection]) -> MassQuoteParams {
+    if rejections.iter().any(|r| r.instrument_name.is_none()) {
+        return batch.clone();
+    }
+    let quotes = batch
+        .quotes
+        .iter()
+        .filter_map(|quote| {
+            let sides: Vec<_> = rejections
+                .iter()
+                .filter(|r| r.instrument_name.as_ref() == Some(&quote.i))
+                .map(|r| r.side)
+                .collect();
+            if sides.is_empty() {
+                return None;
+            }
+            let bid = sides.iter().any(|s| *s != Some(DirectionEnum::Sell));
+            let ask = sides.iter().any(|s| *s != Some(DirectionEnum::Buy));
+            Some(DoubleSidedQuote {
+                b: quote.b.clone().filter(|_| bid),
+                a: quote.a.clone().filter(|_| ask),
+                ..quote.clone()
+            })
+        })
+        .collect();
+    MassQuoteParams {
+        quotes,
+        ..batch.clone()
+    }
+}
+
+/// Matches the errors of a `private/mass_quote` result to the instruments of `batch` by
+/// side and price, the only location the exchange reports.
+pub fn attribute_errors(
+    batch: &MassQuoteParams,
+    result: &DoubleSidedQuoteResult,
+) -> Vec<QuoteRejection> {
+    result
+        .errors
+        .iter()
+        .map(|error| {
+            let side = match error.side.as_deref() {
+                Some("b" | "bid" | "buy") => Some(DirectionEnum::Buy),
+                Some("a" | "ask" | "sell") => Some(DirectionEnum::Sell),
+                _ => None,
+            };
+            let quotes_level = |levels: Option<&Vec<BookLevel>>| {
+                levels.is_some_and(|levels| levels.iter().any(|l| Some(l.price) == error.price))
+            };
+            let mut owners = batch.quotes.iter().filter(|q| {
+                let bid = side != Some(DirectionEnum::Sell)
+                    && quotes_level(q.b.as_ref().and_then(bid_levels));
+                let ask = side != Some(DirectionEnum::Buy)
+                    && quotes_level(q.a.as_ref().and_then(ask_levels));
+                bid || ask
+            });
+            let instrument_name = match (owners.next(), owners.next()) {
+                (Some(owner), None) => Some(owner.i.clone()),
+                _ if batch.quotes.len() == 1 => Some(batch.quotes[0].i.clone()),
+                _ => None,
+            };
+            QuoteRejection {
+                instrument_name,
+                side,
+                price: error.price,
+                code: error.code,
+                message: error.message.clone(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,593 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use log::{error, info, warn};
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{
+    channels::batch::SubscriptionHandle,
+    manual_models::{book::BookLevel, instrument_id::InstrumentId},
+    models::{
+        AccountSummary, AccountSummaryNotification, AmendParams, CancelMassQuoteParams,
+        CancelParams, DirectionEnum, DoubleSidedQuoteA, DoubleSidedQuoteB, DoubleSidedQuoteResult,
+        InsertParams, MassQuoteParams, OrderStatus, OrderTypeEnum,
+    },
+    order_manager::{OrderManager, SubmitError, SubmittedOrder},
+    portfolio_tracker::PortfolioTracker,
+    types::{ClientError, Error, RequestScope},
+    ws_client::WsClient,
+};
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Why the [`RiskGuard`] refused to send a request.
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum RiskRejection {
+    #[error("kill switch is engaged")]
+    KillSwitch,
+    #[error("position in {instrument_name} would reach {projected}, limit {limit}")]
+    PositionLimit {
+        instrument_name: String,
+        projected: Decimal,
+        limit: Decimal,
+    },
+    #[error("position on {underlying} would reach {projected}, limit {limit}")]
+    UnderlyingLimit {
+        underlying: String,
+        projected: Decimal,
+        limit: Decimal,
+    },
+    #[error("order notional {notional} is above {limit}")]
+    OrderNotional { notional: Decimal, limit: Decimal },
+    #[error("{open} orders are open, limit {limit}")]
+    OpenOrders { open: usize, limit: usize },
+    /// `max_open_orders` is set but no [`OrderManager`] is attached to count open orders.
+    #[error("open order limit {limit} needs an attached OrderManager")]
+    OpenOrdersUntracked { limit: usize },
+    #[error("more than {limit} orders in the last second")]
+    RateLimited { limit: usize },
+    #[error("price {price} is more than {band_bps} bps away from mark {mark}")]
+    PriceBand {
+        price: Decimal,
+        mark: Decimal,
+        band_bps: Decimal,
+    },
+    #[error("no mark price known for {0}")]
+    MissingMark(String),
+    #[error("session loss {loss} has reached the limit {limit}")]
+    SessionLoss { loss: Decimal, limit: Decimal },
+}
+
+#[derive(Debug, Error)]
+pub enum RiskError {
+    #[error("rejected by risk guard: {0}")]
+    Rejected(#[from] RiskRejection),
+    #[error(transparent)]
+    Client(#[from] ClientError),
+    #[error(transparent)]
+    Submit(#[from] SubmitError),
+}
+
+/// Limits enforced by a [`RiskGuard`]. Everything is unlimited by default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RiskLimits {
+    /// Largest absolute position per instrument name.
+    pub max_position: HashMap<String, Decimal>,
+    /// Largest absolute net amount across all instruments of an underlying, keyed by
+    /// base currency (`BTC`). Options count by amount, not by delta.
+    pub max_underlying_position: HashMap<String, Decimal>,
+    /// Largest `price * amount` of a single order or quote level.
+    pub max_order_notional: Option<Decimal>,
+    /// Open orders allowed before new inserts are refused. Needs an [`OrderManager`]
+    /// attached with [`RiskGuard::with_orders`]; without one every insert is refused.
+    pub max_open_orders: Option<usize>,
+    pub max_orders_per_second: Option<usize>,
+    /// Largest distance of a limit price from the mark price, in basis points.
+    pub price_band_bps: Option<Decimal>,
+    /// Loss of the exchange session, realised plus unrealised P&L from the account summary,
+    /// at which new risk is refused.
+    pub max_session_loss: Option<Decimal>,
+}
+
+impl RiskLimits {
+    pub fn max_position(mut self, instrument_name: impl Into<String>, limit: Decimal) -> Self {
+        self.max_position.insert(instrument_name.into(), limit);
+        self
+    }
+
+    pub fn max_underlying_position(mut self, base: impl Into<String>, limit: Decimal) -> Self {
+        self.max_underlying_position.insert(base.into(), limit);
+        self
+    }
+
+    pub fn max_order_notional(mut self, limit: Decimal) -> Self {
+        self.max_order_notional = Some(limit);
+        self
+    }
+
+    pub fn max_open_orders(mut self, limit: usize) -> Self {
+        self.max_open_orders = Some(limit);
+        self
+    }
+
+    pub fn max_orders_per_second(mut self, limit: usize) -> Self {
+        self.max_orders_per_second = Some(limit);
+        self
+    }
+
+    pub fn price_band_bps(mut self, bps: Decimal) -> Self {
+        self.price_band_bps = Some(bps);
+        self
+    }
+
+    pub fn max_session_loss(mut self, limit: Decimal) -> Self {
+        self.max_session_loss = Some(limit);
+        self
+    }
+}
+
+/// Pre-trade checks in front of `private/insert`, `private/amend` and `private/mass_quote`.
+///
+/// Positions come from a [`PortfolioTracker`]; with an [`OrderManager`] attached, resting
+/// orders on the same side count towards position limits as if they had filled. Orders
+/// that only shrink a position pass the position and session loss limits. Mark prices are
+/// taken from [`RiskGuard::update_mark`] or else the portfolio; with a price band or a
+/// market order notional limit set, an order without a known mark is refused.
+///
+/// [`RiskGuard::kill`] pulls everything and refuses all new orders and quotes until
+/// [`RiskGuard::reset_kill_switch`]. Cancels always pass.
+#[derive(Clone)]
+pub struct RiskGuard {
+    limits: Arc<RwLock<RiskLimits>>,
+    portfolio: PortfolioTracker,
+    orders: Option<OrderManager>,
+    marks: Arc<DashMap<String, Decimal>>,
+    sent: Arc<Mutex<VecDeque<Instant>>>,
+    session_pnl: Arc<RwLock<Option<Decimal>>>,
+    killed: Arc<AtomicBool>,
+    subscription: Arc<Mutex<Option<SubscriptionHandle>>>,
+}
+
+impl RiskGuard {
+    pub fn new(limits: RiskLimits, portfolio: PortfolioTracker) -> Self {
+        RiskGuard {
+            limits: Arc::new(RwLock::new(limits)),
+            portfolio,
+            orders: None,
+            marks: Arc::new(DashMap::new()),
+            sent: Arc::new(Mutex::new(VecDeque::new())),
+            session_pnl: Arc::new(RwLock::new(None)),
+            killed: Arc::new(AtomicBool::new(false)),
+            subscription: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Counts the open orders of `orders` towards position and open order limits, and
+    /// tracks the orders sent by [`RiskGuard::insert`].
+    pub fn with_orders(mut self, orders: OrderManager) -> Self {
+        self.orders = Some(orders);
+        self
+    }
+
+    /// Subscribes to `account.summary` for the session loss limit. The client must already
+    /// be logged in.
+    pub async fn start(self, client: Arc<WsClient>) -> Result<Self, Error> {
+        let handler = self.clone();
+        let subscription = client
+            .subscribe_shared_channel(
+                RequestScope::Private,
+                "account.summary".to_string(),
+                move |msg: AccountSummaryNotification| handler.apply_summary(&msg.notification),
+            )
+            .await?;
+        *self.subscription.lock().unwrap() = Some(subscription);
+        info!("Risk guard started");
+        Ok(self)
+    }
+
+    /// Unsubscribes from `account.summary`. The last session P&L is kept and still checked.
+    pub async fn stop(&self, client: &WsClient) {
+        let subscription = self.subscription.lock().unwrap().take();
+        if let Err(e) = client.release(subscription).await {
+            warn!("Failed to unsubscribe account.summary: {e}");
+        }
+    }
+
+    pub fn limits(&self) -> RiskLimits {
+        self.limits.read().unwrap().clone()
+    }
+
+    pub fn set_limits(&self, limits: RiskLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    pub fn update_mark(&self, instrument_name: impl Into<String>, mark_price: Decimal) {
+        self.marks.insert(instrument_name.into(), mark_price);
+    }
+
+    /// Takes the session P&L, realised plus unrealised, from an account summary.
+    pub fn apply_summary(&self, summary: &AccountSummary) {
+        let pnl = summary.session_realised_pnl + summary.unrealised_pnl;
+        let previous = self.session_pnl.write().unwrap().replace(pnl);
+        if let Some(limit) = self.limits().max_session_loss
+            && -pnl >= limit
+            && previous.is_none_or(|p| -p < limit)
+        {
+            error!("Session loss {} reached the limit {limit}", -pnl);
+        }
+    }
+
+    pub fn session_pnl(&self) -> Option<Decimal> {
+        *self.session_pnl.read().unwrap()
+    }
+
+    /// Engages the kill switch and cancels every mass quote, order, conditional order and
+    /// bot of the account. All four cancels are attempted; the first failure is returned.
+    pub async fn kill(&self, client: &WsClient) -> Result<(), ClientError> {
+        self.killed.store(true, Ordering::SeqCst);
+        error!("Kill switch engaged, cancelling everything");
+        let rpc = client.rpc();
+        let results = [
+            rpc.mm()
+                .cancel_mass_quote(CancelMassQuoteParams::new())
+                .await
+                .map(|_| ()),
+            rpc.trading().cancel_all().await.map(|_| ()),
+            rpc.conditional()
+                .cancel_all_conditional_orders()
+                .await
+                .map(|_| ()),
+            rpc.bot().cancel_all_bots().await.map(|_| ()),
+        ];
+        let mut first_error = None;
+        for result in results {
+            if let Err(e) = result {
+                warn!("Kill switch cancel failed: {e}");
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    pub fn reset_kill_switch(&self) {
+        if self.killed.swap(false, Ordering::SeqCst) {
+            info!("Kill switch reset");
+        }
+    }
+
+    /// Checks and sends an order through [`OrderManager::submit`], so it is placed at most
+    /// once. Without an attached [`OrderManager`] the outcome is looked up on the exchange
+    /// only.
+    pub async fn insert(
+        &self,
+        client: &WsClient,
+        params: InsertParams,
+    ) -> Result<SubmittedOrder, RiskError> {
+        self.logged(self.check_insert(&params).and_then(|_| self.admit()))?;
+        let submitted = match &self.orders {
+            Some(orders) => orders.submit(client, params).await,
+            None => OrderManager::new().submit(client, params).await,
+        };
+        Ok(submitted?)
+    }
+
+    /// Checks an amend of an order known to the attached [`OrderManager`]; amends of
+    /// other orders only go through the kill switch and rate limit.
+    pub async fn amend(
+        &self,
+        client: &WsClient,
+        params: AmendParams,
+    ) -> Result<OrderStatus, RiskError> {
+        self.logged(self.check_amend(&params).and_then(|_| self.admit()))?;
+        Ok(client.rpc().trading().amend(params).await?)
+    }
+
+    pub async fn cancel(
+        &self,
+        client: &WsClient,
+        params: CancelParams,
+    ) -> Result<OrderStatus, RiskError> {
+        Ok(client.rpc().trading().cancel(params).await?)
+    }
+
+    pub async fn mass_quote(
+        &self,
+        client: &WsClient,
+        params: MassQuoteParams,
+    ) -> Result<DoubleSidedQuoteResult, RiskError> {
+        self.logged(self.check_mass_quote(&params).and_then(|_| self.admit()))?;
+        Ok(client.rpc().mm().mass_quote(params).await?)
+    }
+
+    pub async fn cancel_mass_quote(
+        &self,
+        client: &WsClient,
+        params: CancelMassQuoteParams,
+    ) -> Result<(), RiskError> {
+        client.rpc().mm().cancel_mass_quote(params).await?;
+        Ok(())
+    }
+
+    /// Every check of [`RiskGuard::insert`] except the rate limit, which counts sends.
+    pub fn check_insert(&self, params: &InsertParams) -> Result<(), RiskRejection> {
+        self.check_killed()?;
+        let limits = self.limits();
+        if let Some(limit) = limits.max_open_orders {
+            let orders = self
+                .orders
+                .as_ref()
+                .ok_or(RiskRejection::OpenOrdersUntracked { limit })?;
+            if orders.len() >= limit {
+                return Err(RiskRejection::OpenOrders {
+                    open: orders.len(),
+                    limit,
+                });
+            }
+        }
+        let reduce_only = params.reduce_only == Some(true);
+        match (&params.instrument_name, &params.legs) {
+            (Some(name), _) => {
+                let market = params.order_type == Some(OrderTypeEnum::Market);
+                let price = match params.price {
+                    Some(price) if !market => {
+                        self.check_band(&limits, name, price)?;
+                        Some(price)
+                    }
+                    _ => None,
+                };
+                if let Some(limit) = limits.max_order_notional {
+                    let price = match price {
+                        Some(price) => price,
+                        None => self.mark(name)?,
+                    };
+                    check_notional(price, params.amount, limit)?;
+                }
+                if !reduce_only {
+                    self.check_position(&limits, name, signed(params.direction, params.amount))?;
+                }
+            }
+            (None, Some(legs)) if !reduce_only => {
+                for leg in legs {
+                    let delta = signed(params.direction, params.amount * leg.quantity);
+                    self.check_position(&limits, &leg.instrument_name, delta)?;
+                }
+            }
+            _ => {}
+        }
+        if !reduce_only {
+            self.check_session_loss(&limits)?;
+        }
+        Ok(())
+    }
+
+    pub fn check_amend(&self, params: &AmendParams) -> Result<(), RiskRejection> {
+        self.check_killed()?;
+        let order = self.orders.as_ref().and_then(|orders| {
+            match (&params.order_id, params.client_order_id) {
+                (Some(order_id), _) => orders.get(order_id),
+                (None, Some(id)) => orders.get_by_client_order_id(Decimal::from(id)),
+                _ => None,
+            }
+        });
+        let Some(order) = order else {
+            return Ok(());
+        };
+        let Some(name) = &order.instrument_name else {
+            return Ok(());
+        };
+        let limits = self.limits();
+        self.check_band(&limits, name, params.price)?;
+        if let Some(limit) = limits.max_order_notional {
+            check_notional(params.price, params.amount, limit)?;
+        }
+        let increase = params.amount - order.amount;
+        if increase > Decimal::ZERO && order.reduce_only != Some(true) {
+            self.check_position(&limits, name, signed(order.direction, increase))?;
+            self.check_session_loss(&limits)?;
+        }
+        Ok(())
+    }
+
+    /// Checks every level of every quote, and the position each side would reach if all
+    /// of its levels filled.
+    pub fn check_mass_quote(&self, params: &MassQuoteParams) -> Result<(), RiskRejection> {
+        self.check_killed()?;
+        let limits = self.limits();
+        for quote in &params.quotes {
+            let sides = [
+                (DirectionEnum::Buy, quote.b.as_ref().map(bid_levels)),
+                (DirectionEnum::Sell, quote.a.as_ref().map(ask_levels)),
+            ];
+            for (direction, levels) in sides {
+                let levels = levels.unwrap_or_default();
+                let mut total = Decimal::ZERO;
+                for level in levels.iter().filter(|l| l.amount > Decimal::ZERO) {
+                    self.check_band(&limits, &quote.i, level.price)?;
+                    if let Some(limit) = limits.max_order_notional {
+                        check_notional(level.price, level.amount, limit)?;
+                    }
+                    total += level.amount;
+                }
+                if !total.is_zero() {
+                    self.check_position(&limits, &quote.i, signed(direction, total))?;
+                    self.check_session_loss(&limits)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn logged(&self, result: Result<(), RiskRejection>) -> Result<(), RiskRejection> {
+        if let Err(rejection) = &result {
+            warn!("Risk guard rejected request: {rejection}");
+        }
+        result
+    }
+
+    fn check_killed(&self) -> Result<(), RiskRejection> {
+        if self.is_killed() {
+            return Err(RiskRejection::KillSwitch);
+        }
+        Ok(())
+    }
+
+    /// Counts one send against the rate limit, or refuses it.
+    fn admit(&self) -> Result<(), RiskRejection> {
+        let Some(limit) = self.limits().max_orders_per_second else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut sent = self.sent.lock().unwrap();
+        while sent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= limit {
+            return Err(RiskRejection::RateLimited { limit });
+        }
+        sent.push_back(now);
+        Ok(())
+    }
+
+    fn mark(&self, instrument_name: &str) -> Result<Decimal, RiskRejection> {
+        self.marks
+            .get(instrument_name)
+            .map(|m| *m)
+            .or_else(|| self.portfolio.entry(instrument_name)?.mark_price)
+            .ok_or_else(|| RiskRejection::MissingMark(instrument_name.to_string()))
+    }
+
+    fn check_band(
+        &self,
+        limits: &RiskLimits,
+        instrument_name: &str,
+        price: Decimal,
+    ) -> Result<(), RiskRejection> {
+        let Some(band_bps) = limits.price_band_bps else {
+            return Ok(());
+        };
+        let mark = self.mark(instrument_name)?;
+        if (price - mark).abs() * BPS > band_bps * mark.abs() {
+            return Err(RiskRejection::PriceBand {
+                price,
+                mark,
+                band_bps,
+            });
+        }
+        Ok(())
+    }
+
+    /// Signed amount resting on the same side as `delta`, which could fill on top of it.
+    fn resting(&self, include: impl Fn(&str) -> bool, delta: Decimal) -> Decimal {
+        let Some(orders) = &self.orders else {
+            return Decimal::ZERO;
+        };
+        orders
+            .open_orders()
+            .iter()
+            .filter(|o| o.instrument_name.as_deref().is_some_and(&include))
+            .map(|o| signed(o.direction, o.remaining_amount))
+            .filter(|amount| amount.is_sign_negative() == delta.is_sign_negative())
+            .sum()
+    }
+
+    fn check_position(
+        &self,
+        limits: &RiskLimits,
+        instrument_name: &str,
+        delta: Decimal,
+    ) -> Result<(), RiskRejection> {
+        if let Some(&limit) = limits.max_position.get(instrument_name) {
+            let current = self.portfolio.position(instrument_name);
+            let projected = current + self.resting(|n| n == instrument_name, delta) + delta;
+            if exceeds(current, projected, limit) {
+                return Err(RiskRejection::PositionLimit {
+                    instrument_name: instrument_name.to_string(),
+                    projected,
+                    limit,
+                });
+            }
+        }
+        let Ok(id) = instrument_name.parse::<InstrumentId>() else {
+            return Ok(());
+        };
+        let base = id.base();
+        if let Some(&limit) = limits.max_underlying_position.get(base) {
+            let current: Decimal = self
+                .portfolio
+                .positions()
+                .iter()
+                .filter(|(id, _)| id.base() == base)
+                .map(|(_, p)| *p)
+                .sum();
+            let on_base = |n: &str| n.parse::<InstrumentId>().is_ok_and(|id| id.base() == base);
+            let projected = current + self.resting(on_base, delta) + delta;
+            if exceeds(current, projected, limit) {
+                return Err(RiskRejection::UnderlyingLimit {
+                    underlying: base.to_string(),
+                    projected,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_session_loss(&self, limits: &RiskLimits) -> Result<(), RiskRejection> {
+        if let (Some(limit), Some(pnl)) = (limits.max_session_loss, self.session_pnl())
+            && -pnl >= limit
+        {
+            return Err(RiskRejection::SessionLoss { loss: -pnl, limit });
+        }
+        Ok(())
+    }
+}
+
+fn signed(direction: DirectionEnum, amount: Decimal) -> Decimal {
+    match direction {
+        DirectionEnum::Buy => amount,
+        DirectionEnum::Sell => -amount,
+    }
+}
+
+/// Over the limit and further from flat than now; reducing a position is always allowed.
+fn exceeds(current: Decimal, projected: Decimal, limit: Decimal) -> bool {
+    projected.abs() > limit && projected.abs() > current.abs()
+}
+
+fn check_notional(price: Decimal, amount: Decimal, limit: Decimal) -> Result<(), RiskRejection> {
+    let notional = (price * amount).abs();
+    if notional > limit {
+        return Err(RiskRejection::OrderNotional { notional, limit });
+    }
+    Ok(())
+}
+
+fn bid_levels(side: &DoubleSidedQuoteB) -> Vec<BookLevel> {
+    match side {
+        DoubleSidedQuoteB::SingleSidedMultiLevelQuote(levels) => levels.clone(),
+        DoubleSidedQuoteB::SingleSidedSingleLevelQuote(q) => vec![BookLevel::new(q.p, q.a)],
+    }
+}
+
+fn ask_levels(side: &DoubleSidedQuoteA) -> Vec<BookLevel> {
+    match side {
+        DoubleSidedQuoteA::SingleSidedMultiLevelQuote(levels) => levels.clone(),
+        DoubleSidedQuoteA::SingleSidedSingleLevelQuote(q) => vec![BookLevel::new(q.p, q.a)],
+    }
+}
@@ -0,0 +1,583 @@
+//! A [`Strategy`] trait and a runner that feeds it events on a single task.
+//!
+//! The runner subscribes the strategy's [`Feed`]s, queues every notification, timer tick
+//! and connection change, and calls one hook at a time with `&mut self`, so strategy
+//! state needs no locking. Hooks are synchronous: orders are requested through the
+//! [`StrategyContext`] and sent in order by the runner's sender task once the hook
+//! returns; their results come back as further events.
+//!
+//! ```ignore
+//! let strategy = StrategyRunner::new(client, MyQuoter::default()).run().await?;
+//! ```
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use log::{info, warn};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    time::{Interval, interval},
+};
+
+use crate::{
+    channels::batch::{ChannelBatch, SubscriptionHandle},
+    models::{
+        AccountPortfolioNotification, AmendParams, Book, BookNotification, CancelParams, Delay,
+        InsertParams, OrderStatus, PortfolioEntry, RecentTrade, RecentTradesNotification,
+        SessionOrdersNotification, Ticker, TickerNotification,
+    },
+    order_book::OrderBook,
+    order_manager::{OrderManager, SubmittedOrder},
+    types::{Error, ExternalEvent, RequestScope},
+    utils::ClientOrderIds,
+    ws_client::WsClient,
+};
+
+/// A subscription a strategy wants its events from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Feed {
+    Ticker {
+        instrument_name: String,
+        delay: Delay,
+    },
+    /// `book.<instrument>.<grouping>.<nlevels>.<delay>`, kept as an [`OrderBook`].
+    Book {
+        instrument_name: String,
+        grouping: String,
+        nlevels: String,
+        delay: Delay,
+    },
+    /// `recent_trades.<target>.<category>`.
+    Trades { target: String, category: String },
+    /// `session.orders`.
+    Orders,
+    /// `account.portfolio`.
+    Portfolio,
+}
+
+impl Feed {
+    pub fn channel(&self) -> String {
+        match self {
+            Feed::Ticker {
+                instrument_name,
+                delay,
+            } => format!("ticker.{instrument_name}.{delay}"),
+            Feed::Book {
+                instrument_name,
+                grouping,
+                nlevels,
+                delay,
+            } => format!("book.{instrument_name}.{grouping}.{nlevels}.{delay}"),
+            Feed::Trades { target, category } => format!("recent_trades.{target}.{category}"),
+            Feed::Orders => "session.orders".to_string(),
+            Feed::Portfolio => "account.portfolio".to_string(),
+        }
+    }
+
+    fn register(&self, batch: &mut ChannelBatch, events: UnboundedSender<StrategyEvent>) {
+        let channel = self.channel();
+        match self {
+            Feed::Ticker {
+                instrument_name, ..
+            } => {
+                let instrument_name = instrument_name.clone();
+                batch.add(
+                    RequestScope::Public,
+                    channel,
+                    move |msg: TickerNotification| {
+                        let _ = events.send(StrategyEvent::Ticker {
+                            instrument_name: instrument_name.clone(),
+                            ticker: msg.notification,
+                        });
+                    },
+                );
+            }
+            Feed::Book { .. } => {
+                batch.add(RequestScope::Public, channel, book_handler(events));
+            }
+            Feed::Trades { .. } => {
+                batch.add(
+                    RequestScope::Public,
+                    channel,
+                    move |msg: RecentTradesNotification| {
+                        let _ = events.send(StrategyEvent::Trades(msg.notification));
+                    },
+                );
+            }
+            Feed::Orders => {
+                batch.add(
+                    RequestScope::Private,
+                    channel,
+                    move |msg: SessionOrdersNotification| {
+                        for order in msg.notification {
+                            let _ = events.send(StrategyEvent::OrderUpdate(order));
+                        }
+                    },
+                );
+            }
+            Feed::Portfolio => {
+                batch.add(
+                    RequestScope::Private,
+                    channel,
+                    move |msg: AccountPortfolioNotification| {
+                        let _ = events.send(StrategyEvent::Portfolio(msg.notification));
+                    },
+                );
+            }
+        }
+    }
+}
+
+fn book_handler(
+    events: UnboundedSender<StrategyEvent>,
+) -> impl FnMut(BookNotification) + Send + 'static {
+    move |msg: BookNotification| {
+        let _ = events.send(StrategyEvent::Book {
+            channel: msg.channel_name,
+            update: msg.notification,
+        });
+    }
+}
+
+/// Everything a [`Strategy`] can be called with, in the order it happened.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StrategyEvent {
+    Ticker {
+        instrument_name: String,
+        ticker: Ticker,
+    },
+    Book {
+        channel: String,
+        update: Book,
+    },
+    Trades(Vec<RecentTrade>),
+    /// From `session.orders` or the result of an order action. The same state may
+    /// arrive from both.
+    OrderUpdate(OrderStatus),
+    Portfolio(Vec<PortfolioEntry>),
+    Timer,
+    Connection(ExternalEvent),
+    ActionFailed {
+        action: OrderAction,
+        error: String,
+    },
+}
+
+/// An order request queued by a hook.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrderAction {
+    Insert(InsertParams),
+    Amend(AmendParams),
+    Cancel(CancelParams),
+    CancelAll,
+}
+
+/// What a hook can do besides updating its own state.
+pub struct StrategyContext {
+    client: Option<Arc<WsClient>>,
+    ids: ClientOrderIds,
+    actions: Vec<OrderAction>,
+    stopped: bool,
+}
+
+impl StrategyContext {
+    fn new(client: Arc<WsClient>) -> Self {
+        StrategyContext {
+            client: Some(client),
+            ..StrategyContext::detached()
+        }
+    }
+
+    /// A context without a client, for driving a strategy by hand in tests or replays.
+    /// Client order ids come from a local generator.
+    pub fn detached() -> Self {
+        StrategyContext {
+            client: None,
+            ids: ClientOrderIds::new(),
+            actions: Vec::new(),
+            stopped: false,
+        }
+    }
+
+    /// The runner's client, for its caches; `None` when detached.
+    pub fn client(&self) -> Option<&Arc<WsClient>> {
+        self.client.as_ref()
+    }
+
+    /// Queues an insert and returns its `client_order_id`, assigning one if missing.
+    pub fn insert(&mut self, mut params: InsertParams) -> i32 {
+        let id = *params
+            .client_order_id
+            .get_or_insert_with(|| match &self.client {
+                Some(client) => client.next_client_order_id(),
+                None => self.ids.next_id(),
+            });
+        self.actions.push(OrderAction::Insert(params));
+        id
+    }
+
+    pub fn amend(&mut self, params: AmendParams) {
+        self.actions.push(OrderAction::Amend(params));
+    }
+
+    pub fn cancel(&mut self, params: CancelParams) {
+        self.actions.push(OrderAction::Cancel(params));
+    }
+
+    pub fn cancel_all(&mut self) {
+        self.actions.push(OrderAction::CancelAll);
+    }
+
+    /// Ends the run after the current hook; [`Strategy::on_stop`] is still called.
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Removes and returns the queued order actions, oldest first.
+    pub fn take_actions(&mut self) -> Vec<OrderAction> {
+        std::mem::take(&mut self.actions)
+    }
+}
+
+/// Hooks called by [`StrategyRunner`], one at a time. All have empty defaults.
+pub trait Strategy {
+    /// Channels to subscribe before the first event.
+    fn feeds(&self) -> Vec<Feed>;
+
+    /// Period of [`Strategy::on_timer`], `None` for no timer.
+    fn timer_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    fn on_start(&mut self, _ctx: &mut StrategyContext) {}
+
+    fn on_ticker(&mut self, _ctx: &mut StrategyContext, _instrument_name: &str, _ticker: &Ticker) {}
+
+    fn on_book(&mut self, _ctx: &mut StrategyContext, _book: &OrderBook) {}
+
+    fn on_trade(&mut self, _ctx: &mut StrategyContext, _trade: &RecentTrade) {}
+
+    fn on_order_update(&mut self, _ctx: &mut StrategyContext, _order: &OrderStatus) {}
+
+    fn on_portfolio(&mut self, _ctx: &mut StrategyContext, _positions: &[PortfolioEntry]) {}
+
+    fn on_timer(&mut self, _ctx: &mut StrategyContext) {}
+
+    /// After a reconnect the runner has already logged in and resubscribed.
+    fn on_connection_event(&mut self, _ctx: &mut StrategyContext, _event: ExternalEvent) {}
+
+    fn on_action_failed(
+        &mut self,
+        _ctx: &mut StrategyContext,
+        _action: &OrderAction,
+        _error: &str,
+    ) {
+    }
+
+    /// Last hook of a run; its order actions are sent before the runner returns.
+    fn on_stop(&mut self, _ctx: &mut StrategyContext) {}
+}
+
+/// Routes events to hooks and keeps the order books of `book.*` feeds.
+#[derive(Debug, Default)]
+pub struct Dispatcher {
+    books: HashMap<String, OrderBook>,
+    resync: Vec<String>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Dispatcher::default()
+    }
+
+    pub fn dispatch<S: Strategy>(
+        &mut self,
+        strategy: &mut S,
+        ctx: &mut StrategyContext,
+        event: StrategyEvent,
+    ) {
+        match event {
+            StrategyEvent::Ticker {
+                instrument_name,
+                ticker,
+            } => strategy.on_ticker(ctx, &instrument_name, &ticker),
+            StrategyEvent::Book { channel, update } => {
+                let book = self
+                    .books
+                    .entry(channel.clone())
+                    .or_insert_with(|| new_book(&channel));
+                if let Err(e) = book.apply(&update) {
+                    warn!("Order book {channel} out of sync ({e}), resyncing");
+                    self.resync.push(channel);
+                    return;
+                }
+                strategy.on_book(ctx, book);
+            }
+            StrategyEvent::Trades(trades) => {
+                for trade in &trades {
+                    strategy.on_trade(ctx, trade);
+                }
+            }
+            StrategyEvent::OrderUpdate(order) => strategy.on_order_update(ctx, &order),
+            StrategyEvent::Portfolio(positions) => strategy.on_portfolio(ctx, &positions),
+            StrategyEvent::Timer => strategy.on_timer(ctx),
+            StrategyEvent::Connection(event) => {
+                if event == ExternalEvent::Connected {
+                    // Resubscribed book channels start over with a snapshot.
+                    self.books.values_mut().for_each(OrderBook::clear);
+                }
+                strategy.on_connection_event(ctx, event);
+            }
+            StrategyEvent::ActionFailed { action, error } => {
+                strategy.on_action_failed(ctx, &action, &error)
+            }
+        }
+    }
+
+    pub fn book(&self, channel: &str) -> Option<&OrderBook> {
+        self.books.get(channel)
+    }
+
+    /// Book channels found out of sync since the last call, now cleared.
+    pub fn take_resyncs(&mut self) -> Vec<String> {
+        let channels = std::mem::take(&mut self.resync);
+        for channel in &channels {
+            if let Some(book) = self.books.get_mut(channel) {
+                book.clear();
+            }
+        }
+        channels
+    }
+}
+
+/// An empty book for `book.<instrument>.<grouping>.<nlevels>.<delay>`.
+fn new_book(channel: &str) -> OrderBook {
+    let mut parts = channel.split('.');
+    let instrument_name = parts.nth(1).unwrap_or_default();
+    // Depth-limited books can't be checked against the totals of the full book.
+    let check_totals = parts.nth(1).is_some_and(|n| n.parse::<u32>().is_err());
+    OrderBook::new(instrument_name).with_totals_check(check_totals)
+}
+
+/// Owns the client and a [`Strategy`], and runs the strategy's event loop.
+///
+/// Inserts go through [`OrderManager::submit`]; the manager also follows the strategy's
+/// order updates.
+pub struct StrategyRunner<S> {
+    client: Arc<WsClient>,
+    strategy: S,
+    orders: OrderManager,
+}
+
+impl<S: Strategy> StrategyRunner<S> {
+    pub fn new(client: WsClient, strategy: S) -> Self {
+        StrategyRunner {
+            client: Arc::new(client),
+            strategy,
+            orders: OrderManager::new(),
+        }
+    }
+
+    /// Tracks the strategy's orders in `orders`.
+    pub fn with_orders(mut self, orders: OrderManager) -> Self {
+        self.orders = orders;
+        self
+    }
+
+    pub fn client(&self) -> &Arc<WsClient> {
+        &self.client
+    }
+
+    /// Runs until the strategy stops or the client exits, then returns the strategy.
+    ///
+    /// On every reconnect the runner logs in again, resubscribes and clears the order
+    /// books before calling [`Strategy::on_connection_event`].
+    pub async fn run(mut self) -> Result<S, Error> {
+        let client = self.client.clone();
+        let (tx, mut rx) = unbounded_channel();
+
+        let mut batch = ChannelBatch::new();
+        for feed in self.strategy.feeds() {
+            feed.register(&mut batch, tx.clone());
+        }
+        let mut subscriptions: HashMap<String, SubscriptionHandle> = HashMap::new();
+        if !batch.is_empty() {
+            let report = client.subscribe_shared(batch).await?;
+            for (channel, error) in &report.failed {
+                warn!("Strategy feed {channel} not subscribed: {error:?}");
+            }
+            subscriptions.extend(
+                report
+                    .subscribed
+                    .into_iter()
+                    .map(|handle| (handle.channel().to_string(), handle)),
+            );
+        }
+
+        let watcher = {
+            let client = client.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                loop {
+                    let event = client.run_till_event().await;
+                    if tx.send(StrategyEvent::Connection(event)).is_err()
+                        || event == ExternalEvent::Exited
+                    {
+                        break;
+                    }
+                }
+            })
+        };
+
+        let (actions, queued) = unbounded_channel();
+        let sender = tokio::spawn(send_actions(
+            client.clone(),
+            self.orders.clone(),
+            queued,
+            tx.clone(),
+        ));
+
+        let mut dispatcher = Dispatcher::new();
+        let mut ctx = StrategyContext::new(client.clone());
+        let mut timer = self.strategy.timer_interval().map(interval);
+        self.strategy.on_start(&mut ctx);
+        info!("Strategy started");
+        loop {
+            queue_actions(&actions, &mut ctx);
+            if ctx.is_stopped() {
+                break;
+            }
+            let event = tokio::select! {
+                event = rx.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = tick(&mut timer) => StrategyEvent::Timer,
+            };
+            let exited = event == StrategyEvent::Connection(ExternalEvent::Exited);
+            if let StrategyEvent::OrderUpdate(order) = &event {
+                self.orders.apply(order.clone());
+            }
+            if event == StrategyEvent::Connection(ExternalEvent::Connected) {
+                // Logging in re-applies cancel on disconnect; public clients have no login.
+                client.login().await.ok();
+                if let Err(e) = client.resubscribe_all().await {
+                    warn!("Failed to resubscribe strategy feeds: {e}");
+                }
+            }
+            dispatcher.dispatch(&mut self.strategy, &mut ctx, event);
+            for channel in dispatcher.take_resyncs() {
+                resync_book(&client, &channel, &tx, &mut subscriptions).await;
+            }
+            if exited {
+                break;
+            }
+        }
+
+        watcher.abort();
+        self.strategy.on_stop(&mut ctx);
+        queue_actions(&actions, &mut ctx);
+        drop(actions);
+        if let Err(e) = sender.await {
+            warn!("Strategy order sender failed: {e}");
+        }
+        while let Ok(event) = rx.try_recv() {
+            if let StrategyEvent::ActionFailed { action, error } = event {
+                warn!("Order action {action:?} failed after the strategy stopped: {error}");
+            }
+        }
+        if let Err(e) = client.release(subscriptions.into_values()).await {
+            warn!("Failed to unsubscribe strategy feeds: {e}");
+        }
+        info!("Strategy stopped");
+        Ok(self.strategy)
+    }
+}
+
+async fn tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn queue_actions(actions: &UnboundedSender<Vec<OrderAction>>, ctx: &mut StrategyContext) {
+    let requested = ctx.take_actions();
+    if !requested.is_empty() {
+        let _ = actions.send(requested);
+    }
+}
+
+/// Sends the queued actions one at a time, in the order the hooks requested them, and
+/// queues their results as events. Returns once the queue is closed and drained.
+async fn send_actions(
+    client: Arc<WsClient>,
+    orders: OrderManager,
+    mut queued: UnboundedReceiver<Vec<OrderAction>>,
+    events: UnboundedSender<StrategyEvent>,
+) {
+    while let Some(actions) = queued.recv().await {
+        for action in actions {
+            let event = match execute(&client, &orders, &action).await {
+                Ok(Some(order)) => StrategyEvent::OrderUpdate(order),
+                Ok(None) => continue,
+                Err(error) => StrategyEvent::ActionFailed { action, error },
+            };
+            let _ = events.send(event);
+        }
+    }
+}
+
+async fn execute(
+    client: &WsClient,
+    orders: &OrderManager,
+    action: &OrderAction,
+) -> Result<Option<OrderStatus>, String> {
+    let trading = client.rpc().trading();
+    let result = match action {
+        OrderAction::Insert(params) => {
+            return match orders.submit(client, params.clone()).await {
+                Ok(SubmittedOrder::Live(order)) => Ok(Some(order)),
+                // Closed or not yet seen; `session.orders` reports it.
+                Ok(_) => Ok(None),
+                Err(e) => Err(e.to_string()),
+            };
+        }
+        OrderAction::Amend(params) => trading.amend(params.clone()).await.map(Some),
+        OrderAction::Cancel(params) => trading.cancel(params.clone()).await.map(Some),
+        OrderAction::CancelAll => trading.cancel_all().await.map(|_| None),
+    };
+    if let Ok(Some(order)) = &result {
+        orders.apply(order.clone());
+    }
+    result.map_err(|e| e.to_string())
+}
+
+/// Resubscribes a book channel so it starts over with a snapshot.
+async fn resync_book(
+    client: &WsClient,
+    channel: &str,
+    events: &UnboundedSender<StrategyEvent>,
+    subscriptions: &mut HashMap<String, SubscriptionHandle>,
+) {
+    let _ = client.release(subscriptions.remove(channel)).await;
+    let resubscribed = client
+        .subscribe_shared_channel(
+            RequestScope::Public,
+            channel.to_string(),
+            book_handler(events.clone()),
+        )
+        .await;
+    match resubscribed {
+        Ok(handle) => {
+            subscriptions.insert(channel.to_string(), handle);
+            info!("Resynced order book {channel}");
+        }
+        Err(e) => warn!("Failed to resync {channel}: {e}"),
+    }
+}
@@ -0,0 +1,262 @@
+//! Prices and amounts kept on an instrument's tick and lot grid.
+//!
+//! Everything here is synchronous and works off [`Instrument`] fields already in the
+//! cache, so it can be used on the quoting hot path.
+
+use std::{fmt, ops::Deref};
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use thiserror::Error;
+
+use crate::models::{DirectionEnum, Instrument};
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum TickError {
+    #[error("step size must be positive, got {0}")]
+    InvalidStep(Decimal),
+    #[error("instrument {0} is not in the instrument cache")]
+    UnknownInstrument(String),
+    #[error("{0} has no positive tick size and volume tick size")]
+    MissingTickSize(String),
+}
+
+/// Direction to round an off-grid value in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+    /// Nearest step, ties to even as in [`crate::utils::round_to_ticks`].
+    Nearest,
+}
+
+impl Rounding {
+    /// Rounding that never makes an order more aggressive: bids down, asks up.
+    pub fn passive(direction: DirectionEnum) -> Self {
+        match direction {
+            DirectionEnum::Buy => Rounding::Down,
+            DirectionEnum::Sell => Rounding::Up,
+        }
+    }
+
+    /// Rounding that never makes an order less aggressive: bids up, asks down.
+    pub fn aggressive(direction: DirectionEnum) -> Self {
+        match direction {
+            DirectionEnum::Buy => Rounding::Up,
+            DirectionEnum::Sell => Rounding::Down,
+        }
+    }
+
+    fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::Down => RoundingStrategy::ToNegativeInfinity,
+            Rounding::Up => RoundingStrategy::ToPositiveInfinity,
+            Rounding::Nearest => RoundingStrategy::MidpointNearestEven,
+        }
+    }
+}
+
+fn check_step(step: Decimal) -> Result<Decimal, TickError> {
+    if step <= Decimal::ZERO {
+        return Err(TickError::InvalidStep(step));
+    }
+    Ok(step)
+}
+
+/// `value` on a multiple of `step`, rounded in the given direction. `step` must be
+/// positive.
+pub fn round_to_step(
+    value: Decimal,
+    step: Decimal,
+    rounding: Rounding,
+) -> Result<Decimal, TickError> {
+    Ok(snap(value, check_step(step)?, rounding))
+}
+
+pub fn is_on_step(value: Decimal, step: Decimal) -> Result<bool, TickError> {
+    Ok((value / check_step(step)?).fract().is_zero())
+}
+
+/// [`round_to_step`] for steps already known to be positive.
+fn snap(value: Decimal, step: Decimal, rounding: Rounding) -> Decimal {
+    ((value / step).round_dp_with_strategy(0, rounding.strategy()) * step).normalize()
+}
+
+/// A price on a tick grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Price {
+    value: Decimal,
+    tick_size: Decimal,
+}
+
+impl Price {
+    pub fn new(value: Decimal, tick_size: Decimal, rounding: Rounding) -> Result<Self, TickError> {
+        Ok(Price {
+            value: round_to_step(value, tick_size, rounding)?,
+            tick_size,
+        })
+    }
+
+    pub fn value(&self) -> Decimal {
+        self.value
+    }
+
+    pub fn tick_size(&self) -> Decimal {
+        self.tick_size
+    }
+
+    /// Moves the price by `ticks` ticks, down for negative values.
+    pub fn step(self, ticks: i64) -> Self {
+        Price {
+            value: (self.value + self.tick_size * Decimal::from(ticks)).normalize(),
+            ..self
+        }
+    }
+
+    /// The price `bps` basis points away, back on the grid with `rounding`.
+    pub fn offset_bps(self, bps: Decimal, rounding: Rounding) -> Self {
+        let value = self.value * (Decimal::ONE + bps / BPS);
+        Price {
+            value: snap(value, self.tick_size, rounding),
+            ..self
+        }
+    }
+
+    /// Distance to `other` in ticks.
+    pub fn ticks_to(&self, other: Price) -> Decimal {
+        ((other.value - self.value) / self.tick_size).normalize()
+    }
+}
+
+/// An order amount on a lot grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Amount {
+    value: Decimal,
+    lot_size: Decimal,
+}
+
+impl Amount {
+    pub fn new(value: Decimal, lot_size: Decimal, rounding: Rounding) -> Result<Self, TickError> {
+        Ok(Amount {
+            value: round_to_step(value, lot_size, rounding)?,
+            lot_size,
+        })
+    }
+
+    pub fn value(&self) -> Decimal {
+        self.value
+    }
+
+    pub fn lot_size(&self) -> Decimal {
+        self.lot_size
+    }
+
+    /// Changes the amount by `lots` lots, never below zero.
+    pub fn step(self, lots: i64) -> Self {
+        let value = self.value + self.lot_size * Decimal::from(lots);
+        Amount {
+            value: value.max(Decimal::ZERO).normalize(),
+            ..self
+        }
+    }
+}
+
+macro_rules! decimal_newtype {
+    ($name:ident) => {
+        impl Deref for $name {
+            type Target = Decimal;
+
+            fn deref(&self) -> &Decimal {
+                &self.value
+            }
+        }
+
+        impl From<$name> for Decimal {
+            fn from(value: $name) -> Decimal {
+                value.value
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.value.fmt(f)
+            }
+        }
+    };
+}
+
+decimal_newtype!(Price);
+decimal_newtype!(Amount);
+
+/// Tick and lot sizes of one instrument, for building [`Price`]s and [`Amount`]s.
+///
+/// Both sizes are positive, so rounding through a spec cannot fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TickSpec {
+    tick_size: Decimal,
+    volume_tick_size: Decimal,
+    min_order_amount: Option<Decimal>,
+}
+
+impl TickSpec {
+    pub fn new(
+        tick_size: Decimal,
+        volume_tick_size: Decimal,
+        min_order_amount: Option<Decimal>,
+    ) -> Result<Self, TickError> {
+        Ok(TickSpec {
+            tick_size: check_step(tick_size)?,
+            volume_tick_size: check_step(volume_tick_size)?,
+            min_order_amount,
+        })
+    }
+
+    pub fn from_instrument(instrument: &Instrument) -> Result<Self, TickError> {
+        let missing =
+            || TickError::MissingTickSize(instrument.instrument_name.clone().unwrap_or_default());
+        let (Some(tick_size), Some(volume_tick_size)) =
+            (instrument.tick_size, instrument.volume_tick_size)
+        else {
+            return Err(missing());
+        };
+        TickSpec::new(tick_size, volume_tick_size, instrument.min_order_amount)
+            .map_err(|_| missing())
+    }
+
+    pub fn tick_size(&self) -> Decimal {
+        self.tick_size
+    }
+
+    pub fn volume_tick_size(&self) -> Decimal {
+        self.volume_tick_size
+    }
+
+    pub fn min_order_amount(&self) -> Option<Decimal> {
+        self.min_order_amount
+    }
+
+    pub fn price(&self, value: Decimal, rounding: Rounding) -> Price {
+        Price {
+            value: snap(value, self.tick_size, rounding),
+            tick_size: self.tick_size,
+        }
+    }
+
+    /// Price for an order in `direction`, rounded away from the touch.
+    pub fn passive_price(&self, value: Decimal, direction: DirectionEnum) -> Price {
+        self.price(value, Rounding::passive(direction))
+    }
+
+    pub fn amount(&self, value: Decimal, rounding: Rounding) -> Amount {
+        Amount {
+            value: snap(value, self.volume_tick_size, rounding),
+            lot_size: self.volume_tick_size,
+        }
+    }
+
+    /// Whether `amount` can be sent as an order.
+    pub fn is_tradable(&self, amount: Amount) -> bool {
+        amount.value > Decimal::ZERO && self.min_order_amount.is_none_or(|min| amount.value >= min)
+    }
+}
@@ -26,6 +26,15 @@ pub enum ExternalEvent {
     Exited,
 }
 
+/// Cancel-on-disconnect as configured on a `WsClient`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CancelOnDisconnectStatus {
+    /// Heartbeat timeout to apply on every login, `None` if not configured.
+    pub timeout_secs: Option<i32>,
+    /// Whether the exchange confirmed the setting for the current session.
+    pub active: bool,
+}
+
 #[derive(Clone, Debug)]
 pub struct LoginState {
     pub key_id: String,
@@ -40,7 +49,7 @@ pub struct RpcMessage {
     pub error: Option<ErrorResponse>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum RequestScope {
     Public,
@@ -1,5 +1,76 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash, Hasher},
+    sync::atomic::{AtomicI32, AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 use rust_decimal::Decimal;
 
+/// High bits of a clock-seeded `client_order_id` that tell client runs apart.
+const SESSION_PREFIX_BITS: u32 = 12;
+/// Ids available to one run before it wraps around to the start of its range.
+const IDS_PER_SESSION: i32 = 1 << (31 - SESSION_PREFIX_BITS);
+
+static SESSIONS: AtomicU64 = AtomicU64::new(0);
+
 pub fn round_to_ticks(price: Decimal, tick_size: Decimal) -> Decimal {
     (price / tick_size).round() * tick_size
 }
+
+/// Monotonic `client_order_id` generator.
+///
+/// [`ClientOrderIds::new`] gives every generator a range of its own, picked by a prefix
+/// in the high bits that is derived from per-process random state, the process id, the
+/// time and a count of generators created in this process. A restarted client, or a
+/// second client of the same account, so starts far from the ids an earlier run may
+/// still have open; two runs share a range with a chance of one in 4096.
+#[derive(Debug)]
+pub struct ClientOrderIds {
+    next: AtomicI32,
+    first: i32,
+    last: i32,
+}
+
+impl ClientOrderIds {
+    pub fn new() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos())
+            .hash(&mut hasher);
+        std::process::id().hash(&mut hasher);
+        SESSIONS.fetch_add(1, Ordering::Relaxed).hash(&mut hasher);
+        let prefix = (hasher.finish() >> (64 - SESSION_PREFIX_BITS)) as i32;
+        let first = prefix * IDS_PER_SESSION;
+        ClientOrderIds {
+            next: AtomicI32::new(first.max(1)),
+            first: first.max(1),
+            last: first + (IDS_PER_SESSION - 1),
+        }
+    }
+
+    /// Ids from `first` up to `i32::MAX`, then from 1.
+    pub fn starting_at(first: i32) -> Self {
+        ClientOrderIds {
+            next: AtomicI32::new(first.max(1)),
+            first: 1,
+            last: i32::MAX,
+        }
+    }
+
+    /// Next id; wraps around to the start of the range after its last id.
+    pub fn next_id(&self) -> i32 {
+        self.next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+                Some(if id >= self.last { self.first } else { id + 1 })
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Default for ClientOrderIds {
+    fn default() -> Self {
+        ClientOrderIds::new()
+    }
+}
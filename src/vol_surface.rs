@@ -0,0 +1,425 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use log::{info, warn};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    channels::batch::{ChannelBatch, SubscriptionHandle},
+    models::{BasePriceNotification, Delay, Instrument, Ticker, TickerNotification, TypeEnum},
+    pricing::year_fraction,
+    types::{Error, RequestScope},
+    ws_client::WsClient,
+};
+
+/// Natural cubic spline through `(x, y)` points, flat beyond the first and last point.
+#[derive(Clone, Debug, Default)]
+struct Spline {
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    /// Second derivatives at each point.
+    m: Vec<f64>,
+}
+
+impl Spline {
+    fn fit(points: &[(f64, f64)]) -> Option<Spline> {
+        let n = points.len();
+        if n == 0 {
+            return None;
+        }
+        let xs: Vec<f64> = points.iter().map(|p| p.0).collect();
+        let ys: Vec<f64> = points.iter().map(|p| p.1).collect();
+        let mut m = vec![0.0; n];
+        if n > 2 {
+            // Tridiagonal system for the interior second derivatives, m[0] = m[n-1] = 0.
+            let mut diag = vec![0.0; n];
+            let mut rhs = vec![0.0; n];
+            for i in 1..n - 1 {
+                let h0 = xs[i] - xs[i - 1];
+                let h1 = xs[i + 1] - xs[i];
+                diag[i] = 2.0 * (h0 + h1);
+                rhs[i] = 6.0 * ((ys[i + 1] - ys[i]) / h1 - (ys[i] - ys[i - 1]) / h0);
+                if i > 1 {
+                    let factor = h0 / diag[i - 1];
+                    diag[i] -= factor * h0;
+                    rhs[i] -= factor * rhs[i - 1];
+                }
+            }
+            for i in (1..n - 1).rev() {
+                let h1 = xs[i + 1] - xs[i];
+                m[i] = (rhs[i] - h1 * m[i + 1]) / diag[i];
+            }
+        }
+        Some(Spline { xs, ys, m })
+    }
+
+    fn eval(&self, x: f64) -> f64 {
+        let n = self.xs.len();
+        if n == 1 || x <= self.xs[0] {
+            return self.ys[0];
+        }
+        if x >= self.xs[n - 1] {
+            return self.ys[n - 1];
+        }
+        let i = self.xs.partition_point(|&xi| xi <= x) - 1;
+        let h = self.xs[i + 1] - self.xs[i];
+        let a = (self.xs[i + 1] - x) / h;
+        let b = (x - self.xs[i]) / h;
+        a * self.ys[i]
+            + b * self.ys[i + 1]
+            + ((a * a * a - a) * self.m[i] + (b * b * b - b) * self.m[i + 1]) * h * h / 6.0
+    }
+}
+
+/// Mark IVs of one expiry and the smile fitted through them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SmileSlice {
+    /// Expiry date in ISO format (YYYY-mm-dd).
+    pub expiry_date: String,
+    pub expiration_timestamp: i64,
+    pub forward: Option<f64>,
+    /// Mark IV per strike.
+    pub points: BTreeMap<Decimal, f64>,
+    /// Variance (IV squared) as a spline in log-moneyness.
+    #[serde(skip)]
+    smile: Option<Spline>,
+}
+
+impl SmileSlice {
+    fn refit(&mut self) {
+        self.smile = self.forward.and_then(|forward| {
+            let points: Vec<(f64, f64)> = self
+                .points
+                .iter()
+                .filter_map(|(strike, iv)| Some(((strike.to_f64()? / forward).ln(), iv * iv)))
+                .collect();
+            Spline::fit(&points)
+        });
+    }
+
+    /// Strike as log-moneyness `ln(strike / forward)`.
+    pub fn log_moneyness(&self, strike: f64) -> Option<f64> {
+        Some((strike / self.forward?).ln())
+    }
+
+    /// IV of the fitted smile at log-moneyness `k`. Flat beyond the outermost strikes.
+    pub fn iv_at(&self, k: f64) -> Option<f64> {
+        Some(self.smile.as_ref()?.eval(k).max(0.0).sqrt())
+    }
+
+    pub fn iv(&self, strike: f64) -> Option<f64> {
+        self.iv_at(self.log_moneyness(strike)?)
+    }
+
+    pub fn atm_iv(&self) -> Option<f64> {
+        self.iv_at(0.0)
+    }
+}
+
+/// Implied volatility surface of one underlying, built from option mark IVs.
+///
+/// Each expiry gets a smile: a natural cubic spline of variance in log-moneyness,
+/// flat outside the quoted strikes. Between expiries the surface interpolates total
+/// variance linearly in time at constant log-moneyness, and extrapolates at constant
+/// volatility before the first and after the last expiry. Times are measured from
+/// [`VolSurface::as_of`], the latest mark timestamp seen.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct VolSurface {
+    pub underlying: String,
+    as_of: f64,
+    /// Slices keyed by expiration timestamp.
+    slices: BTreeMap<i64, SmileSlice>,
+}
+
+impl VolSurface {
+    pub fn new(underlying: &str) -> Self {
+        VolSurface {
+            underlying: underlying.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Valuation time (Unix seconds).
+    pub fn as_of(&self) -> f64 {
+        self.as_of
+    }
+
+    pub fn set_as_of(&mut self, now: f64) {
+        self.as_of = now;
+    }
+
+    /// Adds the mark IV and forward of an option on this surface's underlying.
+    /// Returns `false` if the ticker was ignored.
+    pub fn update_ticker(&mut self, instrument: &Instrument, ticker: &Ticker) -> bool {
+        let forward = ticker.forward.and_then(|f| f.to_f64());
+        let accepted = self.update(instrument, ticker.iv, forward);
+        if accepted && let Some(now) = ticker.mark_timestamp.to_f64() {
+            self.as_of = self.as_of.max(now);
+        }
+        accepted
+    }
+
+    /// Adds a mark IV, e.g. from the `v` field of an `lwt` update.
+    pub fn update_iv(&mut self, instrument: &Instrument, iv: Option<Decimal>) -> bool {
+        self.update(instrument, iv, None)
+    }
+
+    fn update(
+        &mut self,
+        instrument: &Instrument,
+        iv: Option<Decimal>,
+        forward: Option<f64>,
+    ) -> bool {
+        if instrument.r#type != Some(TypeEnum::Option)
+            || instrument.underlying.as_deref() != Some(self.underlying.as_str())
+        {
+            return false;
+        }
+        let (Some(strike), Some(expiry), Some(iv)) = (
+            instrument.strike_price,
+            instrument.expiration_timestamp,
+            iv.and_then(|iv| iv.to_f64()),
+        ) else {
+            return false;
+        };
+        if iv <= 0.0 {
+            return false;
+        }
+        let slice = self
+            .slices
+            .entry(i64::from(expiry))
+            .or_insert_with(|| SmileSlice {
+                expiry_date: instrument.expiry_date.clone().unwrap_or_default(),
+                expiration_timestamp: i64::from(expiry),
+                ..Default::default()
+            });
+        slice.points.insert(strike, iv);
+        if forward.is_some() {
+            slice.forward = forward;
+        }
+        slice.refit();
+        true
+    }
+
+    /// Sets the forward of the expiry on `expiry_date` (ISO), e.g. from `base_price`.
+    pub fn set_forward(&mut self, expiry_date: &str, forward: Decimal) {
+        let Some(forward) = forward.to_f64() else {
+            return;
+        };
+        for slice in self
+            .slices
+            .values_mut()
+            .filter(|s| s.expiry_date == expiry_date)
+        {
+            slice.forward = Some(forward);
+            slice.refit();
+        }
+    }
+
+    /// Drops slices that have expired at [`VolSurface::as_of`].
+    pub fn remove_expired(&mut self) {
+        let as_of = self.as_of;
+        self.slices.retain(|&ts, _| ts as f64 > as_of);
+    }
+
+    pub fn smile(&self, expiration_timestamp: i64) -> Option<&SmileSlice> {
+        self.slices.get(&expiration_timestamp)
+    }
+
+    pub fn slices(&self) -> impl Iterator<Item = &SmileSlice> {
+        self.slices.values()
+    }
+
+    /// Fitted, unexpired slices with their time to expiry in years, earliest first.
+    fn live_slices(&self) -> Vec<(f64, &SmileSlice)> {
+        self.slices
+            .values()
+            .filter(|s| s.smile.is_some())
+            .map(|s| (year_fraction(self.as_of, s.expiration_timestamp as f64), s))
+            .filter(|(t, _)| *t > 0.0)
+            .collect()
+    }
+
+    /// Forward for an arbitrary expiry, linear in time between listed expiries.
+    pub fn forward(&self, expiration_timestamp: i64) -> Option<f64> {
+        let slices = self.live_slices();
+        let t = year_fraction(self.as_of, expiration_timestamp as f64);
+        let after = slices.iter().position(|(ti, _)| *ti >= t);
+        match after {
+            Some(0) => slices[0].1.forward,
+            Some(i) => {
+                let (t0, s0) = slices[i - 1];
+                let (t1, s1) = slices[i];
+                let (f0, f1) = (s0.forward?, s1.forward?);
+                Some(f0 + (f1 - f0) * (t - t0) / (t1 - t0))
+            }
+            None => slices.last()?.1.forward,
+        }
+    }
+
+    /// Implied volatility for `strike` at `expiration_timestamp`.
+    pub fn iv(&self, strike: f64, expiration_timestamp: i64) -> Option<f64> {
+        let k = (strike / self.forward(expiration_timestamp)?).ln();
+        self.iv_at_moneyness(k, expiration_timestamp)
+    }
+
+    /// Implied volatility at log-moneyness `k` for `expiration_timestamp`.
+    pub fn iv_at_moneyness(&self, k: f64, expiration_timestamp: i64) -> Option<f64> {
+        let slices = self.live_slices();
+        let t = year_fraction(self.as_of, expiration_timestamp as f64);
+        if t <= 0.0 {
+            return None;
+        }
+        let total_variance = |(ti, slice): (f64, &SmileSlice)| Some(slice.iv_at(k)?.powi(2) * ti);
+        let w = match slices.iter().position(|(ti, _)| *ti >= t) {
+            Some(0) => total_variance(slices[0])? * t / slices[0].0,
+            Some(i) => {
+                let (t0, t1) = (slices[i - 1].0, slices[i].0);
+                let (w0, w1) = (total_variance(slices[i - 1])?, total_variance(slices[i])?);
+                w0 + (w1 - w0) * (t - t0) / (t1 - t0)
+            }
+            None => {
+                let last = *slices.last()?;
+                total_variance(last)? * t / last.0
+            }
+        };
+        Some((w / t).max(0.0).sqrt())
+    }
+
+    /// At-the-money IV of every listed expiry, earliest first.
+    pub fn atm_term_structure(&self) -> Vec<(i64, f64)> {
+        self.slices
+            .values()
+            .filter_map(|s| Some((s.expiration_timestamp, s.atm_iv()?)))
+            .collect()
+    }
+
+    /// Writes the surface's points and forwards to `path` as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Reads a surface written by [`VolSurface::save`] and refits its smiles.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut surface: VolSurface = serde_json::from_slice(&fs::read(path)?)?;
+        for slice in surface.slices.values_mut() {
+            slice.refit();
+        }
+        Ok(surface)
+    }
+}
+
+/// A [`VolSurface`] fed by the tickers of every listed option on an underlying and the
+/// `base_price` forwards of their expiries.
+///
+/// The channels are registered with the client, so [`WsClient::resubscribe_all`] restores
+/// them after a reconnect. Options listed after subscribing are not picked up.
+#[derive(Clone)]
+pub struct LiveVolSurface {
+    surface: Arc<RwLock<VolSurface>>,
+    channels: Vec<String>,
+    subscriptions: Vec<SubscriptionHandle>,
+}
+
+impl LiveVolSurface {
+    /// Subscribes to the options of `underlying` (e.g. `"BTCUSD"`) found in the client's
+    /// instrument cache.
+    pub async fn subscribe(
+        client: Arc<WsClient>,
+        underlying: &str,
+        delay: Delay,
+    ) -> Result<Self, Error> {
+        let surface = Arc::new(RwLock::new(VolSurface::new(underlying)));
+        let options: Vec<Instrument> = client
+            .instruments_cache
+            .iter()
+            .filter(|e| {
+                e.r#type == Some(TypeEnum::Option) && e.underlying.as_deref() == Some(underlying)
+            })
+            .map(|e| e.value().clone())
+            .collect();
+
+        let mut batch = ChannelBatch::new();
+        let mut expiries: Vec<String> = Vec::new();
+        for instrument in options {
+            let Some(name) = instrument.instrument_name.clone() else {
+                continue;
+            };
+            if let Some(expiry) = &instrument.expiry_date
+                && !expiries.contains(expiry)
+            {
+                expiries.push(expiry.clone());
+            }
+            let target = surface.clone();
+            batch.add(
+                RequestScope::Public,
+                format!("ticker.{name}.{delay}"),
+                move |msg: TickerNotification| {
+                    target
+                        .write()
+                        .unwrap()
+                        .update_ticker(&instrument, &msg.notification);
+                },
+            );
+        }
+        for expiry in expiries {
+            let target = surface.clone();
+            batch.add(
+                RequestScope::Public,
+                format!("base_price.{underlying}.{expiry}"),
+                move |msg: BasePriceNotification| {
+                    target
+                        .write()
+                        .unwrap()
+                        .set_forward(&expiry, msg.notification.price);
+                },
+            );
+        }
+
+        let report = client.subscribe_shared(batch).await?;
+        for (channel, error) in &report.failed {
+            warn!("Vol surface channel {channel} not subscribed: {error:?}");
+        }
+        info!(
+            "Vol surface for {underlying} subscribed to {} channels",
+            report.subscribed.len()
+        );
+        Ok(LiveVolSurface {
+            surface,
+            channels: report
+                .subscribed
+                .iter()
+                .map(|handle| handle.channel().to_string())
+                .collect(),
+            subscriptions: report.subscribed,
+        })
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    pub async fn unsubscribe(&self, client: &WsClient) -> Result<(), Error> {
+        client.release(self.subscriptions.clone()).await
+    }
+
+    /// Runs `f` against the current surface.
+    pub fn read<R>(&self, f: impl FnOnce(&VolSurface) -> R) -> R {
+        f(&self.surface.read().unwrap())
+    }
+
+    /// Copy of the current surface.
+    pub fn snapshot(&self) -> VolSurface {
+        self.surface.read().unwrap().clone()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        self.surface.read().unwrap().save(path)
+    }
+}
@@ -1,42 +1,84 @@
-use dashmap::DashMap;
+use dashmap::{DashMap, mapref::entry::Entry};
 use rust_decimal::Decimal;
 use serde::de::DeserializeOwned;
 
 use tokio::{
     sync::oneshot,
     task::JoinHandle,
-    time::{Duration, Instant, MissedTickBehavior, interval, sleep},
+    time::{Duration, Instant, MissedTickBehavior, interval, sleep, timeout},
 };
 
 use bytes::Bytes;
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{SinkExt, StreamExt, future::OptionFuture};
 use log::{debug, error, info, warn};
 use serde_json::Value;
 use std::env::var;
+use std::pin::Pin;
 use std::sync::{
     Arc,
     atomic::{AtomicU64, Ordering},
 };
-use tokio::sync::{Mutex, mpsc, watch};
+use tokio::sync::{Mutex, broadcast, mpsc, watch};
 use yawc::{Frame, OpCode};
 
 use crate::{
     auth_utils::make_auth_token,
-    models::{Instrument, InstrumentsParams, RpcResponse},
+    models::{
+        Instrument, InstrumentsParams, RpcErrorResponse, RpcResponse, SetCancelOnDisconnectParams,
+        SystemNotification, system_event::Event,
+    },
     routing::{extract_channel, extract_id},
+    ticks::{TickError, TickSpec},
     types::{
-        ChannelSender, ClientError, Environment, Error, ExternalEvent, InternalCommand, LoginState,
-        RequestScope, ResponseSender, SubscribeResponse, WsStream,
+        CancelOnDisconnectStatus, ChannelSender, ClientError, Environment, Error, ExternalEvent,
+        InternalCommand, LoginState, RequestScope, ResponseSender, SubscribeResponse, WsStream,
     },
-    utils::round_to_ticks,
+    utils::{ClientOrderIds, round_to_ticks},
 };
 
-use crate::channels::subscriptions::Subscriptions;
+use crate::channels::{
+    batch::{
+        BatchSubscribeResult, ChannelBatch, ChannelHandler, SharedSubscribeResult,
+        SubscriptionHandle,
+    },
+    subscriptions::Subscriptions,
+};
+use crate::instrument_registry::{INSTRUMENT_EVENT_BUFFER, InstrumentEvent};
 use crate::rpc::Rpc;
 use std::str::FromStr;
 
 const PING_INTERVAL: Duration = Duration::from_secs(5);
 const READ_TIMEOUT: Duration = Duration::from_secs(7);
+/// The server announces a maintenance close at least 10 seconds ahead.
+const STANDBY_TIMEOUT: Duration = Duration::from_secs(8);
+/// How long a replaced connection is kept open for responses to requests sent on it.
+const RETIRE_TIMEOUT: Duration = Duration::from_secs(10);
+const SYSTEM_CHANNEL: &str = "system";
+/// Handler id of the callbacks registered by `subscribe_channel` and `subscribe_batch`.
+const EXCLUSIVE_HANDLER: u64 = 0;
+
+/// Every handler of one channel, fed in registration order by the channel's task.
+type HandlerList = Arc<std::sync::Mutex<Vec<(u64, ChannelHandler)>>>;
+
+type StandbyFuture = Pin<Box<dyn Future<Output = Result<WsStream, Error>> + Send>>;
+
+/// State shared between the client and the connection supervisor.
+#[derive(Clone)]
+struct ConnectionContext {
+    pending_requests: Arc<DashMap<u64, ResponseSender>>,
+    public_subscriptions: Arc<DashMap<String, ChannelSender>>,
+    private_subscriptions: Arc<DashMap<String, ChannelSender>>,
+    next_id: Arc<AtomicU64>,
+    login_state: LoginState,
+    logged_in: Arc<watch::Sender<bool>>,
+    cancel_on_disconnect: Arc<watch::Sender<CancelOnDisconnectStatus>>,
+}
+
+enum ConnectionEnd {
+    Closed,
+    /// The server announced a close and a logged-in, resubscribed standby connection took over.
+    Handover(Box<WsStream>),
+}
 
 pub struct WsClient {
     pub write_tx: mpsc::UnboundedSender<InternalCommand>,
@@ -46,11 +88,19 @@ pub struct WsClient {
     next_id: Arc<AtomicU64>,
     shutdown_tx: watch::Sender<bool>,
     pub instruments_cache: Arc<DashMap<String, Instrument>>,
+    /// Events of the instrument registries over `instruments_cache`, including
+    /// instruments added by `cache_instruments`.
+    pub(crate) instrument_events: broadcast::Sender<InstrumentEvent>,
     login_state: LoginState,
+    logged_in: Arc<watch::Sender<bool>>,
+    cancel_on_disconnect: Arc<watch::Sender<CancelOnDisconnectStatus>>,
     connection_state_rx: watch::Receiver<ExternalEvent>,
     current_connection_state: Arc<Mutex<ExternalEvent>>,
     supervisor_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
-    subscription_tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    subscription_tasks: Arc<DashMap<String, JoinHandle<()>>>,
+    channel_handlers: Arc<DashMap<String, HandlerList>>,
+    next_handler_id: Arc<AtomicU64>,
+    client_order_ids: ClientOrderIds,
     pub environment: Environment,
 }
 
@@ -127,13 +177,23 @@ impl WsClient {
             private_key_pem,
         };
 
+        let logged_in = Arc::new(watch::Sender::new(false));
+        let cancel_on_disconnect =
+            Arc::new(watch::Sender::new(CancelOnDisconnectStatus::default()));
+
         let supervisor_handle = tokio::spawn(connection_supervisor(
             url.to_string(),
             cmd_rx,
             shutdown_rx,
-            pending_requests.clone(),
-            public_subscriptions.clone(),
-            private_subscriptions.clone(),
+            ConnectionContext {
+                pending_requests: pending_requests.clone(),
+                public_subscriptions: public_subscriptions.clone(),
+                private_subscriptions: private_subscriptions.clone(),
+                next_id: next_id.clone(),
+                login_state: login_state.clone(),
+                logged_in: logged_in.clone(),
+                cancel_on_disconnect: cancel_on_disconnect.clone(),
+            },
             connection_state_tx.clone(),
         ));
 
@@ -145,50 +205,75 @@ impl WsClient {
             next_id: next_id.clone(),
             shutdown_tx: shutdown_tx.clone(),
             instruments_cache: Arc::new(DashMap::new()),
+            instrument_events: broadcast::channel(INSTRUMENT_EVENT_BUFFER).0,
             login_state,
+            logged_in,
+            cancel_on_disconnect,
             connection_state_rx,
             current_connection_state: Arc::new(Mutex::new(ExternalEvent::Disconnected)),
             supervisor_handle: Arc::new(Mutex::new(Some(supervisor_handle))),
-            subscription_tasks: Arc::new(Mutex::new(Vec::new())),
+            subscription_tasks: Arc::new(DashMap::new()),
+            channel_handlers: Arc::new(DashMap::new()),
+            next_handler_id: Arc::new(AtomicU64::new(EXCLUSIVE_HANDLER + 1)),
+            client_order_ids: ClientOrderIds::new(),
             environment: env,
         };
 
-        client.cache_instruments().await?;
+        // The cache is filled again on the first lookup miss, or kept live by an InstrumentRegistry.
+        if let Err(e) = client.cache_instruments().await {
+            warn!("Initial instruments fetch failed: {e}");
+        }
         Ok(client)
     }
 
-    async fn cache_instruments(&self) -> Result<(), Error> {
-        let instruments = self.get_instruments().await.unwrap();
-        self.instruments_cache.clear();
-        for instrument in &instruments {
-            self.instruments_cache.insert(
-                instrument.instrument_name.clone().unwrap(),
-                instrument.clone(),
-            );
+    /// Merges all active instruments into the cache. Nothing is removed, so readers never
+    /// see a partly filled cache; instruments new to it are announced to registries.
+    pub(crate) async fn cache_instruments(&self) -> Result<(), Error> {
+        let instruments = self.get_instruments().await?;
+        for instrument in instruments {
+            let Some(name) = instrument.instrument_name.clone() else {
+                continue;
+            };
+            if self
+                .instruments_cache
+                .insert(name, instrument.clone())
+                .is_none()
+            {
+                let _ = self
+                    .instrument_events
+                    .send(InstrumentEvent::Added(instrument));
+            }
         }
         Ok(())
     }
 
+    /// Tick and lot sizes of `instrument_name` from the cache, without any request.
+    pub fn tick_spec(&self, instrument_name: &str) -> Result<TickSpec, TickError> {
+        let instrument = self
+            .instruments_cache
+            .get(instrument_name)
+            .ok_or_else(|| TickError::UnknownInstrument(instrument_name.to_string()))?;
+        TickSpec::from_instrument(&instrument)
+    }
+
+    /// Like [`WsClient::tick_spec`], refreshing the cache once if the instrument is missing.
+    pub(crate) async fn fetch_tick_spec(&self, instrument_name: &str) -> Result<TickSpec, Error> {
+        match self.tick_spec(instrument_name) {
+            Err(TickError::UnknownInstrument(_)) => {
+                self.cache_instruments().await?;
+                Ok(self.tick_spec(instrument_name)?)
+            }
+            spec => Ok(spec?),
+        }
+    }
+
     pub async fn round_price_to_ticks(
         &self,
         price: Decimal,
         instrument_name: &str,
     ) -> Result<Decimal, Error> {
-        let instrument = self.instruments_cache.get(instrument_name);
-        // refresh cache if not found
-        if let Some(instr) = instrument {
-            Ok(round_to_ticks(price, instr.tick_size.unwrap()))
-        } else {
-            self.cache_instruments().await?;
-            if let Some(instr) = self.instruments_cache.get(instrument_name) {
-                Ok(round_to_ticks(price, instr.tick_size.unwrap()))
-            } else {
-                Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!("Instrument not found: {instrument_name}"),
-                )))
-            }
-        }
+        let spec = self.fetch_tick_spec(instrument_name).await?;
+        Ok(round_to_ticks(price, spec.tick_size()))
     }
 
     pub async fn round_amount_to_lot_size(
@@ -196,21 +281,8 @@ impl WsClient {
         amount: Decimal,
         instrument_name: &str,
     ) -> Result<Decimal, Error> {
-        let instrument = self.instruments_cache.get(instrument_name);
-        // refresh cache if not found
-        if let Some(instr) = instrument {
-            Ok(round_to_ticks(amount, instr.volume_tick_size.unwrap()))
-        } else {
-            self.cache_instruments().await?;
-            if let Some(instr) = self.instruments_cache.get(instrument_name) {
-                Ok(round_to_ticks(amount, instr.volume_tick_size.unwrap()))
-            } else {
-                Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!("Instrument not found: {instrument_name}"),
-                )))
-            }
-        }
+        let spec = self.fetch_tick_spec(instrument_name).await?;
+        Ok(round_to_ticks(amount, spec.volume_tick_size()))
     }
 
     async fn get_instruments(&self) -> Result<Vec<Instrument>, ClientError> {
@@ -278,121 +350,318 @@ impl WsClient {
                 }
             }
         }
-        for task in self.subscription_tasks.lock().await.drain(..) {
-            task.abort();
+        for task in self.subscription_tasks.iter() {
+            task.value().abort();
         }
+        self.subscription_tasks.clear();
+        self.channel_handlers.clear();
         Ok(())
     }
 
+    /// Subscribes `callback` to `channel`, replacing the previous callback registered through
+    /// this method or [`subscribe_batch`](Self::subscribe_batch). Handlers added with
+    /// [`subscribe_shared`](Self::subscribe_shared) keep receiving the channel.
     pub async fn subscribe_channel<P, F>(
         &self,
         scope: RequestScope,
         channel: String,
-        mut callback: F,
+        callback: F,
     ) -> Result<String, ClientError>
     where
         P: DeserializeOwned + Send + 'static,
         F: FnMut(P) + Send + 'static,
     {
-        let (tx, mut rx) = mpsc::unbounded_channel::<Bytes>();
+        let mut batch = ChannelBatch::new();
+        batch.add(scope, channel.clone(), callback);
+        let (_, mut failed) = self.subscribe_handlers(batch, false).await?;
+        match failed.pop() {
+            None => Ok(channel),
+            Some((_, Some(error))) => Err(ClientError::Rpc(error)),
+            Some((channel, None)) => Err(ClientError::Transport(
+                format!("Channel not confirmed: {channel}").into(),
+            )),
+        }
+    }
 
-        {
-            match scope {
-                RequestScope::Public => {
-                    self.public_subscriptions.insert(channel.clone(), tx);
-                    debug!("Subscribing to public channel: {channel}");
-                }
-                RequestScope::Private => {
-                    self.private_subscriptions.insert(channel.clone(), tx);
-                    debug!("Subscribing to private channel: {channel}");
-                }
-            }
+    /// Subscribes to every channel in `batch` with one request per scope.
+    ///
+    /// Like [`subscribe_channel`](Self::subscribe_channel), each callback replaces the previous
+    /// one of its channel. Channels the server does not confirm are unregistered again and
+    /// reported in [`BatchSubscribeResult::failed`].
+    pub async fn subscribe_batch(
+        &self,
+        batch: ChannelBatch,
+    ) -> Result<BatchSubscribeResult, ClientError> {
+        let (subscribed, failed) = self.subscribe_handlers(batch, false).await?;
+        Ok(BatchSubscribeResult {
+            subscribed: subscribed.into_iter().map(|h| h.channel).collect(),
+            failed,
+        })
+    }
+
+    /// Adds every callback in `batch` to its channel alongside all other handlers, subscribing
+    /// with one request per scope.
+    ///
+    /// Each confirmed callback gets a [`SubscriptionHandle`]; the channel stays subscribed
+    /// until every handle is given back to [`release`](Self::release). Components sharing a
+    /// client subscribe through this so they neither replace nor unsubscribe each other.
+    pub async fn subscribe_shared(
+        &self,
+        batch: ChannelBatch,
+    ) -> Result<SharedSubscribeResult, ClientError> {
+        let (subscribed, failed) = self.subscribe_handlers(batch, true).await?;
+        Ok(SharedSubscribeResult { subscribed, failed })
+    }
+
+    /// [`subscribe_shared`](Self::subscribe_shared) for a single channel.
+    pub async fn subscribe_shared_channel<P, F>(
+        &self,
+        scope: RequestScope,
+        channel: String,
+        callback: F,
+    ) -> Result<SubscriptionHandle, ClientError>
+    where
+        P: DeserializeOwned + Send + 'static,
+        F: FnMut(P) + Send + 'static,
+    {
+        let mut batch = ChannelBatch::new();
+        batch.add(scope, channel.clone(), callback);
+        let mut result = self.subscribe_shared(batch).await?;
+        match (result.subscribed.pop(), result.failed.pop()) {
+            (Some(handle), _) => Ok(handle),
+            (None, Some((_, Some(error)))) => Err(ClientError::Rpc(error)),
+            (None, _) => Err(ClientError::Transport(
+                format!("Channel not confirmed: {channel}").into(),
+            )),
         }
+    }
 
-        let handle = tokio::spawn(async move {
-            while let Some(msg) = rx.recv().await {
-                let parsed: P = match deserialise_to_type(&msg) {
-                    Ok(m) => m,
-                    Err(e) => {
-                        warn!("Failed to parse channel message: {e}; raw: {msg:?}");
-                        continue;
-                    }
-                };
+    /// Removes the handlers behind `handles` and unsubscribes from the channels left without
+    /// any handler.
+    pub async fn release<I>(&self, handles: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = SubscriptionHandle>,
+    {
+        let mut emptied = Vec::new();
+        for handle in handles {
+            if let Some(scope) = self.remove_handler(&handle.channel, handle.id) {
+                emptied.push((scope, handle.channel));
+            }
+        }
+        self.send_unsubscribe(emptied).await
+    }
 
-                callback(parsed);
+    /// [`release`](Self::release) on a background task, for `Drop` implementations.
+    pub fn release_in_background(self: &Arc<Self>, handles: Vec<SubscriptionHandle>) {
+        if handles.is_empty() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let client = self.clone();
+        runtime.spawn(async move {
+            if let Err(e) = client.release(handles).await {
+                warn!("Failed to release subscriptions: {e}");
             }
         });
-        let sub_result: SubscribeResponse = self
-            .send_rpc(
-                &format!("{scope}/subscribe"),
-                serde_json::json!({
-                    "channels": [channel.clone()]
-                }),
-            )
-            .await?;
-        match sub_result {
-            SubscribeResponse::Ok {
-                id: _id,
-                result: _result,
-            } => {
-                debug!("Subscribed to channel: {channel}");
-                self.subscription_tasks.lock().await.push(handle);
-                Ok(channel)
+    }
+
+    async fn subscribe_handlers(
+        &self,
+        batch: ChannelBatch,
+        shared: bool,
+    ) -> Result<
+        (
+            Vec<SubscriptionHandle>,
+            Vec<(String, Option<RpcErrorResponse>)>,
+        ),
+        ClientError,
+    > {
+        let mut subscribed = Vec::new();
+        let mut failed = Vec::new();
+        for (scope, entries) in [
+            (RequestScope::Public, batch.public),
+            (RequestScope::Private, batch.private),
+        ] {
+            if entries.is_empty() {
+                continue;
             }
-            SubscribeResponse::Err { error, id: _id } => {
-                warn!("Subscription error: {error:?}");
+            let mut handles = Vec::with_capacity(entries.len());
+            for (channel, handler) in entries {
+                let id = if shared {
+                    self.next_handler_id.fetch_add(1, Ordering::Relaxed)
+                } else {
+                    EXCLUSIVE_HANDLER
+                };
+                self.register_channel(&scope, &channel, id, handler);
+                handles.push(SubscriptionHandle { channel, id });
+            }
+            let channels: Vec<&str> = handles.iter().map(|h| h.channel.as_str()).collect();
+            let sub_result: SubscribeResponse = match self
+                .send_rpc(
+                    &format!("{scope}/subscribe"),
+                    serde_json::json!({ "channels": channels }),
+                )
+                .await
+            {
+                Ok(res) => res,
+                Err(e) => {
+                    for handle in &handles {
+                        self.remove_handler(&handle.channel, handle.id);
+                    }
+                    return Err(e);
+                }
+            };
+            match sub_result {
+                SubscribeResponse::Ok { result, .. } => {
+                    for handle in handles {
+                        if result.contains(&handle.channel) {
+                            debug!("Subscribed to {scope} channel: {}", handle.channel);
+                            subscribed.push(handle);
+                        } else {
+                            warn!(
+                                "Channel not confirmed by {scope}/subscribe: {}",
+                                handle.channel
+                            );
+                            self.remove_handler(&handle.channel, handle.id);
+                            failed.push((handle.channel, None));
+                        }
+                    }
+                }
+                SubscribeResponse::Err { error, .. } => {
+                    warn!("Subscription error: {error:?}");
+                    for handle in handles {
+                        self.remove_handler(&handle.channel, handle.id);
+                        failed.push((handle.channel, Some(error.clone())));
+                    }
+                }
+            }
+        }
+        debug!(
+            "Subscribed to {} channels, {} failed",
+            subscribed.len(),
+            failed.len()
+        );
+        Ok((subscribed, failed))
+    }
+
+    /// Adds `handler` to the handlers of `channel`, replacing the one with the same `id`.
+    /// The first handler of a channel starts the task that fans its messages out.
+    fn register_channel(
+        &self,
+        scope: &RequestScope,
+        channel: &str,
+        id: u64,
+        handler: ChannelHandler,
+    ) {
+        match self.channel_handlers.entry(channel.to_string()) {
+            Entry::Occupied(entry) => {
+                let mut handlers = lock_handlers(entry.get());
+                handlers.retain(|(existing, _)| *existing != id);
+                handlers.push((id, handler));
+            }
+            Entry::Vacant(entry) => {
+                let handlers: HandlerList = Arc::new(std::sync::Mutex::new(vec![(id, handler)]));
+                entry.insert(handlers.clone());
+                let (tx, mut rx) = mpsc::unbounded_channel::<Bytes>();
                 match scope {
                     RequestScope::Public => {
-                        self.public_subscriptions.remove(&channel);
+                        self.public_subscriptions.insert(channel.to_string(), tx);
+                        debug!("Subscribing to public channel: {channel}");
                     }
                     RequestScope::Private => {
-                        self.private_subscriptions.remove(&channel);
+                        self.private_subscriptions.insert(channel.to_string(), tx);
+                        debug!("Subscribing to private channel: {channel}");
+                    }
+                }
+                let handle = tokio::spawn(async move {
+                    while let Some(msg) = rx.recv().await {
+                        for (_, handler) in lock_handlers(&handlers).iter_mut() {
+                            handler(msg.clone());
+                        }
                     }
+                });
+                if let Some(previous) = self.subscription_tasks.insert(channel.to_string(), handle)
+                {
+                    previous.abort();
                 }
-                handle.abort();
-                Err(ClientError::Rpc(error))
             }
         }
     }
 
-    pub async fn unsubscribe(&self, channel: &str) -> Result<(), Error> {
-        let channel = channel.to_string();
-        if let Some(task) = self.subscription_tasks.lock().await.pop() {
+    /// Removes the handler `id` from `channel`. Returns the scope of the channel when that was
+    /// its last handler, after which it is no longer dispatched.
+    fn remove_handler(&self, channel: &str, id: u64) -> Option<RequestScope> {
+        self.channel_handlers.remove_if(channel, |_, handlers| {
+            let mut handlers = lock_handlers(handlers);
+            handlers.retain(|(existing, _)| *existing != id);
+            handlers.is_empty()
+        })?;
+        let scope = if self.public_subscriptions.remove(channel).is_some() {
+            RequestScope::Public
+        } else {
+            self.private_subscriptions.remove(channel);
+            RequestScope::Private
+        };
+        if let Some((_, task)) = self.subscription_tasks.remove(channel) {
             task.abort();
         }
+        Some(scope)
+    }
+
+    /// Drops the callback registered with [`subscribe_channel`](Self::subscribe_channel) and
+    /// unsubscribes unless shared handlers still use the channel.
+    pub async fn unsubscribe(&self, channel: &str) -> Result<(), Error> {
+        if !self.public_subscriptions.contains_key(channel)
+            && !self.private_subscriptions.contains_key(channel)
         {
-            if self.public_subscriptions.remove(&channel).is_some() {
-                let _: RpcResponse = self
-                    .send_rpc(
-                        "public/unsubscribe",
-                        serde_json::json!({
-                            "channels": [channel.clone()]
-                        }),
-                    )
-                    .await?;
-                info!("Unsubscribed from public channel: {channel}");
-                return Ok(());
+            warn!("No active subscription found for channel: {channel}");
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No active subscription for channel: {channel}"),
+            )));
+        }
+        self.unsubscribe_channels(&[channel]).await
+    }
+
+    /// [`unsubscribe`](Self::unsubscribe) for several channels, with one request per scope.
+    /// Channels without an active subscription are skipped.
+    pub async fn unsubscribe_channels<S: AsRef<str>>(&self, channels: &[S]) -> Result<(), Error> {
+        let mut emptied = Vec::new();
+        for channel in channels.iter().map(AsRef::as_ref) {
+            if !self.channel_handlers.contains_key(channel) {
+                warn!("No active subscription found for channel: {channel}");
+            } else if let Some(scope) = self.remove_handler(channel, EXCLUSIVE_HANDLER) {
+                emptied.push((scope, channel.to_string()));
+            } else {
+                debug!("Channel {channel} is still used by shared subscriptions");
             }
         }
-        {
-            if self.private_subscriptions.remove(&channel).is_some() {
-                let _: RpcResponse = self
-                    .send_rpc(
-                        "private/unsubscribe",
-                        serde_json::json!({
-                            "channels": [channel.clone()]
-                        }),
-                    )
-                    .await?;
-                info!("Unsubscribed from private channel: {channel}");
-                return Ok(());
+        self.send_unsubscribe(emptied).await
+    }
+
+    async fn send_unsubscribe(&self, channels: Vec<(RequestScope, String)>) -> Result<(), Error> {
+        for scope in [RequestScope::Public, RequestScope::Private] {
+            let channels: Vec<&str> = channels
+                .iter()
+                .filter(|(s, _)| *s == scope)
+                .map(|(_, c)| c.as_str())
+                .collect();
+            if channels.is_empty() {
+                continue;
             }
+            let _: RpcResponse = self
+                .send_rpc(
+                    &format!("{scope}/unsubscribe"),
+                    serde_json::json!({
+                        "channels": channels
+                    }),
+                )
+                .await?;
+            info!("Unsubscribed from {scope} channels: {channels:?}");
         }
-        warn!("No active subscription found for channel: {channel}");
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("No active subscription for channel: {channel}"),
-        )))
+        Ok(())
     }
 
     pub async fn login(&self) -> Result<(), Error> {
@@ -422,19 +691,59 @@ impl WsClient {
                 tokio::time::sleep(Duration::from_secs(3)).await;
             } else {
                 debug!("Login successful");
+                self.logged_in
+                    .send_if_modified(|v| !std::mem::replace(v, true));
+                // The session is usable either way; a failure shows as an inactive
+                // `cancel_on_disconnect_status`.
+                if let Err(e) = self.apply_cancel_on_disconnect().await {
+                    warn!("Logged in, but failed to set cancel_on_disconnect: {e}");
+                }
                 return Ok(());
             }
         }
     }
 
-    pub async fn set_cancel_on_disconnect(&self) -> Result<(), Error> {
-        let result: RpcResponse = self
-            .send_rpc(
-                "private/set_cancel_on_disconnect",
-                serde_json::json!({ "timeout_secs": 6}),
-            )
+    /// Makes the exchange cancel our orders if this session stays silent for
+    /// `timeout_secs`. The setting is applied now if logged in and again on every login,
+    /// including logins after a reconnect.
+    pub async fn set_cancel_on_disconnect(&self, timeout_secs: i32) -> Result<(), Error> {
+        self.cancel_on_disconnect.send_modify(|s| {
+            *s = CancelOnDisconnectStatus {
+                timeout_secs: Some(timeout_secs),
+                active: false,
+            }
+        });
+        if *self.logged_in.borrow() {
+            self.apply_cancel_on_disconnect().await?;
+        }
+        Ok(())
+    }
+
+    /// A `client_order_id` not yet used by this client.
+    pub fn next_client_order_id(&self) -> i32 {
+        self.client_order_ids.next_id()
+    }
+
+    /// Whether cancel_on_disconnect is configured and in force for the current session.
+    /// [`WsClient::login`] succeeds even if applying it fails; `active` stays `false` then.
+    pub fn cancel_on_disconnect_status(&self) -> CancelOnDisconnectStatus {
+        *self.cancel_on_disconnect.borrow()
+    }
+
+    async fn apply_cancel_on_disconnect(&self) -> Result<(), Error> {
+        let Some(timeout_secs) = self.cancel_on_disconnect.borrow().timeout_secs else {
+            return Ok(());
+        };
+        let result = self
+            .rpc()
+            .session_management()
+            .set_cancel_on_disconnect(SetCancelOnDisconnectParams { timeout_secs })
             .await?;
         debug!("Set cancel_on_disconnect result: {result:?}");
+        self.cancel_on_disconnect.send_if_modified(|s| {
+            let applies = s.timeout_secs == Some(timeout_secs);
+            applies && !std::mem::replace(&mut s.active, true)
+        });
         Ok(())
     }
 
@@ -490,6 +799,19 @@ impl WsClient {
         }
     }
 
+    /// Receiver for connection state changes, for components that resync after a reconnect.
+    pub fn connection_state(&self) -> watch::Receiver<ExternalEvent> {
+        self.connection_state_rx.clone()
+    }
+
+    /// Receiver for the session's login status. It turns `true` after a successful
+    /// [`WsClient::login`] and `false` when the connection drops. It is also marked
+    /// changed, still `true`, when a standby connection takes over with a new session,
+    /// so per-session state (session channels, mm protection) is set up again.
+    pub fn login_status(&self) -> watch::Receiver<bool> {
+        self.logged_in.subscribe()
+    }
+
     pub fn is_connected(&self) -> bool {
         // Remove async - this is just reading a value
         *self.connection_state_rx.borrow() == ExternalEvent::Connected
@@ -520,9 +842,7 @@ async fn connection_supervisor(
     url: String,
     mut cmd_rx: mpsc::UnboundedReceiver<InternalCommand>,
     mut shutdown_rx: watch::Receiver<bool>,
-    pending_requests: Arc<DashMap<u64, ResponseSender>>,
-    public_subscriptions: Arc<DashMap<String, ChannelSender>>,
-    private_subscriptions: Arc<DashMap<String, ChannelSender>>,
+    ctx: ConnectionContext,
     connection_state_tx: watch::Sender<ExternalEvent>,
 ) {
     debug!("Connection supervisor started for {url}");
@@ -536,21 +856,40 @@ async fn connection_supervisor(
 
         debug!("Attempting to connect to {url} (attempt {attempts})");
         match yawc::WebSocket::connect(url.parse().unwrap()).await {
-            Ok(ws_stream) => {
+            Ok(mut ws_stream) => {
+                if let Err(e) = ws_stream
+                    .send(subscribe_frame(
+                        &ctx,
+                        &RequestScope::Public,
+                        &[SYSTEM_CHANNEL],
+                    ))
+                    .await
+                {
+                    warn!("Failed to subscribe to {SYSTEM_CHANNEL} on {url}: {e}");
+                }
                 connection_state_tx.send(ExternalEvent::Connected).ok();
                 attempts = 1;
                 debug!("Connected to {url}");
-                let result = run_single_connection(
-                    &url,
-                    ws_stream,
-                    &mut cmd_rx,
-                    &mut shutdown_rx,
-                    &pending_requests,
-                    &public_subscriptions,
-                    &private_subscriptions,
-                )
-                .await;
-                debug!("Connection to {url} ended with result: {result:?}");
+                let mut result =
+                    run_single_connection(&url, ws_stream, &mut cmd_rx, &mut shutdown_rx, &ctx)
+                        .await;
+                // The connection state stays `Connected` across a handover, but the
+                // standby runs a new session: login listeners set it up again.
+                while let Ok(ConnectionEnd::Handover(standby)) = result {
+                    info!("Switched to standby connection for {url}");
+                    ctx.logged_in.send_if_modified(|logged_in| *logged_in);
+                    result =
+                        run_single_connection(&url, *standby, &mut cmd_rx, &mut shutdown_rx, &ctx)
+                            .await;
+                }
+                debug!(
+                    "Connection to {url} ended with result: {:?}",
+                    result.as_ref().err()
+                );
+                ctx.logged_in
+                    .send_if_modified(|v| std::mem::replace(v, false));
+                ctx.cancel_on_disconnect
+                    .send_if_modified(|s| std::mem::replace(&mut s.active, false));
 
                 if result.is_ok() {
                     connection_state_tx.send(ExternalEvent::Exited).ok();
@@ -562,15 +901,7 @@ async fn connection_supervisor(
                     error!("Connection error on {url}: {e}");
                 }
 
-                for key in pending_requests
-                    .iter()
-                    .map(|e| *e.key())
-                    .collect::<Vec<u64>>()
-                {
-                    if let Some((_, tx)) = pending_requests.remove(&key) {
-                        let _ = tx.send(r#"{"error":"connection closed"}"#.into());
-                    }
-                }
+                fail_pending_requests(&ctx.pending_requests, None);
 
                 if *shutdown_rx.borrow() {
                     connection_state_tx.send(ExternalEvent::Exited).ok();
@@ -603,10 +934,8 @@ async fn run_single_connection(
     mut ws: WsStream,
     cmd_rx: &mut mpsc::UnboundedReceiver<InternalCommand>,
     shutdown_rx: &mut watch::Receiver<bool>,
-    pending_requests: &Arc<DashMap<u64, ResponseSender>>,
-    public_subscriptions: &Arc<DashMap<String, ChannelSender>>,
-    private_subscriptions: &Arc<DashMap<String, ChannelSender>>,
-) -> Result<(), Error> {
+    ctx: &ConnectionContext,
+) -> Result<ConnectionEnd, Error> {
     // Set up ping interval
     let mut ping_interval = interval(PING_INTERVAL);
     ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
@@ -614,6 +943,8 @@ async fn run_single_connection(
     let read_deadline = sleep(READ_TIMEOUT);
     tokio::pin!(read_deadline);
 
+    let mut standby: Option<StandbyFuture> = None;
+
     loop {
         tokio::select! {
             _ = ping_interval.tick() => {
@@ -627,7 +958,7 @@ async fn run_single_connection(
                 if *shutdown_rx.borrow() {
                     info!("Shutdown requested for {url}");
                     let _ = ws.close().await;
-                    return Ok(());
+                    return Ok(ConnectionEnd::Closed);
                 }
             }
 
@@ -644,7 +975,24 @@ async fn run_single_connection(
                     None => {
                         info!("Command channel closed for {url}");
                         let _ = ws.close().await;
-                        return Ok(());
+                        return Ok(ConnectionEnd::Closed);
+                    }
+                }
+            }
+
+            Some(prepared) = OptionFuture::from(standby.as_mut()), if standby.is_some() => {
+                standby = None;
+                match prepared {
+                    Ok(next) => {
+                        tokio::spawn(retire_connection(
+                            url.to_string(),
+                            ws,
+                            ctx.pending_requests.clone(),
+                        ));
+                        return Ok(ConnectionEnd::Handover(Box::new(next)));
+                    }
+                    Err(e) => {
+                        warn!("Standby connection for {url} failed, waiting for the server to close: {e}");
                     }
                 }
             }
@@ -657,20 +1005,17 @@ async fn run_single_connection(
                 };
 
                 match frame.opcode() {
-                    OpCode::Text => {
+                    OpCode::Text | OpCode::Binary => {
+                        let payload = frame.into_payload();
+                        if standby.is_none() && is_reconnect_event(&payload) {
+                            info!("Server announced a reconnect for {url}, opening standby connection");
+                            standby = Some(Box::pin(prepare_standby(url.to_string(), ctx.clone())));
+                        }
                         handle_incoming(
-                            frame.into_payload(),
-                            pending_requests,
-                            public_subscriptions,
-                            private_subscriptions,
-                        );
-                    }
-                    OpCode::Binary => {
-                        handle_incoming(
-                            frame.into_payload(),
-                            pending_requests,
-                            public_subscriptions,
-                            private_subscriptions,
+                            payload,
+                            &ctx.pending_requests,
+                            &ctx.public_subscriptions,
+                            &ctx.private_subscriptions,
                         );
                     }
                     OpCode::Ping => {
@@ -697,6 +1042,172 @@ async fn run_single_connection(
     }
 }
 
+/// Whether `bytes` is the `system` notification announcing that the server will close
+/// this connection.
+pub fn is_reconnect_event(bytes: &Bytes) -> bool {
+    extract_channel(bytes) == Some(SYSTEM_CHANNEL)
+        && serde_json::from_slice::<SystemNotification>(bytes)
+            .is_ok_and(|msg| msg.notification.event == Event::Reconnect)
+}
+
+fn rpc_frame(ctx: &ConnectionContext, method: &str, params: Value) -> (u64, Frame) {
+    let id = ctx.next_id.fetch_add(1, Ordering::Relaxed);
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params
+    });
+    (id, Frame::text(request.to_string()))
+}
+
+fn subscribe_frame<S: AsRef<str>>(
+    ctx: &ConnectionContext,
+    scope: &RequestScope,
+    channels: &[S],
+) -> Frame {
+    let channels: Vec<&str> = channels.iter().map(AsRef::as_ref).collect();
+    rpc_frame(
+        ctx,
+        &format!("{scope}/subscribe"),
+        serde_json::json!({ "channels": channels }),
+    )
+    .1
+}
+
+/// Opens a second connection and brings it to the state of the live one: logged in if
+/// the live one is, and subscribed to every registered channel.
+///
+/// Notifications received before the switch are dropped, the live connection still
+/// delivers them.
+async fn prepare_standby(url: String, ctx: ConnectionContext) -> Result<WsStream, Error> {
+    timeout(STANDBY_TIMEOUT, async {
+        let mut ws = yawc::WebSocket::connect(url.parse()?).await?;
+        let logged_in = *ctx.logged_in.borrow();
+        if logged_in {
+            let token = make_auth_token(&ctx.login_state.key_id, &ctx.login_state.private_key_pem)?;
+            let params = serde_json::json!({
+                "token": token,
+                "account": &ctx.login_state.account_id
+            });
+            standby_request(&mut ws, rpc_frame(&ctx, "public/login", params)).await?;
+            // Orders stay protected across the switch.
+            let cancel_on_disconnect = ctx.cancel_on_disconnect.borrow().timeout_secs;
+            if let Some(timeout_secs) = cancel_on_disconnect {
+                let params = serde_json::json!({ "timeout_secs": timeout_secs });
+                standby_request(
+                    &mut ws,
+                    rpc_frame(&ctx, "private/set_cancel_on_disconnect", params),
+                )
+                .await?;
+            }
+        }
+        let mut public_channels: Vec<String> = ctx
+            .public_subscriptions
+            .iter()
+            .map(|e| e.key().clone())
+            .collect();
+        if !public_channels.iter().any(|c| c == SYSTEM_CHANNEL) {
+            public_channels.push(SYSTEM_CHANNEL.to_string());
+        }
+        let private_channels: Vec<String> = ctx
+            .private_subscriptions
+            .iter()
+            .map(|e| e.key().clone())
+            .collect();
+        for (scope, channels) in [
+            (RequestScope::Public, public_channels),
+            (RequestScope::Private, private_channels),
+        ] {
+            if channels.is_empty() || (matches!(scope, RequestScope::Private) && !logged_in) {
+                continue;
+            }
+            let params = serde_json::json!({ "channels": channels });
+            standby_request(
+                &mut ws,
+                rpc_frame(&ctx, &format!("{scope}/subscribe"), params),
+            )
+            .await?;
+            debug!("Standby connection subscribed to {scope} channels: {channels:?}");
+        }
+        Ok(ws)
+    })
+    .await
+    .map_err(|_| -> Error { "standby connection timed out".into() })?
+}
+
+/// Sends one request on a connection nobody else is reading and waits for its response.
+async fn standby_request(ws: &mut WsStream, (id, frame): (u64, Frame)) -> Result<(), Error> {
+    ws.send(frame).await?;
+    while let Some(frame) = ws.next().await {
+        match frame.opcode() {
+            OpCode::Text | OpCode::Binary => {
+                let payload = frame.into_payload();
+                if extract_id(&payload) != Some(id) {
+                    continue;
+                }
+                let response: Value = serde_json::from_slice(&payload)?;
+                if let Some(error) = response.get("error") {
+                    return Err(format!("standby request failed: {error}").into());
+                }
+                return Ok(());
+            }
+            OpCode::Ping => ws.send(Frame::pong(Vec::default())).await?,
+            OpCode::Close => break,
+            _ => {}
+        }
+    }
+    Err("standby connection closed".into())
+}
+
+/// Keeps a replaced connection open until requests sent on it have been answered.
+///
+/// Everything else it receives is dropped, the new connection delivers subscriptions.
+async fn retire_connection(
+    url: String,
+    mut ws: WsStream,
+    pending_requests: Arc<DashMap<u64, ResponseSender>>,
+) {
+    let in_flight: Vec<u64> = pending_requests.iter().map(|e| *e.key()).collect();
+    let deadline = sleep(RETIRE_TIMEOUT);
+    tokio::pin!(deadline);
+    while in_flight.iter().any(|id| pending_requests.contains_key(id)) {
+        tokio::select! {
+            msg = ws.next() => {
+                let Some(frame) = msg else {
+                    break;
+                };
+                if matches!(frame.opcode(), OpCode::Text | OpCode::Binary) {
+                    check_and_send_pending_request(&frame.into_payload(), &pending_requests);
+                }
+            }
+            _ = &mut deadline => break,
+        }
+    }
+    let _ = ws.close().await;
+    fail_pending_requests(&pending_requests, Some(&in_flight));
+    debug!("Retired replaced connection to {url}");
+}
+
+/// Answers outstanding requests with a connection error, either all of them or only `ids`.
+fn fail_pending_requests(pending_requests: &DashMap<u64, ResponseSender>, ids: Option<&[u64]>) {
+    let ids = match ids {
+        Some(ids) => ids.to_vec(),
+        None => pending_requests.iter().map(|e| *e.key()).collect(),
+    };
+    for key in ids {
+        if let Some((_, tx)) = pending_requests.remove(&key) {
+            let _ = tx.send(r#"{"error":"connection closed"}"#.into());
+        }
+    }
+}
+
+fn lock_handlers(handlers: &HandlerList) -> std::sync::MutexGuard<'_, Vec<(u64, ChannelHandler)>> {
+    handlers
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
 #[inline(always)]
 pub fn handle_incoming(
     bytes: Bytes,
@@ -0,0 +1,64 @@
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    manual_models::book::{BookLevel, BookTrade},
+    models::{BookNotification, BookRpcResult, DirectionEnum, LwtNotification},
+};
+
+#[test]
+fn book_notification_parses_typed_levels() {
+    let raw = r#"{"channel_name":"book.BTC-PERPETUAL.none.10.raw","notification":{
+        "trades":[[9120.5,15,"buy",156789.103,false],[9121,30,"sell",156789.123,true]],
+        "bid_changes":[[9120.5,0,0],[9121,54,53]],
+        "ask_changes":[[9122,1.5,1.5]],
+        "total_bid_amount":45,"total_ask_amount":30,"time":1683901254.785744}}"#;
+    let msg: BookNotification = serde_json::from_str(raw).unwrap();
+    let book = msg.notification;
+
+    let bids = book.bid_changes.unwrap();
+    assert!(bids[0].is_empty());
+    assert_eq!(
+        bids[1],
+        BookLevel {
+            price: dec!(9121),
+            amount: dec!(54),
+            outright_amount: Some(dec!(53)),
+        }
+    );
+    assert_eq!(book.ask_changes.unwrap()[0].amount, dec!(1.5));
+
+    let trades = book.trades.unwrap();
+    assert_eq!(
+        trades[1],
+        BookTrade {
+            price: dec!(9121),
+            amount: dec!(30),
+            direction: DirectionEnum::Sell,
+            time: dec!(156789.123),
+            implied_taker: true,
+        }
+    );
+}
+
+#[test]
+fn lwt_and_snapshot_share_level_type() {
+    let raw = r#"{"channel_name":"lwt.BTC-PERPETUAL.1000ms","notification":{
+        "b":[47240,0.4,0.4],"a":[47260,1,0.9],"l":47252.64,"m":65536.16}}"#;
+    let lwt: LwtNotification = serde_json::from_str(raw).unwrap();
+    let best_bid = lwt.notification.b.unwrap();
+    assert_eq!(best_bid.price, dec!(47240));
+    assert_eq!(lwt.notification.a.unwrap().outright_amount, Some(dec!(0.9)));
+
+    let raw = r#"{"bids":[[26490,0.001,0.001]],"asks":[],"last":26500,"time":1683901254.7}"#;
+    let snapshot: BookRpcResult = serde_json::from_str(raw).unwrap();
+    assert_eq!(snapshot.bids[0].price, dec!(26490));
+    assert!(snapshot.asks.is_empty());
+}
+
+#[test]
+fn book_level_round_trips() {
+    let level = BookLevel::new(dec!(100.5), dec!(2));
+    let json = serde_json::to_string(&level).unwrap();
+    assert_eq!(json, "[\"100.5\",\"2\"]");
+    let parsed: BookLevel = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, level);
+}
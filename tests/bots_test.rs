@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    bots::{BotEvent, BotMonitor, BotValidationError, DHedgeBuilder, GridBuilder, SgslBuilder},
+    models::{
+        Bot, CreateBotParams, Grid,
+        bot::StopReason,
+        grid::{Status, StopReason as GridStopReason},
+        sgsl_1::Signal,
+    },
+};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+const NOW: rust_decimal::Decimal = dec!(1_700_000_000);
+
+fn grid_bot(bot_id: &str, status: Status, realized_pnl: rust_decimal::Decimal) -> Bot {
+    Bot::Grid(Grid {
+        bot_id: bot_id.to_string(),
+        status,
+        strategy: "grid".to_string(),
+        instrument_name: "BTC-PERPETUAL".to_string(),
+        realized_pnl,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn builders_reject_inconsistent_parameters() {
+    let grid = || {
+        GridBuilder::new(
+            "BTC-PERPETUAL",
+            vec![dec!(80000), dec!(90000), dec!(100000)],
+            dec!(0.01),
+            NOW + dec!(3600),
+        )
+    };
+    let Ok(CreateBotParams::Grid1(params)) = grid()
+        .exit_prices(dec!(110000), dec!(70000))
+        .base_position(dec!(0), dec!(90000))
+        .build_at(NOW)
+    else {
+        panic!("valid grid rejected");
+    };
+    assert_eq!(params.strategy, "grid");
+
+    assert_eq!(
+        GridBuilder::new(
+            "BTC-PERPETUAL",
+            vec![dec!(80000), dec!(80000)],
+            dec!(0.01),
+            NOW + dec!(3600)
+        )
+        .build_at(NOW),
+        Err(BotValidationError::NotMonotonic("grid"))
+    );
+    assert!(matches!(
+        grid().exit_prices(dec!(95000), dec!(70000)).build_at(NOW),
+        Err(BotValidationError::Inconsistent(_))
+    ));
+    assert_eq!(
+        grid().build_at(NOW + dec!(3600)),
+        Err(BotValidationError::EndTimeInPast {
+            end_time: NOW + dec!(3600),
+            now: NOW + dec!(3600),
+        })
+    );
+
+    let sgsl = SgslBuilder::new(
+        "BTC-PERPETUAL",
+        Signal::Mark,
+        dec!(90000),
+        dec!(1),
+        dec!(95000),
+        dec!(0),
+        NOW + dec!(60),
+    );
+    assert!(matches!(
+        sgsl.build_at(NOW),
+        Err(BotValidationError::Inconsistent(_))
+    ));
+    // Without an end time a delta hedger runs until cancelled.
+    assert!(
+        DHedgeBuilder::new("BTC-PERPETUAL", dec!(60))
+            .band(dec!(0.1), dec!(0.05))
+            .build_at(NOW)
+            .is_ok()
+    );
+    assert!(
+        DHedgeBuilder::new("BTC-PERPETUAL", dec!(60))
+            .band(dec!(0.1), dec!(0.2))
+            .build_at(NOW)
+            .is_err()
+    );
+}
+
+#[test]
+fn monitor_diffs_snapshots() {
+    let monitor = BotMonitor::new();
+
+    // The first snapshot, with what was running or stopped before, only seeds.
+    let events = monitor.apply(vec![
+        grid_bot("a", Status::Active, dec!(0)),
+        grid_bot("old", Status::Stopped, dec!(5)),
+    ]);
+    assert!(events.is_empty());
+    assert_eq!(monitor.active().len(), 1);
+
+    let mut stopped = grid_bot("a", Status::Stopped, dec!(2));
+    if let Bot::Grid(grid) = &mut stopped {
+        grid.stop_reason = Some(GridStopReason::MarginBreach);
+    }
+    let events = monitor.apply(vec![
+        grid_bot("c", Status::Active, dec!(0)),
+        grid_bot("b", Status::Active, dec!(0)),
+        stopped.clone(),
+        grid_bot("d", Status::Stopped, dec!(0)),
+    ]);
+    assert_eq!(
+        events,
+        vec![
+            BotEvent::Stopped {
+                bot: stopped,
+                reason: Some(StopReason::MarginBreach),
+            },
+            BotEvent::Started(grid_bot("b", Status::Active, dec!(0))),
+            BotEvent::Started(grid_bot("c", Status::Active, dec!(0))),
+        ]
+    );
+
+    let events = monitor.apply(vec![
+        grid_bot("b", Status::Active, dec!(1.5)),
+        grid_bot("c", Status::Active, dec!(0)),
+    ]);
+    assert_eq!(
+        events,
+        vec![BotEvent::PnlChanged {
+            bot_id: "b".to_string(),
+            previous: dec!(0),
+            realized_pnl: dec!(1.5),
+        }]
+    );
+    assert_eq!(monitor.active().len(), 2);
+
+    let events = monitor.apply(vec![]);
+    let ids: Vec<&str> = events.iter().map(BotEvent::bot_id).collect();
+    assert_eq!(ids, ["b", "c"]);
+    assert!(
+        events
+            .iter()
+            .all(|e| matches!(e, BotEvent::Stopped { reason: None, .. }))
+    );
+}
+
+#[tokio::test]
+async fn stop_unsubscribes_the_bots_channel() {
+    let (url, _connections, mut methods) = mock_exchange().await;
+    let client = Arc::new(logged_in_client(url).await);
+    let monitor = BotMonitor::new().start(client.clone()).await.unwrap();
+    monitor.stop(&client).await;
+
+    let seen: Vec<String> = std::iter::from_fn(|| methods.try_recv().ok()).collect();
+    assert!(
+        seen.ends_with(&["private/unsubscribe".to_string()]),
+        "{seen:?}"
+    );
+    client.shutdown("Test complete").await.unwrap();
+}
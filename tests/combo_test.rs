@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    combo::{Combo, ComboError, ComboLeg, ComboTemplates},
+    models::{DirectionEnum, Instrument, OptionTypeEnum, TimeInForceEnum},
+};
+
+fn templates() -> ComboTemplates {
+    let instruments = Arc::new(DashMap::new());
+    let options = [
+        (
+            "BTC-27JUN25-90000-C",
+            "2025-06-27",
+            "option",
+            Some((90000, "call")),
+        ),
+        (
+            "BTC-27JUN25-90000-P",
+            "2025-06-27",
+            "option",
+            Some((90000, "put")),
+        ),
+        (
+            "BTC-27JUN25-100000-C",
+            "2025-06-27",
+            "option",
+            Some((100000, "call")),
+        ),
+        ("BTC-27JUN25", "2025-06-27", "future", None),
+        ("BTC-26SEP25", "2025-09-26", "future", None),
+    ];
+    for (name, expiry, kind, option) in options {
+        let option_fields = option.map_or(String::new(), |(strike, option_type)| {
+            format!(r#","strike_price":{strike},"option_type":"{option_type}""#)
+        });
+        let raw = format!(
+            r#"{{"instrument_name":"{name}","underlying":"BTCUSD","type":"{kind}",
+            "expiry_date":"{expiry}"{option_fields}}}"#
+        );
+        let instrument: Instrument = serde_json::from_str(&raw).unwrap();
+        instruments.insert(name.to_string(), instrument);
+    }
+    ComboTemplates::new(instruments)
+}
+
+#[test]
+fn templates_resolve_legs_from_the_cache() {
+    let templates = templates();
+    let straddle = templates
+        .straddle("BTCUSD", "2025-06-27", dec!(90000))
+        .unwrap();
+    assert_eq!(
+        straddle.book_name(),
+        "[BTC-27JUN25-90000-P:1,BTC-27JUN25-90000-C:1]"
+    );
+    let vertical = templates
+        .vertical(
+            "BTCUSD",
+            "2025-06-27",
+            OptionTypeEnum::Call,
+            dec!(90000),
+            dec!(100000),
+        )
+        .unwrap();
+    assert_eq!(
+        vertical.legs(),
+        &[
+            ComboLeg::new("BTC-27JUN25-90000-C", dec!(1)),
+            ComboLeg::new("BTC-27JUN25-100000-C", dec!(-1)),
+        ]
+    );
+    let roll = templates
+        .futures_roll("BTCUSD", "2025-06-27", "2025-09-26")
+        .unwrap();
+    assert_eq!(roll.book_name(), "[BTC-27JUN25:-1,BTC-26SEP25:1]");
+    assert!(matches!(
+        templates.straddle("BTCUSD", "2025-06-27", dec!(95000)),
+        Err(ComboError::InstrumentNotFound(_))
+    ));
+}
+
+#[test]
+fn legs_convert_to_every_api() {
+    let combo = Combo::new(vec![
+        ComboLeg::new("BTC-27JUN25-90000-C", dec!(1)),
+        ComboLeg::new("BTC-27JUN25-100000-C", dec!(-2)),
+    ])
+    .unwrap();
+    let insert = combo.insert_params(DirectionEnum::Sell, dec!(0.5), dec!(1200));
+    assert_eq!(insert.instrument_name, None);
+    assert_eq!(insert.legs.unwrap()[1].quantity, dec!(-2));
+    assert_eq!(
+        insert.time_in_force,
+        Some(TimeInForceEnum::ImmediateOrCancel)
+    );
+    assert_eq!(
+        combo.margin_params(dec!(1), dec!(1200)).legs.unwrap().len(),
+        2
+    );
+    assert_eq!(combo.rfq_legs()[1].fee_quantity, dec!(2));
+    assert_eq!(combo.inverted().legs()[0].quantity, dec!(-1));
+
+    assert_eq!(
+        Combo::new(vec![ComboLeg::new("BTC-PERPETUAL", dec!(1))]),
+        Err(ComboError::LegCount(1))
+    );
+    assert!(matches!(
+        Combo::new(vec![
+            ComboLeg::new("BTC-PERPETUAL", dec!(1)),
+            ComboLeg::new("BTC-27JUN25", dec!(0.5)),
+        ]),
+        Err(ComboError::InvalidQuantity { .. })
+    ));
+}
@@ -0,0 +1,181 @@
+//! A stand-in for the exchange's websocket API, for tests that need a live connection.
+// Each test binary includes this file and uses only part of it.
+#![allow(dead_code)]
+
+use serde_json::{Value, json};
+use thalex_rust_sdk::{types::Environment, ws_client::WsClient};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+};
+
+/// Throwaway key for logging in to the mock server; it is registered nowhere.
+const MOCK_KEY: &str = include_str!("mock_key.pem");
+
+/// Server side of one websocket connection, just enough to stand in for the exchange.
+struct MockConnection {
+    stream: TcpStream,
+}
+
+impl MockConnection {
+    async fn accept(listener: &TcpListener) -> MockConnection {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+        stream
+            .write_all(
+                b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                Connection: Upgrade\r\nSec-WebSocket-Accept: mock\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        MockConnection { stream }
+    }
+
+    /// Next text message from the client, answering pings on the way.
+    async fn recv(&mut self) -> Option<Value> {
+        loop {
+            let opcode = self.stream.read_u8().await.ok()? & 0x0f;
+            let len = self.stream.read_u8().await.ok()?;
+            let masked = len & 0x80 != 0;
+            let len = match len & 0x7f {
+                126 => self.stream.read_u16().await.ok()? as usize,
+                127 => self.stream.read_u64().await.ok()? as usize,
+                n => n as usize,
+            };
+            let mut mask = [0u8; 4];
+            if masked {
+                self.stream.read_exact(&mut mask).await.ok()?;
+            }
+            let mut payload = vec![0u8; len];
+            self.stream.read_exact(&mut payload).await.ok()?;
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+            match opcode {
+                0x1 | 0x2 => return serde_json::from_slice(&payload).ok(),
+                0x8 => return None,
+                0x9 => self.write_frame(0xa, &payload).await,
+                _ => {}
+            }
+        }
+    }
+
+    async fn send(&mut self, msg: &Value) {
+        self.write_frame(0x1, msg.to_string().as_bytes()).await;
+    }
+
+    async fn write_frame(&mut self, opcode: u8, payload: &[u8]) {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            n if n < 126 => frame.push(n as u8),
+            n if n <= u16::MAX as usize => {
+                frame.push(126);
+                frame.extend((n as u16).to_be_bytes());
+            }
+            n => {
+                frame.push(127);
+                frame.extend((n as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        let _ = self.stream.write_all(&frame).await;
+    }
+
+    /// Reports every request's method, answers it as a successful call would be, and
+    /// sends whatever is pushed.
+    async fn serve(mut self, methods: UnboundedSender<String>, mut push: UnboundedReceiver<Value>) {
+        loop {
+            tokio::select! {
+                request = self.recv() => {
+                    let Some(request) = request else {
+                        break;
+                    };
+                    let method = request["method"].as_str().unwrap_or_default().to_string();
+                    let result = match method.as_str() {
+                        "public/subscribe" | "private/subscribe" => request["params"]["channels"].clone(),
+                        "public/instruments" | "private/open_orders" | "private/portfolio" => json!([]),
+                        "private/order_history" => order_history_page(&request["params"]),
+                        "private/mass_quote" => json!({
+                            "n_success": 1,
+                            "n_fail": 1,
+                            "errors": [{"code": 3, "message": "insufficient margin", "side": "buy", "price": 90000}],
+                        }),
+                        "public/book" => json!({
+                            "bids": [[99.5, 1], [99.2, 2], [98, 1]],
+                            "asks": [[100.5, 1], [101, 2]],
+                            "time": 10,
+                        }),
+                        _ => json!({}),
+                    };
+                    let _ = methods.send(method);
+                    self.send(&json!({ "id": request["id"], "result": result })).await;
+                }
+                Some(msg) = push.recv() => self.send(&msg).await,
+            }
+        }
+    }
+}
+
+/// Two pages of history holding client order id 7 twice: an older order for 2 on the first
+/// page, and one for 1 on the second.
+fn order_history_page(params: &Value) -> Value {
+    let order = |order_id: &str, amount: u32| {
+        json!({
+            "order_id": order_id, "order_type": "limit", "instrument_name": "BTC-PERPETUAL",
+            "direction": "buy", "price": 90000, "amount": amount, "filled_amount": 0,
+            "client_order_id": 7, "status": "cancelled", "fills": [],
+            "delete_reason": "client_cancel", "insert_reason": "client_request",
+            "create_time": 1700000000, "close_time": 1700000060,
+        })
+    };
+    match params["bookmark"].as_str() {
+        None => json!({ "orders": [order("0001", 2)], "bookmark": "page-2" }),
+        Some(_) => json!({ "orders": [order("0002", 1)] }),
+    }
+}
+
+/// Starts a mock exchange. Yields a sender per accepted connection for pushing
+/// notifications, and the methods of all requests received.
+pub async fn mock_exchange() -> (
+    String,
+    UnboundedReceiver<UnboundedSender<Value>>,
+    UnboundedReceiver<String>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (connections_tx, connections) = unbounded_channel();
+    let (methods_tx, methods) = unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let connection = MockConnection::accept(&listener).await;
+            let (push_tx, push) = unbounded_channel();
+            let _ = connections_tx.send(push_tx);
+            tokio::spawn(connection.serve(methods_tx.clone(), push));
+        }
+    });
+    (url, connections, methods)
+}
+
+/// A client connected to the mock exchange at `url` and logged in.
+pub async fn logged_in_client(url: String) -> WsClient {
+    let client = WsClient::new(
+        Environment::Custom(url),
+        "mock".to_string(),
+        None,
+        MOCK_KEY.to_string(),
+    )
+    .await
+    .unwrap();
+    client.wait_for_connection().await;
+    client.login().await.unwrap();
+    client
+}
+
+/// The `system` notification announcing that the server will close the connection.
+pub fn reconnect_event() -> Value {
+    json!({ "channel_name": "system", "notification": { "event": "reconnect" } })
+}
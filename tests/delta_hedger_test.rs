@@ -0,0 +1,93 @@
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    delta_hedger::{DeltaHedger, HedgeConfig},
+    models::{DirectionEnum, OrderTypeEnum, Ticker, TimeInForceEnum},
+    ticks::TickSpec,
+};
+
+fn spec() -> TickSpec {
+    TickSpec::new(dec!(1), dec!(0.001), Some(dec!(0.001))).unwrap()
+}
+
+fn ticker(
+    bid: rust_decimal::Decimal,
+    mark: rust_decimal::Decimal,
+    ask: rust_decimal::Decimal,
+) -> Ticker {
+    Ticker {
+        best_bid_price: Some(bid),
+        best_ask_price: Some(ask),
+        mark_price: mark,
+        ..Default::default()
+    }
+}
+
+fn hedger(config: HedgeConfig) -> DeltaHedger {
+    let hedger = DeltaHedger::new(
+        config
+            .instruments(["BTC-PERPETUAL", "BTC-27MAR26"])
+            .band(dec!(0.1), dec!(0.02))
+            .max_slippage_bps(dec!(20)),
+    );
+    for name in ["BTC-PERPETUAL", "BTC-27MAR26"] {
+        hedger.set_tick_spec(name, spec());
+    }
+    hedger.update_ticker(
+        "BTC-PERPETUAL",
+        ticker(dec!(99990), dec!(100000), dec!(100010)),
+    );
+    // The future's bid is closer to its mark than the perpetual's, its ask further.
+    hedger.update_ticker(
+        "BTC-27MAR26",
+        ticker(dec!(100995), dec!(101000), dec!(101030)),
+    );
+    hedger
+}
+
+#[test]
+fn hedges_outside_the_band_on_the_cheapest_instrument() {
+    let hedger = hedger(HedgeConfig::new("BTC"));
+    assert_eq!(
+        hedger.cost_bps("BTC-PERPETUAL", DirectionEnum::Buy),
+        Some(dec!(1))
+    );
+    assert!(hedger.plan(dec!(0.05)).is_none());
+    assert!(hedger.plan(dec!(-0.1)).is_none());
+
+    let sell = hedger.plan(dec!(0.5)).unwrap();
+    assert_eq!(sell.instrument_name, "BTC-27MAR26");
+    assert_eq!(sell.direction, DirectionEnum::Sell);
+    // Back to the 0.02 tolerance, limit 20 bps under the mark.
+    assert_eq!(sell.amount, dec!(0.48));
+    assert_eq!(sell.limit_price, dec!(100798));
+
+    let buy = hedger.plan(dec!(-0.5)).unwrap();
+    assert_eq!(buy.instrument_name, "BTC-PERPETUAL");
+    assert_eq!(buy.limit_price, dec!(100200));
+    let params = buy.params(Some("hedge".to_string()));
+    assert_eq!(params.order_type, Some(OrderTypeEnum::Limit));
+    assert_eq!(
+        params.time_in_force,
+        Some(TimeInForceEnum::ImmediateOrCancel)
+    );
+}
+
+#[test]
+fn skips_small_hedges_and_wide_markets() {
+    let hedger = hedger(HedgeConfig::new("BTC").min_size(dec!(0.5)).dry_run());
+    assert!(hedger.config().dry_run);
+    assert!(hedger.plan(dec!(0.5)).is_none());
+    assert!(hedger.plan(dec!(0.6)).is_some());
+
+    hedger.update_ticker(
+        "BTC-PERPETUAL",
+        ticker(dec!(99990), dec!(100000), dec!(100300)),
+    );
+    // Both asks are now more than 20 bps over mark.
+    hedger.update_ticker(
+        "BTC-27MAR26",
+        ticker(dec!(100995), dec!(101000), dec!(101400)),
+    );
+    assert!(hedger.plan(dec!(-0.6)).is_none());
+    assert!(hedger.plan(dec!(0.6)).is_some());
+}
@@ -0,0 +1,184 @@
+use std::sync::{Arc, Mutex};
+
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    emulated_orders::{Comparison, ConditionalEngine, Trigger},
+    models::{ConditionalOrderStatusEnum, DirectionEnum, IndexComponents, InsertParams, Ticker},
+};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+fn order(instrument_name: &str) -> InsertParams {
+    InsertParams {
+        direction: DirectionEnum::Buy,
+        instrument_name: Some(instrument_name.to_string()),
+        amount: dec!(0.1),
+        price: Some(dec!(100)),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn triggers_fire_once_when_met() {
+    let engine = ConditionalEngine::new();
+    let statuses = Arc::new(Mutex::new(Vec::new()));
+    let seen = statuses.clone();
+    engine.on_status(move |order| seen.lock().unwrap().push(order.status));
+
+    let iv = engine.track(
+        Trigger::Iv {
+            instrument_name: "BTC-27MAR26-100000-C".to_string(),
+            comparison: Comparison::Above,
+            threshold: dec!(0.8),
+        },
+        order("BTC-27MAR26-100000-C"),
+    );
+    let spread = engine.track(
+        Trigger::Spread {
+            instrument_name: "BTC-27MAR26".to_string(),
+            other: "BTC-PERPETUAL".to_string(),
+            comparison: Comparison::Below,
+            threshold: dec!(-10),
+        },
+        order("BTC-27MAR26"),
+    );
+    let later = engine.track(
+        Trigger::Time {
+            at: dec!(4_000_000_000),
+        },
+        order("BTC-PERPETUAL"),
+    );
+    assert_eq!(
+        *statuses.lock().unwrap(),
+        [
+            ConditionalOrderStatusEnum::Created,
+            ConditionalOrderStatusEnum::Active
+        ]
+        .repeat(3)
+    );
+    assert_eq!(iv.stop_price, dec!(0.8));
+
+    let option = |iv| Ticker {
+        iv: Some(iv),
+        mark_price: dec!(5000),
+        ..Default::default()
+    };
+    assert!(
+        engine
+            .apply_ticker("BTC-27MAR26-100000-C", &option(dec!(0.7)))
+            .is_empty()
+    );
+    let fired = engine.apply_ticker("BTC-27MAR26-100000-C", &option(dec!(0.81)));
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].order_id, iv.order_id);
+    assert!(
+        engine
+            .apply_ticker("BTC-27MAR26-100000-C", &option(dec!(0.9)))
+            .is_empty()
+    );
+
+    let mark = |mark_price| Ticker {
+        mark_price,
+        ..Default::default()
+    };
+    // Only one leg known yet.
+    assert!(
+        engine
+            .apply_ticker("BTC-27MAR26", &mark(dec!(90000)))
+            .is_empty()
+    );
+    assert!(
+        engine
+            .apply_ticker("BTC-PERPETUAL", &mark(dec!(90005)))
+            .is_empty()
+    );
+    let fired = engine.apply_ticker("BTC-PERPETUAL", &mark(dec!(90010)));
+    assert_eq!(fired[0].order_id, spread.order_id);
+
+    assert!(engine.take_triggered(dec!(3_999_999_999)).is_empty());
+    assert_eq!(
+        engine.take_triggered(dec!(4_000_000_000))[0].order_id,
+        later.order_id
+    );
+    assert!(engine.pending().is_empty());
+}
+
+#[test]
+fn pending_triggers_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!("emulated-orders-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let engine = ConditionalEngine::new().with_persistence(&path);
+    let divergence = Trigger::IndexDivergence {
+        underlying: "BTCUSD".to_string(),
+        component: None,
+        threshold_bps: dec!(50),
+    };
+    let kept = engine.track(divergence.clone(), order("BTC-PERPETUAL"));
+    let cancelled = engine.track(Trigger::Time { at: dec!(2000) }, order("BTC-PERPETUAL"));
+    assert_eq!(
+        engine.cancel(&cancelled.order_id).unwrap().status,
+        ConditionalOrderStatusEnum::Cancelled
+    );
+
+    let restarted = ConditionalEngine::new().with_persistence(&path);
+    assert_eq!(restarted.restore().unwrap(), 1);
+    assert_eq!(restarted.get(&kept.order_id), Some(kept.clone()));
+
+    let components = |binance: &str| IndexComponents {
+        index_price: Some(dec!(100000)),
+        components: Some(serde_json::json!({ "coinbase": "100010", "binance": binance })),
+    };
+    // 40 bps off the index.
+    assert!(
+        restarted
+            .apply_index_components("BTCUSD", &components("100400"))
+            .is_empty()
+    );
+    let fired = restarted.apply_index_components("BTCUSD", &components("99500"));
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].trigger, divergence);
+
+    assert_eq!(
+        ConditionalEngine::new()
+            .with_persistence(&path)
+            .restore()
+            .unwrap(),
+        0
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn a_stopped_engine_starts_again() {
+    let (url, _connections, mut methods) = mock_exchange().await;
+    let client = Arc::new(logged_in_client(url).await);
+    let engine = ConditionalEngine::new();
+    engine.track(
+        Trigger::Iv {
+            instrument_name: "BTC-27MAR26-100000-C".to_string(),
+            comparison: Comparison::Above,
+            threshold: dec!(0.8),
+        },
+        order("BTC-27MAR26-100000-C"),
+    );
+    let mut next_method = async || {
+        tokio::time::timeout(std::time::Duration::from_secs(5), methods.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    };
+
+    let engine = engine.start(client.clone()).await.unwrap();
+    while next_method().await != "public/subscribe" {}
+    engine.stop();
+    while next_method().await != "public/unsubscribe" {}
+    let engine = engine.start(client.clone()).await.unwrap();
+    while next_method().await != "public/subscribe" {}
+
+    assert_eq!(engine.pending().len(), 1);
+    engine.stop();
+    client.shutdown("Test complete").await.unwrap();
+}
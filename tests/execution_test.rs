@@ -0,0 +1,70 @@
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    execution::{DisplaySizer, ExecutionProgress, twap_slice, vwap_slice},
+    models::{DirectionEnum, RecentTradesNotification},
+    ticks::TickSpec,
+};
+
+#[test]
+fn slices_stay_on_the_lot_grid() {
+    let spec = TickSpec::new(dec!(1), dec!(0.001), Some(dec!(0.01))).unwrap();
+    let coarse = TickSpec::new(dec!(1), dec!(0.01), None).unwrap();
+    assert_eq!(twap_slice(dec!(1), 3, &spec), dec!(0.333));
+    assert_eq!(twap_slice(dec!(0.334), 1, &spec), dec!(0.334));
+    assert_eq!(
+        vwap_slice(dec!(1), dec!(12.34), dec!(0.05), &coarse, false),
+        dec!(0.61)
+    );
+    assert_eq!(
+        vwap_slice(dec!(0.2), dec!(12.34), dec!(0.05), &coarse, false),
+        dec!(0.2)
+    );
+    assert_eq!(
+        vwap_slice(dec!(0.37), dec!(0), dec!(0.05), &coarse, true),
+        dec!(0.37)
+    );
+
+    let mut sizer = DisplaySizer::with_seed(dec!(0.1), dec!(0.2), &spec, 42);
+    let mut remaining = dec!(1.005);
+    let mut sizes = Vec::new();
+    while !remaining.is_zero() {
+        let size = sizer.next(remaining);
+        remaining -= size;
+        sizes.push(size);
+    }
+    let (last, shown) = sizes.split_last().unwrap();
+    for size in shown {
+        assert!((dec!(0.08)..=dec!(0.12)).contains(size), "{size}");
+        assert!((size / dec!(0.001)).fract().is_zero());
+    }
+    // The leftover below the minimum amount is folded into the last child.
+    assert!(*last >= dec!(0.01));
+    assert!(sizes.windows(2).any(|w| w[0] != w[1]));
+}
+
+#[test]
+fn progress_reports_average_and_slippage() {
+    let mut buy = ExecutionProgress::new(DirectionEnum::Buy, dec!(1), Some(dec!(100000)));
+    assert_eq!(buy.average_price(), None);
+    buy.apply_fill(dec!(100010), dec!(0.4));
+    buy.apply_fill(dec!(100030), dec!(0.4));
+    assert_eq!(buy.remaining(), dec!(0.2));
+    assert_eq!(buy.average_price(), Some(dec!(100020)));
+    assert_eq!(buy.slippage_bps(), Some(dec!(2)));
+
+    let mut sell = ExecutionProgress::new(DirectionEnum::Sell, dec!(1), Some(dec!(100000)));
+    sell.apply_fill(dec!(100050), dec!(1.5));
+    assert_eq!(sell.remaining(), Decimal::ZERO);
+    assert_eq!(sell.slippage_bps(), Some(dec!(-5)));
+
+    let msg: RecentTradesNotification = serde_json::from_str(
+        r#"{"channel_name":"recent_trades.BTC-PERPETUAL.single","notification":[
+            [400, 1, "buy", 1652187265.515, "BTC-PERPETUAL", false],
+            [401, 0.5, "sell", 1652187266.1, "BTC-PERPETUAL"]]}"#,
+    )
+    .unwrap();
+    let sizes: Vec<Decimal> = msg.notification.iter().map(|t| t.size).collect();
+    assert_eq!(sizes, vec![dec!(1), dec!(0.5)]);
+    assert_eq!(msg.notification[1].side, DirectionEnum::Sell);
+}
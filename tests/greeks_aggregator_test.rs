@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    greeks_aggregator::{GreeksAggregator, GreeksBucket},
+    manual_models::instrument_id::{Expiry, InstrumentId},
+    models::{Instrument, OptionTypeEnum, PortfolioMarginBreakdown, Ticker, TypeEnum},
+    pricing::{Black76, year_fraction},
+};
+
+const NOW: i64 = 1_735_000_000;
+const EXPIRY: i64 = 1_735_286_400; // 27DEC24
+
+fn aggregator() -> GreeksAggregator {
+    let mut aggregator = GreeksAggregator::new();
+    for (name, r#type) in [
+        ("BTC-PERPETUAL", TypeEnum::Perpetual),
+        ("BTC-27DEC24", TypeEnum::Future),
+        ("BTC-27DEC24-100000-C", TypeEnum::Option),
+    ] {
+        aggregator.set_instrument(Instrument {
+            instrument_name: Some(name.to_string()),
+            underlying: Some("BTCUSD".to_string()),
+            r#type: Some(r#type),
+            option_type: (r#type == TypeEnum::Option).then_some(OptionTypeEnum::Call),
+            strike_price: Some(dec!(100000)),
+            expiration_timestamp: Some(EXPIRY as i32),
+            ..Default::default()
+        });
+    }
+    aggregator.update_ticker(
+        "BTC-27DEC24-100000-C",
+        Ticker {
+            mark_timestamp: NOW.into(),
+            delta: dec!(0.4),
+            index: dec!(100000),
+            forward: Some(dec!(100000)),
+            iv: Some(dec!(0.5)),
+            ..Default::default()
+        },
+    );
+    aggregator.set_positions(HashMap::from([
+        ("BTC-PERPETUAL".parse().unwrap(), dec!(-1)),
+        ("BTC-27DEC24".parse().unwrap(), dec!(0.5)),
+        ("BTC-27DEC24-100000-C".parse().unwrap(), dec!(2)),
+        (
+            "ETH-27DEC24-3000-C".parse::<InstrumentId>().unwrap(),
+            dec!(1),
+        ),
+    ]));
+    aggregator
+}
+
+#[test]
+fn buckets_delta_one_and_option_greeks() {
+    let (buckets, gaps) = aggregator().by_bucket();
+    let model = Black76::new(
+        OptionTypeEnum::Call,
+        100000.0,
+        100000.0,
+        year_fraction(NOW as f64, EXPIRY as f64),
+        0.5,
+    )
+    .greeks();
+
+    let perp = &buckets[&GreeksBucket {
+        base: "BTC".to_string(),
+        expiry: None,
+    }];
+    assert_eq!(perp.delta, -1.0);
+    let dec_bucket = &buckets[&GreeksBucket {
+        base: "BTC".to_string(),
+        expiry: Some(Expiry::new(2024, 12, 27).unwrap()),
+    }];
+    assert!((dec_bucket.delta - 1.3).abs() < 1e-12);
+    assert!((dec_bucket.vega - 2.0 * model.vega).abs() < 1e-9);
+    assert!((dec_bucket.gamma - 2.0 * model.gamma).abs() < 1e-15);
+
+    assert_eq!(gaps.len(), 1);
+    assert_eq!(gaps[0].0, "ETH-27DEC24-3000-C");
+}
+
+#[test]
+fn compares_delta_with_margin_scenarios() {
+    let breakdown: PortfolioMarginBreakdown = serde_json::from_str(
+        r#"{"portfolio":{"required_margin":100,"underlyings":[{"underlying":"BTCUSD","scenarios":[
+            {"underlying_change_pct":-10,"vol_change_pct_point":0,"pnl":-3100},
+            {"underlying_change_pct":10,"vol_change_pct_point":0,"pnl":2900},
+            {"underlying_change_pct":10,"vol_change_pct_point":20,"pnl":5000}]}]}}"#,
+    )
+    .unwrap();
+    let checks = aggregator().compare_delta(&breakdown);
+
+    assert_eq!(checks.len(), 1);
+    assert_eq!(checks[0].underlying, "BTCUSD");
+    assert!((checks[0].computed - 0.3).abs() < 1e-12);
+    // (2900 + 3100) / 0.2 / 100000
+    assert!((checks[0].exchange.unwrap() - 0.3).abs() < 1e-12);
+    assert!(checks[0].difference().unwrap().abs() < 1e-12);
+}
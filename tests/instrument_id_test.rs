@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    manual_models::instrument_id::{Expiry, InstrumentId, InstrumentIdError, InstrumentKind},
+    models::{Instrument, OptionTypeEnum, TypeEnum},
+};
+
+#[test]
+fn parses_all_naming_schemes() {
+    let perp: InstrumentId = "BTC-PERPETUAL".parse().unwrap();
+    assert_eq!(perp.instrument_type(), TypeEnum::Perpetual);
+    assert_eq!(perp.base(), "BTC");
+
+    let future: InstrumentId = "BTC-27DEC24".parse().unwrap();
+    assert_eq!(future.expiry(), Some(Expiry::new(2024, 12, 27).unwrap()));
+
+    let option: InstrumentId = "BTC-03APR26-72000-P".parse().unwrap();
+    assert_eq!(option.strike(), Some(dec!(72000)));
+    assert_eq!(option.option_type(), Some(OptionTypeEnum::Put));
+    assert_eq!(option.expiry().unwrap().to_iso(), "2026-04-03");
+
+    let roll: InstrumentId = "BTC-25DEC26-25SEP26".parse().unwrap();
+    assert_eq!(
+        roll.kind(),
+        &InstrumentKind::Roll {
+            base: "BTC".to_string(),
+            first: Expiry::new(2026, 12, 25).unwrap(),
+            second: Expiry::new(2026, 9, 25).unwrap(),
+        }
+    );
+    assert_eq!(roll.instrument_type(), TypeEnum::Combination);
+
+    assert!(matches!(
+        "NOT_EXISTING".parse::<InstrumentId>(),
+        Err(InstrumentIdError::InvalidName(_))
+    ));
+    assert!("BTC-32DEC24".parse::<InstrumentId>().is_err());
+}
+
+#[test]
+fn formats_round_trip() {
+    for name in [
+        "ETH-PERPETUAL",
+        "BTC-24JUN25",
+        "BTC-25DEC26-120000-C",
+        "BTC-03APR26-02APR26",
+    ] {
+        let id: InstrumentId = name.parse().unwrap();
+        assert_eq!(id.to_string(), name);
+    }
+    let built = InstrumentId::option(
+        "BTC",
+        Expiry::new(2024, 12, 27).unwrap(),
+        dec!(100000.00),
+        OptionTypeEnum::Call,
+    );
+    assert_eq!(built, "BTC-27DEC24-100000-C");
+    let json = serde_json::to_string(&built).unwrap();
+    assert_eq!(serde_json::from_str::<InstrumentId>(&json).unwrap(), built);
+}
+
+#[test]
+fn rejects_days_past_the_end_of_the_month() {
+    for name in ["31FEB25", "29FEB25", "31APR26", "00JAN26"] {
+        assert!(matches!(
+            name.parse::<Expiry>(),
+            Err(InstrumentIdError::InvalidExpiry(_))
+        ));
+    }
+    assert!("BTC-31JUN26".parse::<InstrumentId>().is_err());
+    assert_eq!("29FEB28".parse::<Expiry>(), Expiry::new(2028, 2, 29));
+    assert!(Expiry::new(2100, 2, 29).is_err());
+    assert!(Expiry::from_iso("2000-02-29").is_ok());
+}
+
+#[test]
+fn checks_against_instrument() {
+    let instrument = Instrument {
+        instrument_name: Some("BTC-27DEC24-100000-C".to_string()),
+        r#type: Some(TypeEnum::Option),
+        option_type: Some(OptionTypeEnum::Call),
+        expiry_date: Some("2024-12-27".to_string()),
+        strike_price: Some(dec!(100000)),
+        ..Default::default()
+    };
+    let id = InstrumentId::try_from(&instrument).unwrap();
+    assert_eq!(id.strike(), Some(dec!(100000)));
+
+    let wrong = Instrument {
+        strike_price: Some(dec!(90000)),
+        ..instrument
+    };
+    assert!(matches!(
+        InstrumentId::try_from(&wrong),
+        Err(InstrumentIdError::Mismatch {
+            field: "strike_price",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn usable_as_map_key_by_name() {
+    let mut positions = HashMap::new();
+    positions.insert(InstrumentId::perpetual("BTC"), dec!(1.5));
+    assert_eq!(positions.get("BTC-PERPETUAL"), Some(&dec!(1.5)));
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    instrument_registry::{InstrumentEvent, InstrumentRegistry},
+    models::{Added, Instrument, InstrumentDelta, InstrumentsNotification, Removed, TypeEnum},
+};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+fn option(name: &str, strike: rust_decimal::Decimal, expiration_timestamp: i32) -> Instrument {
+    Instrument {
+        instrument_name: Some(name.to_string()),
+        underlying: Some("BTCUSD".to_string()),
+        r#type: Some(TypeEnum::Option),
+        expiry_date: Some("2024-12-27".to_string()),
+        expiration_timestamp: Some(expiration_timestamp),
+        strike_price: Some(strike),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn applies_channel_deltas_and_emits_events() {
+    let registry = InstrumentRegistry::new(Arc::new(DashMap::new()));
+    let mut events = registry.events();
+
+    let raw = r#"{"channel_name":"instruments","notification":[
+        {"added":{"instrument_name":"BTC-PERPETUAL","underlying":"BTCUSD","type":"perpetual"}},
+        {"added":{"instrument_name":"BTC-27DEC24","underlying":"BTCUSD","type":"future"}},
+        {"removed":{"instrument_name":"BTC-27DEC24"}}]}"#;
+    let msg: InstrumentsNotification = serde_json::from_str(raw).unwrap();
+    for delta in msg.notification {
+        registry.apply(delta);
+    }
+
+    assert_eq!(registry.len(), 1);
+    assert!(registry.get("BTC-PERPETUAL").is_some());
+    assert!(matches!(events.try_recv(), Ok(InstrumentEvent::Added(_))));
+    assert!(matches!(events.try_recv(), Ok(InstrumentEvent::Added(_))));
+    match events.try_recv() {
+        Ok(InstrumentEvent::Removed(i)) => {
+            assert_eq!(i.instrument_name.as_deref(), Some("BTC-27DEC24"))
+        }
+        other => panic!("expected removal, got {other:?}"),
+    }
+}
+
+#[test]
+fn sweeps_expired_and_queries_strikes() {
+    let registry = InstrumentRegistry::new(Arc::new(DashMap::new()));
+    for (name, strike, ts) in [
+        ("BTC-27DEC24-90000-C", dec!(90000), 2000),
+        ("BTC-27DEC24-100000-C", dec!(100000), 2000),
+        ("BTC-27DEC24-110000-C", dec!(110000), 2000),
+        ("BTC-26DEC24-100000-C", dec!(100000), 1000),
+    ] {
+        registry.apply(InstrumentDelta::Added(Added::new(option(name, strike, ts))));
+    }
+    let mut events = registry.events();
+
+    registry.sweep_expired(1500);
+    assert!(matches!(events.try_recv(), Ok(InstrumentEvent::Expired(_))));
+    assert_eq!(registry.len(), 3);
+
+    let found = registry.options_in_strike_range("BTCUSD", "2024-12-27", dec!(95000), dec!(120000));
+    let names: Vec<_> = found
+        .iter()
+        .map(|i| i.instrument_name.as_deref().unwrap())
+        .collect();
+    assert_eq!(names, ["BTC-27DEC24-100000-C", "BTC-27DEC24-110000-C"]);
+
+    registry.apply(InstrumentDelta::Removed(Removed::new(option(
+        "BTC-27DEC24-90000-C",
+        dec!(90000),
+        2000,
+    ))));
+    assert_eq!(registry.by_type(TypeEnum::Option).len(), 2);
+    assert_eq!(registry.expiries("BTCUSD"), ["2024-12-27"]);
+}
+
+#[tokio::test]
+async fn stop_unsubscribes_the_instruments_channel() {
+    let (url, _connections, mut methods) = mock_exchange().await;
+    let client = Arc::new(logged_in_client(url).await);
+    let mut registry = InstrumentRegistry::start(client.clone()).await.unwrap();
+    registry.stop(&client).await;
+
+    let seen: Vec<String> = std::iter::from_fn(|| methods.try_recv().ok()).collect();
+    assert!(
+        seen.ends_with(&["public/unsubscribe".to_string()]),
+        "{seen:?}"
+    );
+    client.shutdown("Test complete").await.unwrap();
+}
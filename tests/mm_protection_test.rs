@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    mm_protection::{MmProtectionManager, ProtectionConfig, ProtectionEvent},
+    models::{SessionMmProtectionNotification, session_mm_protection_payload_inner::Reason},
+};
+use tokio::time::timeout;
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange, reconnect_event};
+
+fn update(product: &str, remaining: &str, reason: &str) -> SessionMmProtectionNotification {
+    let raw = format!(
+        r#"{{"channel_name":"session.mm_protection","notification":[
+        {{"product":"{product}","remaining_amount":{remaining},"reason":"{reason}"}}]}}"#
+    );
+    serde_json::from_str(&raw).unwrap()
+}
+
+#[test]
+fn reports_trips_and_rearms_once() {
+    let manager = MmProtectionManager::new(HashMap::from([(
+        "FBTCUSD".to_string(),
+        ProtectionConfig::new(dec!(1), dec!(0.5)),
+    )]));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    manager.on_event(move |e| sink.lock().unwrap().push(e.clone()));
+    let paused = manager.paused();
+
+    assert!(
+        manager
+            .apply(update("FBTCUSD", "1", "refill").notification)
+            .is_empty()
+    );
+    manager.apply(update("FBTCUSD", "0.4", "executions").notification);
+    assert_eq!(manager.remaining_amount("FBTCUSD"), Some(dec!(0.4)));
+    assert!(!*paused.borrow());
+
+    let tripped = manager.apply(update("FBTCUSD", "-0.1", "executions").notification);
+    assert_eq!(tripped, vec!["FBTCUSD".to_string()]);
+    assert!(manager.is_paused("FBTCUSD"));
+    assert!(*paused.borrow());
+    // Further updates while tripped don't trip again.
+    assert!(
+        manager
+            .apply(update("FBTCUSD", "0", "client_cancel").notification)
+            .is_empty()
+    );
+
+    manager.apply(update("FBTCUSD", "1", "refill").notification);
+    assert!(!manager.is_paused("FBTCUSD"));
+    assert!(!*paused.borrow());
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            ProtectionEvent::Tripped {
+                product: "FBTCUSD".to_string(),
+                remaining_amount: Some(dec!(-0.1)),
+                reason: Some(Reason::Executions),
+            },
+            ProtectionEvent::Rearmed {
+                product: "FBTCUSD".to_string(),
+                remaining_amount: Some(dec!(1)),
+            },
+        ]
+    );
+}
+
+#[test]
+fn session_end_trips_with_amount_left() {
+    let manager = MmProtectionManager::default();
+    manager.apply(update("OETHUSD", "5", "refill").notification);
+    let tripped = manager.apply(update("OETHUSD", "5", "session_end").notification);
+    assert_eq!(tripped, vec!["OETHUSD".to_string()]);
+    assert_eq!(
+        manager.status("OETHUSD").unwrap().last_reason,
+        Some(Reason::SessionEnd)
+    );
+}
+
+#[tokio::test]
+async fn configures_the_standby_session_after_a_handover() {
+    let (url, mut connections, mut methods) = mock_exchange().await;
+    let client = Arc::new(logged_in_client(url).await);
+    let manager = MmProtectionManager::new(HashMap::from([(
+        "FBTCUSD".to_string(),
+        ProtectionConfig::new(dec!(1), dec!(0.5)),
+    )]))
+    .start(client.clone())
+    .await
+    .unwrap();
+
+    connections
+        .recv()
+        .await
+        .unwrap()
+        .send(reconnect_event())
+        .unwrap();
+    let mut configured = 0;
+    while configured < 2 {
+        let method = timeout(Duration::from_secs(5), methods.recv())
+            .await
+            .expect("protection was not configured on the standby session")
+            .unwrap();
+        if method == "private/set_mm_protection" {
+            configured += 1;
+        }
+    }
+    assert!(!manager.is_paused("FBTCUSD"));
+
+    manager.stop(&client).await;
+    let seen: Vec<String> = std::iter::from_fn(|| methods.try_recv().ok()).collect();
+    assert!(
+        seen.ends_with(&["private/unsubscribe".to_string()]),
+        "{seen:?}"
+    );
+    client.shutdown("Test complete").await.unwrap();
+}
@@ -20,7 +20,10 @@ async fn test_mm_flow() {
         "THALEX_ACCOUNT_ID"
     );
     let client = WsClient::from_env().await.unwrap();
-    client.set_cancel_on_disconnect().await.unwrap();
+    client.set_cancel_on_disconnect(6).await.unwrap();
+    let status = client.cancel_on_disconnect_status();
+    assert_eq!(status.timeout_secs, Some(6));
+    assert!(status.active);
     // Set MM protection
     let set_protection_result = client
         .rpc()
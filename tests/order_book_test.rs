@@ -0,0 +1,136 @@
+use std::{sync::Arc, time::Duration};
+
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde_json::json;
+use thalex_rust_sdk::{
+    manual_models::book::BookLevel,
+    models::{Book, BookRpcResult, Delay},
+    order_book::{BookSide, LiveOrderBook, OrderBook, OrderBookError},
+};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+fn level(price: Decimal, amount: Decimal) -> BookLevel {
+    BookLevel::new(price, amount)
+}
+
+fn seeded_book() -> OrderBook {
+    let snapshot = BookRpcResult::new(
+        vec![level(dec!(99), dec!(1)), level(dec!(98), dec!(2))],
+        vec![level(dec!(101), dec!(3)), level(dec!(102), dec!(4))],
+        dec!(1),
+    );
+    OrderBook::from_snapshot("BTC-PERPETUAL", &snapshot)
+}
+
+#[test]
+fn snapshot_top_of_book() {
+    let book = seeded_book();
+    assert_eq!(book.best_bid().unwrap().price, dec!(99));
+    assert_eq!(book.best_ask().unwrap().price, dec!(101));
+    assert_eq!(book.spread(), Some(dec!(2)));
+    assert_eq!(book.mid(), Some(dec!(100)));
+    // (99 * 3 + 101 * 1) / 4
+    assert_eq!(book.microprice(), Some(dec!(99.5)));
+}
+
+#[test]
+fn deltas_update_and_remove_levels() {
+    let mut book = seeded_book();
+    let update = Book {
+        bid_changes: Some(vec![level(dec!(99), dec!(0)), level(dec!(99.5), dec!(5))]),
+        ask_changes: Some(vec![level(dec!(102), dec!(1))]),
+        total_bid_amount: Some(dec!(7)),
+        total_ask_amount: Some(dec!(4)),
+        ..Default::default()
+    };
+    book.apply(&update).unwrap();
+    assert_eq!(book.best_bid().unwrap().price, dec!(99.5));
+    assert_eq!(book.depth_at(BookSide::Bid, dec!(99)), Decimal::ZERO);
+    assert_eq!(book.depth_at(BookSide::Ask, dec!(102)), dec!(1));
+    assert_eq!(book.cumulative_depth(BookSide::Bid, dec!(98)), dec!(7));
+    assert_eq!(book.cumulative_depth(BookSide::Ask, dec!(101)), dec!(3));
+}
+
+#[test]
+fn totals_mismatch_is_reported() {
+    let mut book = seeded_book();
+    let update = Book {
+        bid_changes: Some(vec![level(dec!(97), dec!(1))]),
+        total_bid_amount: Some(dec!(10)),
+        ..Default::default()
+    };
+    assert_eq!(
+        book.apply(&update),
+        Err(OrderBookError::TotalsMismatch {
+            side: BookSide::Bid,
+            expected: dec!(10),
+            actual: dec!(4),
+        })
+    );
+    let mut unchecked = seeded_book().with_totals_check(false);
+    assert!(unchecked.apply(&update).is_ok());
+}
+
+#[test]
+fn vwap_walks_levels() {
+    let book = seeded_book();
+    // 3 @ 101 + 1 @ 102
+    assert_eq!(
+        book.vwap_to_size(BookSide::Ask, dec!(4)),
+        Some(dec!(101.25))
+    );
+    assert_eq!(book.vwap_to_size(BookSide::Bid, dec!(1)), Some(dec!(99)));
+    assert_eq!(book.vwap_to_size(BookSide::Bid, dec!(10)), None);
+}
+
+#[tokio::test]
+async fn live_book_is_seeded_like_the_channel() {
+    let (url, mut connections, mut methods) = mock_exchange().await;
+    let client = Arc::new(logged_in_client(url).await);
+    let push = connections.recv().await.unwrap();
+    let live = LiveOrderBook::subscribe(client.clone(), "BTC-PERPETUAL", "1", "1", Delay::Raw)
+        .await
+        .unwrap();
+
+    // The mock snapshot grouped into 1-wide buckets, one level per side.
+    let seeded = live.snapshot();
+    assert_eq!(seeded.best_bid(), Some(level(dec!(99), dec!(3))));
+    assert_eq!(seeded.best_ask(), Some(level(dec!(101), dec!(3))));
+    assert_eq!(seeded.levels(BookSide::Bid).count(), 1);
+
+    let update = |time, price| {
+        json!({
+            "channel_name": live.channel(),
+            "notification": { "bid_changes": [[price, 5]], "time": time },
+        })
+    };
+    // Older than the snapshot, then newer.
+    push.send(update(9, 99)).unwrap();
+    push.send(update(11, 98)).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while live
+            .read(|book| book.depth_at(BookSide::Bid, dec!(98)))
+            .is_zero()
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        live.read(|book| book.depth_at(BookSide::Bid, dec!(99))),
+        dec!(3)
+    );
+
+    live.stop(&client).await;
+    let seen: Vec<String> = std::iter::from_fn(|| methods.try_recv().ok()).collect();
+    assert!(
+        seen.ends_with(&["public/unsubscribe".to_string()]),
+        "{seen:?}"
+    );
+    client.shutdown("Test complete").await.unwrap();
+}
@@ -0,0 +1,93 @@
+use dashmap::DashMap;
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    models::{DirectionEnum, Instrument, OrderTypeEnum},
+    order_builder::{AmendBuilder, OrderBuilder, OrderValidationError},
+};
+
+fn instruments() -> DashMap<String, Instrument> {
+    let instrument: Instrument = serde_json::from_str(
+        r#"{"instrument_name":"BTC-PERPETUAL","tick_size":0.5,"volume_tick_size":0.0001,
+        "min_order_amount":0.001,"type":"perpetual"}"#,
+    )
+    .unwrap();
+    DashMap::from_iter([("BTC-PERPETUAL".to_string(), instrument)])
+}
+
+#[test]
+fn validates_against_instrument_ticks() {
+    let instruments = instruments();
+    let buy =
+        |amount, price| OrderBuilder::limit(DirectionEnum::Buy, "BTC-PERPETUAL", amount, price);
+
+    let params = buy(dec!(0.01), dec!(90000.5))
+        .post_only()
+        .label("mm")
+        .build(&instruments)
+        .unwrap();
+    assert_eq!(params.price, Some(dec!(90000.5)));
+    assert_eq!(params.order_type, Some(OrderTypeEnum::Limit));
+    assert_eq!(params.post_only, Some(true));
+
+    assert_eq!(
+        buy(dec!(0.01), dec!(90000.3)).build(&instruments),
+        Err(OrderValidationError::PriceNotAligned {
+            price: dec!(90000.3),
+            tick_size: dec!(0.5)
+        })
+    );
+    assert!(matches!(
+        buy(dec!(0.0005), dec!(90000)).build(&instruments),
+        Err(OrderValidationError::BelowMinimumAmount { .. })
+    ));
+    assert_eq!(
+        OrderBuilder::market(DirectionEnum::Sell, "BTC-PERPETUAL", dec!(0.01))
+            .post_only()
+            .build(&instruments),
+        Err(OrderValidationError::PostOnlyNotResting)
+    );
+    assert_eq!(
+        buy(dec!(0.01), dec!(1))
+            .immediate_or_cancel()
+            .build(&DashMap::new()),
+        Err(OrderValidationError::UnknownInstrument(
+            "BTC-PERPETUAL".to_string()
+        ))
+    );
+}
+
+#[test]
+fn auto_round_moves_away_from_the_touch() {
+    let instruments = instruments();
+    let bid = OrderBuilder::limit(
+        DirectionEnum::Buy,
+        "BTC-PERPETUAL",
+        dec!(0.012345),
+        dec!(90000.3),
+    )
+    .auto_round()
+    .build(&instruments)
+    .unwrap();
+    assert_eq!((bid.price, bid.amount), (Some(dec!(90000)), dec!(0.0123)));
+    let ask = OrderBuilder::limit(
+        DirectionEnum::Sell,
+        "BTC-PERPETUAL",
+        dec!(0.01),
+        dec!(90000.3),
+    )
+    .auto_round()
+    .build(&instruments)
+    .unwrap();
+    assert_eq!(ask.price, Some(dec!(90000.5)));
+
+    let amend = AmendBuilder::order_id("0001", "BTC-PERPETUAL", DirectionEnum::Sell)
+        .price(dec!(90000.1))
+        .amount(dec!(0.02))
+        .auto_round()
+        .build(&instruments)
+        .unwrap();
+    assert_eq!(
+        (amend.price, amend.order_id.as_deref()),
+        (dec!(90000.5), Some("0001"))
+    );
+}
@@ -0,0 +1,188 @@
+use std::sync::{Arc, Mutex};
+
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    models::{
+        DirectionEnum, InsertParams, OrderHistory, OrderStatus, SessionOrdersNotification,
+        StatusEnum,
+    },
+    order_manager::{OrderFeed, OrderManager, SubmittedOrder, is_duplicate_order_id},
+    types::ClientError,
+    utils::ClientOrderIds,
+};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+fn order(
+    status: &str,
+    change_reason: &str,
+    filled: &str,
+    remaining: &str,
+    fills: &str,
+) -> OrderStatus {
+    let raw = format!(
+        r#"{{"order_id":"0001","order_type":"limit","time_in_force":"good_till_cancelled",
+        "instrument_name":"BTC-PERPETUAL","direction":"buy","price":90000,"amount":2,
+        "filled_amount":{filled},"remaining_amount":{remaining},"label":"mm","client_order_id":7,
+        "status":"{status}","fills":[{fills}],"change_reason":"{change_reason}",
+        "insert_reason":"client_request","create_time":1700000000.5,"persistent":false}}"#
+    );
+    serde_json::from_str(&raw).unwrap()
+}
+
+fn fill(trade_id: &str) -> String {
+    format!(
+        r#"{{"trade_id":"{trade_id}","price":90000,"amount":1,"maker_taker":"maker","leg_index":0}}"#
+    )
+}
+
+#[test]
+fn tracks_fills_once_and_drops_closed_orders() {
+    let manager = OrderManager::new();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    manager.on_fill(move |_, fill| sink.lock().unwrap().push(fill.trade_id.clone()));
+    let counted = Arc::new(Mutex::new(0));
+    let counter = counted.clone();
+    let removable = manager.on_fill(move |_, _| *counter.lock().unwrap() += 1);
+
+    manager.apply(order("open", "insert", "0", "2", ""));
+    assert_eq!(
+        manager.get_by_client_order_id(dec!(7)).unwrap().order_id,
+        "0001"
+    );
+    assert_eq!(manager.by_label("mm").len(), 1);
+
+    manager.apply(order("partially_filled", "fill", "1", "1", &fill("t1")));
+    // A full resend of known fills doesn't fire the callback again.
+    manager.apply(order("partially_filled", "existing", "1", "1", &fill("t1")));
+    assert_eq!(manager.get("0001").unwrap().fills.len(), 1);
+    manager.remove_callback(removable);
+
+    let raw = format!(
+        r#"{{"channel_name":"session.orders","notification":[{}]}}"#,
+        serde_json::to_string(&order("filled", "fill", "2", "0", &fill("t2"))).unwrap()
+    );
+    let msg: SessionOrdersNotification = serde_json::from_str(&raw).unwrap();
+    for update in msg.notification {
+        manager.apply(update);
+    }
+
+    assert_eq!(*seen.lock().unwrap(), ["t1", "t2"]);
+    assert_eq!(*counted.lock().unwrap(), 1);
+    assert!(manager.is_empty());
+    assert!(manager.get_by_client_order_id(dec!(7)).is_none());
+}
+
+#[test]
+fn reconcile_reports_vanished_orders_as_cancelled() {
+    let manager = OrderManager::new();
+    let cancelled = Arc::new(Mutex::new(Vec::new()));
+    let sink = cancelled.clone();
+    manager.on_cancel(move |order| sink.lock().unwrap().push(order.status));
+
+    manager.apply(order("partially_filled", "fill", "1", "1", &fill("t1")));
+    manager.reconcile(Vec::new(), &[]);
+
+    assert!(manager.is_empty());
+    assert_eq!(
+        *cancelled.lock().unwrap(),
+        [StatusEnum::CancelledPartiallyFilled]
+    );
+
+    manager.reconcile(vec![order("open", "existing", "0", "2", "")], &[]);
+    assert_eq!(manager.by_instrument("BTC-PERPETUAL").len(), 1);
+}
+
+#[test]
+fn reconcile_reports_fills_of_vanished_orders() {
+    let manager = OrderManager::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let fills = events.clone();
+    manager.on_fill(move |order, fill| {
+        fills
+            .lock()
+            .unwrap()
+            .push(format!("{:?} {}", order.status, fill.trade_id))
+    });
+    let cancels = events.clone();
+    manager.on_cancel(move |order| cancels.lock().unwrap().push(format!("{:?}", order.status)));
+
+    manager.apply(order("partially_filled", "fill", "1", "1", &fill("t1")));
+    let history: OrderHistory = serde_json::from_str(&format!(
+        r#"{{"order_id":"0001","order_type":"limit","instrument_name":"BTC-PERPETUAL",
+        "direction":"buy","price":90000,"amount":2,"filled_amount":2,"client_order_id":7,
+        "status":"filled","fills":[{},{}],"delete_reason":"filled",
+        "insert_reason":"client_request","create_time":1700000000.5,"close_time":1700000060}}"#,
+        fill("t1"),
+        fill("t2")
+    ))
+    .unwrap();
+    manager.reconcile(Vec::new(), &[history]);
+
+    // Only the fill missed while disconnected is new; the order is not cancelled.
+    assert!(manager.is_empty());
+    assert_eq!(*events.lock().unwrap(), ["PartiallyFilled t1", "Filled t2"]);
+}
+
+#[test]
+fn client_order_ids_and_duplicate_detection() {
+    let ids = ClientOrderIds::starting_at(i32::MAX - 1);
+    assert_eq!(ids.next_id(), i32::MAX - 1);
+    assert_eq!(ids.next_id(), i32::MAX);
+    assert_eq!(ids.next_id(), 1);
+    let seeded = ClientOrderIds::new();
+    assert!(seeded.next_id() < seeded.next_id());
+
+    let rpc_error = |code: u32| {
+        let raw = format!(r#"{{"id":5,"error":{{"code":{code},"message":"refused"}}}}"#);
+        ClientError::Rpc(serde_json::from_str(&raw).unwrap())
+    };
+    assert!(is_duplicate_order_id(&rpc_error(2)));
+    assert!(!is_duplicate_order_id(&rpc_error(1)));
+}
+
+#[tokio::test]
+async fn stop_unsubscribes_the_orders_feed() {
+    let (url, _connections, mut methods) = mock_exchange().await;
+    let client = Arc::new(logged_in_client(url).await);
+    let manager = OrderManager::start(client.clone(), OrderFeed::Session)
+        .await
+        .unwrap();
+    manager.stop(&client).await;
+
+    let seen: Vec<String> = std::iter::from_fn(|| methods.try_recv().ok()).collect();
+    assert!(
+        seen.ends_with(&["private/unsubscribe".to_string()]),
+        "{seen:?}"
+    );
+    client.shutdown("Test complete").await.unwrap();
+}
+
+#[tokio::test]
+async fn resolve_pages_history_for_the_matching_order() {
+    let (url, _connections, _methods) = mock_exchange().await;
+    let client = logged_in_client(url).await;
+    let manager = OrderManager::new();
+    let params = InsertParams {
+        direction: DirectionEnum::Buy,
+        instrument_name: Some("BTC-PERPETUAL".to_string()),
+        amount: dec!(1),
+        client_order_id: Some(7),
+        ..Default::default()
+    };
+
+    // The order for 2 on the first page reused the id; ours is on the second.
+    match manager.resolve(&client, &params).await.unwrap() {
+        Some(SubmittedOrder::Closed(order)) => assert_eq!(order.order_id, "0002"),
+        other => panic!("unexpected {other:?}"),
+    }
+    let params = InsertParams {
+        direction: DirectionEnum::Sell,
+        ..params
+    };
+    assert!(manager.resolve(&client, &params).await.unwrap().is_none());
+    client.shutdown("Test complete").await.unwrap();
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    models::{Delay, DirectionEnum, OrderStatus, PortfolioEntry},
+    order_manager::OrderManager,
+    pegged_order::{PegAction, PegConfig, PegQuote, PegReference, PeggedOrder},
+    portfolio_tracker::PortfolioTracker,
+    ticks::TickSpec,
+};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+fn spec() -> TickSpec {
+    TickSpec::new(dec!(1), dec!(0.001), Some(dec!(0.01))).unwrap()
+}
+
+const QUOTE: PegQuote = PegQuote {
+    best_bid: Some(dec!(90000)),
+    best_ask: Some(dec!(90010)),
+    mark: Some(dec!(90020)),
+};
+
+fn peg(config: PegConfig) -> PeggedOrder {
+    PeggedOrder::new(config, spec(), OrderManager::new())
+}
+
+#[test]
+fn prices_follow_the_reference() {
+    let buy = |reference| PegConfig::new(DirectionEnum::Buy, "BTC-PERPETUAL", dec!(0.1), reference);
+    let sell =
+        |reference| PegConfig::new(DirectionEnum::Sell, "BTC-PERPETUAL", dec!(0.1), reference);
+
+    // 2 bps of 90000 is 18.
+    assert_eq!(
+        peg(buy(PegReference::Best).offset_bps(dec!(2))).target_price(&QUOTE),
+        Some(dec!(89982))
+    );
+    // Mid 90005 plus 1 bp (9.0005), rounded up for an ask.
+    assert_eq!(
+        peg(sell(PegReference::Mid).offset_bps(dec!(1))).target_price(&QUOTE),
+        Some(dec!(90015))
+    );
+    // A post-only bid pegged to a mark above the ask stays one tick under the ask.
+    assert_eq!(
+        peg(buy(PegReference::Mark)).target_price(&QUOTE),
+        Some(dec!(90009))
+    );
+    assert_eq!(
+        peg(buy(PegReference::Mark).allow_taking()).target_price(&QUOTE),
+        Some(dec!(90020))
+    );
+    assert_eq!(
+        peg(buy(PegReference::Mid)).target_price(&PegQuote {
+            best_ask: None,
+            ..QUOTE
+        }),
+        None
+    );
+}
+
+#[test]
+fn amends_outside_tolerance_and_shrinks_with_position() {
+    let portfolio = PortfolioTracker::new();
+    portfolio.replace_all(vec![PortfolioEntry {
+        instrument_name: Some("BTC-PERPETUAL".to_string()),
+        position: Some(dec!(0.45)),
+        ..Default::default()
+    }]);
+    let config = PegConfig::new(
+        DirectionEnum::Buy,
+        "BTC-PERPETUAL",
+        dec!(0.1),
+        PegReference::Best,
+    )
+    .tolerance_bps(dec!(1))
+    .max_position(dec!(0.5));
+    let peg = peg(config).with_portfolio(portfolio.clone());
+
+    assert_eq!(peg.target_amount(), dec!(0.05));
+    assert_eq!(
+        peg.decide(None, &QUOTE),
+        PegAction::Insert {
+            price: dec!(90000),
+            amount: dec!(0.05),
+        }
+    );
+
+    let live = OrderStatus {
+        direction: DirectionEnum::Buy,
+        price: Some(dec!(89995)),
+        amount: dec!(0.06),
+        filled_amount: dec!(0.01),
+        remaining_amount: dec!(0.05),
+        ..Default::default()
+    };
+    // 5 below the peg is under 1 bp.
+    assert_eq!(peg.decide(Some(&live), &QUOTE), PegAction::Hold);
+    let moved = PegQuote {
+        best_bid: Some(dec!(90020)),
+        ..QUOTE
+    };
+    assert_eq!(
+        peg.decide(Some(&live), &moved),
+        PegAction::Amend {
+            price: dec!(90009),
+            amount: dec!(0.06),
+        }
+    );
+
+    portfolio.replace_all(vec![PortfolioEntry {
+        instrument_name: Some("BTC-PERPETUAL".to_string()),
+        position: Some(dec!(0.495)),
+        ..Default::default()
+    }]);
+    // What is left below the limit is under the minimum order amount.
+    assert_eq!(peg.decide(Some(&live), &QUOTE), PegAction::Cancel);
+    assert_eq!(peg.decide(None, &QUOTE), PegAction::Hold);
+}
+
+#[tokio::test]
+async fn stop_unsubscribes_the_ticker() {
+    let (url, _connections, mut methods) = mock_exchange().await;
+    let client = Arc::new(logged_in_client(url).await);
+    let config = PegConfig::new(
+        DirectionEnum::Sell,
+        "BTC-PERPETUAL",
+        dec!(0.1),
+        PegReference::Best,
+    );
+    let peg = peg(config).start(client.clone(), Delay::Raw).await.unwrap();
+    peg.stop(&client).await;
+
+    let seen: Vec<String> = std::iter::from_fn(|| methods.try_recv().ok()).collect();
+    assert!(
+        seen.ends_with(&["public/unsubscribe".to_string()]),
+        "{seen:?}"
+    );
+    client.shutdown("Test complete").await.unwrap();
+}
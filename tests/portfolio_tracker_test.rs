@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    models::{AccountPortfolioNotification, OrderFill, OrderStatus},
+    portfolio_tracker::{Pnl, PortfolioTracker},
+};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+fn snapshot() -> AccountPortfolioNotification {
+    serde_json::from_str(
+        r#"{"channel_name":"account.portfolio","notification":[
+        {"instrument_name":"BTC-PERPETUAL","position":1,"mark_price":100000,"unrealised_pnl":50,
+         "realised_pnl":10,"unrealised_perpetual_funding":-2},
+        {"instrument_name":"BTC-27DEC24-100000-C","position":-2,"mark_price":1000,"unrealised_pnl":-20,
+         "realised_pnl":0},
+        {"instrument_name":"ETH-PERPETUAL","position":5,"mark_price":3000,"unrealised_pnl":7,
+         "realised_pnl":1}]}"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn merges_deltas_and_sums_pnl_per_underlying() {
+    let tracker = PortfolioTracker::new();
+    tracker.replace_all(snapshot().notification);
+
+    let delta: AccountPortfolioNotification = serde_json::from_str(
+        r#"{"channel_name":"account.portfolio","notification":[
+        {"instrument_name":"ETH-PERPETUAL","position":4,"mark_price":3000,"unrealised_pnl":6,
+         "realised_pnl":3}]}"#,
+    )
+    .unwrap();
+    tracker.apply(delta.notification);
+
+    assert_eq!(tracker.position("ETH-PERPETUAL"), dec!(4));
+    assert_eq!(tracker.position("BTC-PERPETUAL"), dec!(1));
+    assert_eq!(
+        tracker.pnl_by_underlying("BTC"),
+        Pnl {
+            realised: dec!(10),
+            unrealised: dec!(30),
+            funding: dec!(-2),
+        }
+    );
+    assert_eq!(tracker.total_pnl().total(), dec!(47));
+}
+
+#[test]
+fn fills_move_position_until_portfolio_catches_up() {
+    let tracker = PortfolioTracker::new();
+    tracker.replace_all(snapshot().notification);
+
+    let order: OrderStatus = serde_json::from_str(
+        r#"{"order_id":"1","order_type":"limit","time_in_force":"good_till_cancelled",
+        "instrument_name":"BTC-PERPETUAL","direction":"sell","price":99000,"amount":1,
+        "filled_amount":0.5,"remaining_amount":0.5,"status":"partially_filled","fills":[],
+        "change_reason":"fill","insert_reason":"client_request","create_time":0,"persistent":false}"#,
+    )
+    .unwrap();
+    let fill: OrderFill = serde_json::from_str(
+        r#"{"trade_id":"t1","price":99000,"amount":0.5,"maker_taker":"maker","leg_index":0}"#,
+    )
+    .unwrap();
+    tracker.apply_fill(&order, &fill);
+
+    assert_eq!(tracker.position("BTC-PERPETUAL"), dec!(0.5));
+    // Selling 0.5 at 99000 against a 100000 mark costs 500 unrealised.
+    assert_eq!(tracker.pnl("BTC-PERPETUAL").unrealised, dec!(-450));
+
+    let update: AccountPortfolioNotification = serde_json::from_str(
+        r#"{"channel_name":"account.portfolio","notification":[
+        {"instrument_name":"BTC-PERPETUAL","position":0.5,"mark_price":100000,"unrealised_pnl":25,
+         "realised_pnl":10}]}"#,
+    )
+    .unwrap();
+    tracker.apply(update.notification);
+    assert_eq!(tracker.position("BTC-PERPETUAL"), dec!(0.5));
+    assert_eq!(tracker.positions().len(), 3);
+}
+
+#[test]
+fn late_fills_already_in_the_portfolio_are_ignored() {
+    let tracker = PortfolioTracker::new();
+    tracker.replace_all(snapshot().notification);
+    let order: OrderStatus = serde_json::from_str(
+        r#"{"order_id":"2","order_type":"limit","time_in_force":"good_till_cancelled",
+        "instrument_name":"ETH-PERPETUAL","direction":"buy","price":3000,"amount":2,
+        "filled_amount":2,"remaining_amount":0,"status":"filled","fills":[],
+        "change_reason":"fill","insert_reason":"client_request","create_time":0,"persistent":false}"#,
+    )
+    .unwrap();
+    let fill = |trade_id: &str, time: &str| -> OrderFill {
+        serde_json::from_str(&format!(
+            r#"{{"trade_id":"{trade_id}","price":3000,"amount":1,"time":{time},
+            "maker_taker":"taker","leg_index":0}}"#
+        ))
+        .unwrap()
+    };
+
+    // Executed long before the snapshot arrived: already in its position of 5.
+    tracker.apply_fill(&order, &fill("t1", "1700000000"));
+    assert_eq!(tracker.position("ETH-PERPETUAL"), dec!(5));
+    // Executed after it, in 2100: not in the portfolio yet.
+    tracker.apply_fill(&order, &fill("t2", "4102444800"));
+    assert_eq!(tracker.position("ETH-PERPETUAL"), dec!(6));
+
+    // Pending fills on an instrument without a portfolio line count in both views.
+    let option: OrderStatus = serde_json::from_str(
+        &serde_json::to_string(&order)
+            .unwrap()
+            .replace("ETH-PERPETUAL", "ETH-27DEC24-3000-P"),
+    )
+    .unwrap();
+    tracker.apply_fill(&option, &fill("t3", "4102444800"));
+    assert_eq!(tracker.positions().len(), 4);
+    assert_eq!(tracker.pnl("ETH-27DEC24-3000-P"), Pnl::default());
+    assert_eq!(tracker.pnl_by_underlying("ETH").realised, dec!(1));
+
+    // Those fills show the exchange clock is in 2100, so a snapshot now is stamped then.
+    tracker.replace_all(snapshot().notification);
+    tracker.apply_fill(&order, &fill("t4", "4102444700"));
+    assert_eq!(tracker.position("ETH-PERPETUAL"), dec!(5));
+}
+
+#[tokio::test]
+async fn stop_unsubscribes_the_portfolio_channel() {
+    let (url, _connections, mut methods) = mock_exchange().await;
+    let client = Arc::new(logged_in_client(url).await);
+    let tracker = PortfolioTracker::start(client.clone(), None).await.unwrap();
+    tracker.stop(&client).await;
+
+    let seen: Vec<String> = std::iter::from_fn(|| methods.try_recv().ok()).collect();
+    assert!(
+        seen.ends_with(&["private/unsubscribe".to_string()]),
+        "{seen:?}"
+    );
+    client.shutdown("Test complete").await.unwrap();
+}
@@ -0,0 +1,110 @@
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    models::{Instrument, OptionTypeEnum, Ticker, TypeEnum},
+    pricing::{self, Black76, PricingError, norm_cdf},
+};
+
+fn close(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance
+}
+
+#[test]
+fn normal_cdf_reference_values() {
+    assert!(close(norm_cdf(0.0), 0.5, 1e-15));
+    assert!(close(norm_cdf(1.96), 0.975_002_104_851_780, 1e-14));
+    assert!(close(norm_cdf(-3.0), 0.001_349_898_031_630_094, 1e-16));
+}
+
+#[test]
+fn price_parity_and_implied_vol_round_trip() {
+    let call = Black76::new(OptionTypeEnum::Call, 100_000.0, 110_000.0, 0.25, 0.6);
+    let put = Black76 {
+        option_type: OptionTypeEnum::Put,
+        ..call
+    };
+    // Undiscounted put-call parity: C - P = F - K.
+    assert!(close(call.price() - put.price(), -10_000.0, 1e-8));
+
+    for model in [call, put] {
+        let iv = model.implied_vol(model.price()).unwrap();
+        assert!(close(iv, 0.6, 1e-8), "{iv}");
+    }
+    assert_eq!(
+        call.implied_vol(1.0e6),
+        Err(PricingError::PriceOutOfBounds(1.0e6))
+    );
+}
+
+#[test]
+fn greeks_match_finite_differences() {
+    let model = Black76::new(OptionTypeEnum::Put, 3_000.0, 2_800.0, 30.0 / 365.0, 0.7);
+    let greeks = model.greeks();
+    let bump = |f: &dyn Fn(&mut Black76), g: &dyn Fn(&Black76) -> f64, h: f64| {
+        let (mut up, mut down) = (model, model);
+        f(&mut up);
+        f(&mut down);
+        down.forward -= 2.0 * (up.forward - model.forward);
+        down.vol -= 2.0 * (up.vol - model.vol);
+        down.time -= 2.0 * (up.time - model.time);
+        (g(&up) - g(&down)) / (2.0 * h)
+    };
+    let h = 0.01;
+    let delta = bump(&|m| m.forward += h, &|m| m.price(), h);
+    let gamma = bump(&|m| m.forward += h, &|m| m.greeks().delta, h);
+    let vega = bump(&|m| m.vol += 1e-5, &|m| m.price(), 1e-5);
+    let vanna = bump(&|m| m.vol += 1e-5, &|m| m.greeks().delta, 1e-5);
+    let theta = -bump(&|m| m.time += 1e-6, &|m| m.price(), 1e-6);
+
+    assert!(close(greeks.delta, delta, 1e-6));
+    assert!(close(greeks.gamma, gamma, 1e-6));
+    assert!(close(greeks.vega, vega, 1e-4));
+    assert!(close(greeks.vanna, vanna, 1e-4));
+    assert!(close(greeks.theta, theta, 1e-2));
+}
+
+#[test]
+fn ticker_helpers_reprice_the_mark() {
+    let instrument = Instrument {
+        instrument_name: Some("BTC-27DEC24-100000-C".to_string()),
+        r#type: Some(TypeEnum::Option),
+        option_type: Some(OptionTypeEnum::Call),
+        strike_price: Some(dec!(100000)),
+        expiration_timestamp: Some(1_735_286_400),
+        ..Default::default()
+    };
+    let model = Black76::new(
+        OptionTypeEnum::Call,
+        98_000.0,
+        100_000.0,
+        pricing::year_fraction(1_732_608_000.0, 1_735_286_400.0),
+        0.55,
+    );
+    let ticker = Ticker {
+        mark_price: rust_decimal::Decimal::from_f64_retain(model.price()).unwrap(),
+        mark_timestamp: dec!(1732608000),
+        iv: Some(dec!(0.55)),
+        forward: Some(dec!(98000)),
+        ..Default::default()
+    };
+
+    assert!(close(
+        pricing::price(&instrument, &ticker).unwrap(),
+        model.price(),
+        1e-9
+    ));
+    let iv = pricing::implied_vol(&instrument, &ticker, ticker.mark_price).unwrap();
+    assert!(close(iv, 0.55, 1e-8));
+    assert_eq!(
+        pricing::greeks(&instrument, &ticker).unwrap(),
+        model.greeks()
+    );
+
+    let perpetual = Instrument {
+        r#type: Some(TypeEnum::Perpetual),
+        ..Default::default()
+    };
+    assert_eq!(
+        pricing::greeks(&perpetual, &ticker),
+        Err(PricingError::NotAnOption)
+    );
+}
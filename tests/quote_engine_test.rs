@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    manual_models::book::BookLevel,
+    models::{DirectionEnum, DoubleSidedQuoteB, DoubleSidedQuoteResult, Instrument},
+    quote_engine::{QuoteEngine, QuoteError, attribute_errors},
+};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+fn engine(names: &[&str]) -> QuoteEngine {
+    let instruments = Arc::new(DashMap::new());
+    for name in names {
+        let raw = format!(
+            r#"{{"instrument_name":"{name}","product":"FBTCUSD","tick_size":5,
+            "volume_tick_size":0.001,"min_order_amount":0.001,"type":"perpetual"}}"#
+        );
+        let instrument: Instrument = serde_json::from_str(&raw).unwrap();
+        instruments.insert(name.to_string(), instrument);
+    }
+    QuoteEngine::new(instruments)
+}
+
+fn level(price: rust_decimal::Decimal, amount: rust_decimal::Decimal) -> BookLevel {
+    BookLevel::new(price, amount)
+}
+
+#[test]
+fn rounds_away_from_the_touch_and_validates() {
+    let engine = engine(&["BTC-PERPETUAL"]);
+    engine
+        .set_quotes(
+            "BTC-PERPETUAL",
+            vec![
+                level(dec!(89993), dec!(0.1)),
+                level(dec!(89999), dec!(0.2509)),
+            ],
+            vec![level(dec!(90001), dec!(0.0004))],
+        )
+        .unwrap();
+    let quotes = engine.desired("BTC-PERPETUAL").unwrap();
+    assert_eq!(
+        quotes.bids,
+        vec![
+            level(dec!(89995), dec!(0.25)),
+            level(dec!(89990), dec!(0.1))
+        ]
+    );
+    // The ask rounds to a zero amount and is dropped.
+    assert!(quotes.asks.is_empty());
+
+    engine.set_quote_limit("FBTCUSD", dec!(1));
+    assert!(matches!(
+        engine.set_quotes("BTC-PERPETUAL", vec![level(dec!(90000), dec!(2))], vec![]),
+        Err(QuoteError::AboveProtectionAmount { .. })
+    ));
+    assert!(matches!(
+        engine.set_quotes("ETH-PERPETUAL", vec![], vec![]),
+        Err(QuoteError::UnknownInstrument(_))
+    ));
+    let too_many = (0..11).map(|i| {
+        level(
+            dec!(80000) + dec!(5) * rust_decimal::Decimal::from(i),
+            dec!(0.1),
+        )
+    });
+    assert!(matches!(
+        engine.set_quotes("BTC-PERPETUAL", too_many.collect(), vec![]),
+        Err(QuoteError::TooManyLevels { levels: 11, .. })
+    ));
+}
+
+#[test]
+fn sends_only_changed_sides_in_chunks() {
+    let names: Vec<String> = (0..150).map(|i| format!("BTC-{i}")).collect();
+    let refs: Vec<&str> = names.iter().map(String::as_str).collect();
+    let engine = engine(&refs);
+    for name in &names {
+        engine
+            .set_quotes(
+                name,
+                vec![level(dec!(89000), dec!(0.1))],
+                vec![level(dec!(91000), dec!(0.1))],
+            )
+            .unwrap();
+    }
+    let batches = engine.pending_batches();
+    assert_eq!(
+        batches.iter().map(|b| b.quotes.len()).collect::<Vec<_>>(),
+        vec![100, 50]
+    );
+    batches.iter().for_each(|b| engine.mark_live(b));
+    assert!(engine.pending_batches().is_empty());
+
+    // Moving the bid resends only the bid side of that instrument.
+    engine
+        .set_quotes(
+            "BTC-7",
+            vec![level(dec!(89005), dec!(0.1))],
+            vec![level(dec!(91000), dec!(0.1))],
+        )
+        .unwrap();
+    let batches = engine.pending_batches();
+    assert_eq!(batches.len(), 1);
+    let quote = &batches[0].quotes[0];
+    assert_eq!(quote.i, "BTC-7");
+    assert!(quote.a.is_none());
+    assert_eq!(
+        quote.b,
+        Some(DoubleSidedQuoteB::SingleSidedMultiLevelQuote(vec![level(
+            dec!(89005),
+            dec!(0.1)
+        )]))
+    );
+    assert_eq!(
+        serde_json::to_value(quote).unwrap(),
+        serde_json::json!({"i": "BTC-7", "b": [["89005", "0.1"]]})
+    );
+    batches.iter().for_each(|b| engine.mark_live(b));
+
+    // Clearing sends empty arrays for both sides; a reset resends everything.
+    engine.clear_quotes("BTC-3");
+    let quote = &engine.pending_batches()[0].quotes[0];
+    assert_eq!(
+        serde_json::to_value(quote).unwrap(),
+        serde_json::json!({"i": "BTC-3", "b": [], "a": []})
+    );
+    engine.reset_live();
+    assert_eq!(engine.pending_batches()[0].quotes.len(), 100);
+}
+
+#[test]
+fn attributes_errors_to_instruments() {
+    let engine = engine(&["BTC-PERPETUAL", "BTC-27JUN25"]);
+    engine
+        .set_quotes("BTC-PERPETUAL", vec![level(dec!(89000), dec!(0.1))], vec![])
+        .unwrap();
+    engine
+        .set_quotes("BTC-27JUN25", vec![level(dec!(90000), dec!(0.1))], vec![])
+        .unwrap();
+    let batch = &engine.pending_batches()[0];
+    let result: DoubleSidedQuoteResult = serde_json::from_str(
+        r#"{"n_success":1,"n_fail":1,"errors":[
+            {"code":3,"message":"insufficient margin","side":"buy","price":90000}]}"#,
+    )
+    .unwrap();
+    let rejections = attribute_errors(batch, &result);
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(
+        rejections[0].instrument_name.as_deref(),
+        Some("BTC-27JUN25")
+    );
+    assert_eq!(rejections[0].side, Some(DirectionEnum::Buy));
+}
+
+#[tokio::test]
+async fn rejected_sides_stay_pending_after_flush() {
+    let (url, _connections, _methods) = mock_exchange().await;
+    let client = logged_in_client(url).await;
+    let engine = engine(&["BTC-PERPETUAL", "BTC-27JUN25"]);
+    engine
+        .set_quotes(
+            "BTC-PERPETUAL",
+            vec![level(dec!(89000), dec!(0.1))],
+            vec![level(dec!(91000), dec!(0.1))],
+        )
+        .unwrap();
+    engine
+        .set_quotes(
+            "BTC-27JUN25",
+            vec![level(dec!(90000), dec!(0.1))],
+            vec![level(dec!(92000), dec!(0.1))],
+        )
+        .unwrap();
+
+    // The mock exchange refuses the bid at 90000.
+    let report = engine.flush(&client).await.unwrap();
+    assert_eq!(report.rejections.len(), 1);
+    let batches = engine.pending_batches();
+    assert_eq!(batches.len(), 1);
+    let quotes = &batches[0].quotes;
+    assert_eq!(quotes.len(), 1);
+    assert_eq!(quotes[0].i, "BTC-27JUN25");
+    assert!(quotes[0].b.is_some() && quotes[0].a.is_none());
+    client.shutdown("Test complete").await.unwrap();
+}
@@ -0,0 +1,51 @@
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    manual_models::recent_trade::RecentTrade,
+    models::{DirectionEnum, RecentTradesNotification},
+};
+
+#[test]
+fn recent_trades_parse_from_tuples() {
+    let raw = r#"{"channel_name":"recent_trades.BTC-PERPETUAL.single","notification":[
+        [68000.5, 0.25, "sell", 1712345678.123, "BTC-PERPETUAL", true],
+        [68001, 1, "buy", 1712345679, "BTC-PERPETUAL"],
+        [68002, 2, "buy", 1712345680, "BTC-PERPETUAL", false, "added later"]]}"#;
+    let msg: RecentTradesNotification = serde_json::from_str(raw).unwrap();
+    let trades = msg.notification;
+
+    assert_eq!(
+        trades[0],
+        RecentTrade {
+            price: dec!(68000.5),
+            size: dec!(0.25),
+            side: DirectionEnum::Sell,
+            timestamp: dec!(1712345678.123),
+            instrument_name: "BTC-PERPETUAL".to_string(),
+            implied_taker: true,
+        }
+    );
+    // implied_taker is optional and trailing fields are ignored.
+    assert!(!trades[1].implied_taker);
+    assert_eq!(trades[1].side, DirectionEnum::Buy);
+    assert_eq!(trades[2].size, dec!(2));
+}
+
+#[test]
+fn recent_trade_round_trips_and_rejects_short_tuples() {
+    let trade = RecentTrade {
+        price: dec!(3000),
+        size: dec!(1.5),
+        side: DirectionEnum::Buy,
+        timestamp: dec!(1712345678.5),
+        instrument_name: "ETH-PERPETUAL".to_string(),
+        implied_taker: false,
+    };
+    let json = serde_json::to_string(&trade).unwrap();
+    assert!(json.starts_with('['), "{json}");
+    assert_eq!(serde_json::from_str::<RecentTrade>(&json).unwrap(), trade);
+
+    let error = serde_json::from_str::<RecentTrade>(r#"[3000, 1.5, "buy", 1712345678.5]"#)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("invalid length 4"), "{error}");
+}
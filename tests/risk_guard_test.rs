@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    manual_models::book::BookLevel,
+    models::{
+        AccountSummary, DirectionEnum, DoubleSidedQuote, DoubleSidedQuoteA, DoubleSidedQuoteB,
+        InsertParams, MassQuoteParams, OrderStatus, OrderTypeEnum, PortfolioEntry, StatusEnum,
+    },
+    order_manager::OrderManager,
+    portfolio_tracker::PortfolioTracker,
+    risk_guard::{RiskError, RiskGuard, RiskLimits, RiskRejection},
+    ws_client::WsClient,
+};
+
+mod common;
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+fn guard(limits: RiskLimits, position: Decimal) -> RiskGuard {
+    let portfolio = PortfolioTracker::new();
+    portfolio.replace_all(vec![PortfolioEntry {
+        instrument_name: Some("BTC-PERPETUAL".to_string()),
+        position: Some(position),
+        mark_price: Some(dec!(90000)),
+        ..Default::default()
+    }]);
+    RiskGuard::new(limits, portfolio)
+}
+
+fn order(direction: DirectionEnum, amount: Decimal, price: Decimal) -> InsertParams {
+    InsertParams {
+        direction,
+        instrument_name: Some("BTC-PERPETUAL".to_string()),
+        amount,
+        price: Some(price),
+        order_type: Some(OrderTypeEnum::Limit),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn enforces_position_notional_and_band() {
+    let limits = RiskLimits::default()
+        .max_position("BTC-PERPETUAL", dec!(1))
+        .max_underlying_position("BTC", dec!(1.5))
+        .max_order_notional(dec!(100000))
+        .price_band_bps(dec!(50));
+    let guard = guard(limits, dec!(0.8));
+
+    assert_eq!(
+        guard.check_insert(&order(DirectionEnum::Buy, dec!(0.3), dec!(90000))),
+        Err(RiskRejection::PositionLimit {
+            instrument_name: "BTC-PERPETUAL".to_string(),
+            projected: dec!(1.1),
+            limit: dec!(1),
+        })
+    );
+    // Selling 1 reduces the position to 0.2 short.
+    assert!(
+        guard
+            .check_insert(&order(DirectionEnum::Sell, dec!(1), dec!(90000)))
+            .is_ok()
+    );
+    assert!(matches!(
+        guard.check_insert(&order(DirectionEnum::Sell, dec!(1.2), dec!(90000))),
+        Err(RiskRejection::OrderNotional { .. })
+    ));
+    // 50 bps of 90000 is 450.
+    assert!(
+        guard
+            .check_insert(&order(DirectionEnum::Sell, dec!(0.1), dec!(90450)))
+            .is_ok()
+    );
+    assert!(matches!(
+        guard.check_insert(&order(DirectionEnum::Sell, dec!(0.1), dec!(90451))),
+        Err(RiskRejection::PriceBand { .. })
+    ));
+    assert_eq!(
+        guard.check_insert(&InsertParams {
+            instrument_name: Some("BTC-27JUN25".to_string()),
+            ..order(DirectionEnum::Buy, dec!(0.1), dec!(91000))
+        }),
+        Err(RiskRejection::MissingMark("BTC-27JUN25".to_string()))
+    );
+    guard.update_mark("BTC-27JUN25", dec!(91000));
+    assert_eq!(
+        guard.check_insert(&InsertParams {
+            instrument_name: Some("BTC-27JUN25".to_string()),
+            ..order(DirectionEnum::Buy, dec!(0.8), dec!(91000))
+        }),
+        Err(RiskRejection::UnderlyingLimit {
+            underlying: "BTC".to_string(),
+            projected: dec!(1.6),
+            limit: dec!(1.5),
+        })
+    );
+
+    let quotes = MassQuoteParams {
+        quotes: vec![DoubleSidedQuote {
+            i: "BTC-PERPETUAL".to_string(),
+            b: Some(DoubleSidedQuoteB::SingleSidedMultiLevelQuote(vec![
+                BookLevel::new(dec!(89990), dec!(0.1)),
+                BookLevel::new(dec!(89980), dec!(0.2)),
+            ])),
+            a: Some(DoubleSidedQuoteA::SingleSidedMultiLevelQuote(vec![
+                BookLevel::new(dec!(90010), dec!(0.5)),
+            ])),
+        }],
+        ..Default::default()
+    };
+    assert!(matches!(
+        guard.check_mass_quote(&quotes),
+        Err(RiskRejection::PositionLimit { projected, .. }) if projected == dec!(1.1)
+    ));
+}
+
+#[test]
+fn session_loss_and_kill_switch_block_new_risk() {
+    let guard = guard(
+        RiskLimits::default().max_session_loss(dec!(1000)),
+        dec!(0.5),
+    );
+    let summary = |realised, unrealised| AccountSummary {
+        session_realised_pnl: realised,
+        unrealised_pnl: unrealised,
+        ..Default::default()
+    };
+    guard.apply_summary(&summary(dec!(-400), dec!(-500)));
+    assert!(
+        guard
+            .check_insert(&order(DirectionEnum::Buy, dec!(0.1), dec!(90000)))
+            .is_ok()
+    );
+    guard.apply_summary(&summary(dec!(-400), dec!(-600)));
+    assert_eq!(
+        guard.check_insert(&order(DirectionEnum::Buy, dec!(0.1), dec!(90000))),
+        Err(RiskRejection::SessionLoss {
+            loss: dec!(1000),
+            limit: dec!(1000),
+        })
+    );
+    // Reduce-only orders may still close the position.
+    let mut close = order(DirectionEnum::Sell, dec!(0.5), dec!(90000));
+    close.reduce_only = Some(true);
+    assert!(guard.check_insert(&close).is_ok());
+}
+
+#[test]
+fn open_order_limit_needs_an_order_manager() {
+    let limits = RiskLimits::default().max_open_orders(2);
+    let bid = order(DirectionEnum::Buy, dec!(0.1), dec!(90000));
+    assert_eq!(
+        guard(limits.clone(), dec!(0)).check_insert(&bid),
+        Err(RiskRejection::OpenOrdersUntracked { limit: 2 })
+    );
+    let orders = OrderManager::new();
+    let tracked = guard(limits, dec!(0)).with_orders(orders.clone());
+    assert!(tracked.check_insert(&bid).is_ok());
+    for id in ["1", "2"] {
+        orders.apply(OrderStatus {
+            order_id: id.to_string(),
+            status: StatusEnum::Open,
+            amount: dec!(0.1),
+            remaining_amount: dec!(0.1),
+            ..Default::default()
+        });
+    }
+    assert_eq!(
+        tracked.check_insert(&bid),
+        Err(RiskRejection::OpenOrders { open: 2, limit: 2 })
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial(private_rpc)]
+async fn kill_switch_cancels_and_blocks() {
+    dotenv::dotenv().ok();
+    let (_, _, _) = require_env!(
+        "THALEX_PRIVATE_KEY_PATH",
+        "THALEX_KEY_ID",
+        "THALEX_ACCOUNT_ID"
+    );
+    let client = WsClient::from_env().await.unwrap();
+    let guard = guard(RiskLimits::default(), dec!(0));
+    guard.kill(&client).await.unwrap();
+    assert!(guard.is_killed());
+    let low_bid = order(DirectionEnum::Buy, dec!(0.001), dec!(1000));
+    assert!(matches!(
+        guard.insert(&client, low_bid.clone()).await,
+        Err(RiskError::Rejected(RiskRejection::KillSwitch))
+    ));
+    guard.reset_kill_switch();
+    assert!(guard.check_insert(&low_bid).is_ok());
+    client.shutdown("Test complete").await.unwrap();
+}
+
+#[tokio::test]
+async fn stop_unsubscribes_the_account_summary() {
+    let (url, _connections, mut methods) = mock_exchange().await;
+    let client = Arc::new(logged_in_client(url).await);
+    let guard = guard(RiskLimits::default(), dec!(0))
+        .start(client.clone())
+        .await
+        .unwrap();
+    guard.stop(&client).await;
+
+    let seen: Vec<String> = std::iter::from_fn(|| methods.try_recv().ok()).collect();
+    assert!(
+        seen.ends_with(&["private/unsubscribe".to_string()]),
+        "{seen:?}"
+    );
+    client.shutdown("Test complete").await.unwrap();
+}
@@ -0,0 +1,214 @@
+use std::time::Duration;
+
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    manual_models::book::BookLevel,
+    models::{Book, Delay, DirectionEnum, InsertParams, OrderStatus, Ticker},
+    order_book::OrderBook,
+    strategy::{
+        Dispatcher, Feed, OrderAction, Strategy, StrategyContext, StrategyEvent, StrategyRunner,
+    },
+    types::ExternalEvent,
+};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange};
+
+const BOOK: &str = "book.BTC-PERPETUAL.none.10.100ms";
+
+#[derive(Default)]
+struct Quoter {
+    pending: Option<i32>,
+    live: Option<String>,
+    best_bid: Option<Decimal>,
+    timer_ticks: u32,
+}
+
+impl Strategy for Quoter {
+    fn feeds(&self) -> Vec<Feed> {
+        vec![
+            Feed::Ticker {
+                instrument_name: "BTC-PERPETUAL".to_string(),
+                delay: Delay::Variant100ms,
+            },
+            Feed::Orders,
+        ]
+    }
+
+    fn timer_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(1))
+    }
+
+    fn on_ticker(&mut self, ctx: &mut StrategyContext, instrument_name: &str, ticker: &Ticker) {
+        if self.pending.is_some() || self.live.is_some() {
+            return;
+        }
+        self.pending = Some(ctx.insert(InsertParams {
+            direction: DirectionEnum::Buy,
+            instrument_name: Some(instrument_name.to_string()),
+            amount: dec!(0.1),
+            price: ticker.best_bid_price,
+            ..Default::default()
+        }));
+    }
+
+    fn on_book(&mut self, _ctx: &mut StrategyContext, book: &OrderBook) {
+        self.best_bid = book.best_bid().map(|level| level.price);
+    }
+
+    fn on_order_update(&mut self, _ctx: &mut StrategyContext, order: &OrderStatus) {
+        if order.client_order_id == self.pending.map(Decimal::from) {
+            self.pending = None;
+            self.live = Some(order.order_id.clone());
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut StrategyContext) {
+        self.timer_ticks += 1;
+        if self.timer_ticks == 2 {
+            ctx.cancel_all();
+            ctx.stop();
+        }
+    }
+
+    fn on_connection_event(&mut self, _ctx: &mut StrategyContext, event: ExternalEvent) {
+        if event == ExternalEvent::Connected {
+            // Cancel on disconnect took our order down.
+            self.live = None;
+        }
+    }
+}
+
+fn ticker() -> StrategyEvent {
+    StrategyEvent::Ticker {
+        instrument_name: "BTC-PERPETUAL".to_string(),
+        ticker: Ticker {
+            best_bid_price: Some(dec!(90000)),
+            ..Default::default()
+        },
+    }
+}
+
+#[test]
+fn hooks_queue_order_actions() {
+    let mut quoter = Quoter::default();
+    assert_eq!(quoter.feeds()[0].channel(), "ticker.BTC-PERPETUAL.100ms");
+    assert_eq!(quoter.feeds()[1].channel(), "session.orders");
+
+    let mut dispatcher = Dispatcher::new();
+    let mut ctx = StrategyContext::detached();
+    dispatcher.dispatch(&mut quoter, &mut ctx, ticker());
+    let actions = ctx.take_actions();
+    let [OrderAction::Insert(params)] = &actions[..] else {
+        panic!("expected one insert, got {actions:?}");
+    };
+    assert_eq!(params.client_order_id, quoter.pending);
+    assert_eq!(params.price, Some(dec!(90000)));
+
+    // Nothing more until the insert is answered.
+    dispatcher.dispatch(&mut quoter, &mut ctx, ticker());
+    assert!(ctx.take_actions().is_empty());
+    dispatcher.dispatch(
+        &mut quoter,
+        &mut ctx,
+        StrategyEvent::OrderUpdate(OrderStatus {
+            order_id: "o-1".to_string(),
+            client_order_id: params.client_order_id.map(Decimal::from),
+            ..Default::default()
+        }),
+    );
+    assert_eq!(quoter.live.as_deref(), Some("o-1"));
+
+    dispatcher.dispatch(
+        &mut quoter,
+        &mut ctx,
+        StrategyEvent::Connection(ExternalEvent::Connected),
+    );
+    dispatcher.dispatch(&mut quoter, &mut ctx, ticker());
+    assert_eq!(ctx.take_actions().len(), 1);
+}
+
+#[test]
+fn books_are_kept_and_timer_stops_the_run() {
+    let mut quoter = Quoter::default();
+    let mut dispatcher = Dispatcher::new();
+    let mut ctx = StrategyContext::detached();
+    let update = |bids: Vec<BookLevel>| StrategyEvent::Book {
+        channel: BOOK.to_string(),
+        update: Book {
+            bid_changes: Some(bids),
+            ..Default::default()
+        },
+    };
+
+    dispatcher.dispatch(
+        &mut quoter,
+        &mut ctx,
+        update(vec![
+            BookLevel::new(dec!(89990), dec!(1)),
+            BookLevel::new(dec!(90000), dec!(2)),
+        ]),
+    );
+    assert_eq!(quoter.best_bid, Some(dec!(90000)));
+    dispatcher.dispatch(
+        &mut quoter,
+        &mut ctx,
+        update(vec![BookLevel::new(dec!(90000), dec!(0))]),
+    );
+    assert_eq!(quoter.best_bid, Some(dec!(89990)));
+    assert_eq!(
+        dispatcher
+            .book(BOOK)
+            .map(|b| b.instrument_name().to_string()),
+        Some("BTC-PERPETUAL".to_string())
+    );
+
+    dispatcher.dispatch(&mut quoter, &mut ctx, StrategyEvent::Timer);
+    assert!(!ctx.is_stopped());
+    dispatcher.dispatch(&mut quoter, &mut ctx, StrategyEvent::Timer);
+    assert!(ctx.is_stopped());
+    assert_eq!(ctx.take_actions(), vec![OrderAction::CancelAll]);
+}
+
+/// Inserts on start, then stops and cancels everything.
+struct OneShot;
+
+impl Strategy for OneShot {
+    fn feeds(&self) -> Vec<Feed> {
+        Vec::new()
+    }
+
+    fn on_start(&mut self, ctx: &mut StrategyContext) {
+        ctx.insert(InsertParams {
+            direction: DirectionEnum::Buy,
+            instrument_name: Some("BTC-PERPETUAL".to_string()),
+            amount: dec!(0.1),
+            price: Some(dec!(90000)),
+            ..Default::default()
+        });
+        ctx.stop();
+    }
+
+    fn on_stop(&mut self, ctx: &mut StrategyContext) {
+        ctx.cancel_all();
+    }
+}
+
+#[tokio::test]
+async fn actions_are_sent_in_order_before_the_run_ends() {
+    let (url, _connections, mut methods) = mock_exchange().await;
+    let client = logged_in_client(url).await;
+    let runner = StrategyRunner::new(client, OneShot);
+    let client = runner.client().clone();
+    runner.run().await.unwrap();
+
+    let seen: Vec<String> = std::iter::from_fn(|| methods.try_recv().ok())
+        .filter(|m| m.starts_with("private/"))
+        .collect();
+    let insert = seen.iter().position(|m| m == "private/insert");
+    let cancel_all = seen.iter().position(|m| m == "private/cancel_all");
+    assert!(insert.is_some() && insert < cancel_all, "{seen:?}");
+    client.shutdown("Test complete").await.unwrap();
+}
@@ -1,6 +1,11 @@
 use log::{Level::Info, info};
 use simple_logger::init_with_level;
-use thalex_rust_sdk::{types::Environment, ws_client::WsClient};
+use thalex_rust_sdk::{
+    channels::batch::ChannelBatch,
+    models::{Delay, PriceIndexNotification, TickerNotification},
+    types::{Environment, RequestScope},
+    ws_client::WsClient,
+};
 
 #[tokio::test]
 async fn instruments_sub() -> Result<(), Box<dyn std::error::Error>> {
@@ -36,3 +41,34 @@ async fn instruments_sub() -> Result<(), Box<dyn std::error::Error>> {
     }
     Ok(())
 }
+
+#[tokio::test]
+async fn batch_sub() -> Result<(), Box<dyn std::error::Error>> {
+    let client = WsClient::new_public(Environment::Testnet).await.unwrap();
+    client.wait_for_connection().await;
+
+    let mut batch = ChannelBatch::new();
+    let mut tickers = batch.stream::<TickerNotification>(
+        RequestScope::Public,
+        format!("ticker.BTC-PERPETUAL.{}", Delay::Raw),
+    );
+    batch.add(
+        RequestScope::Public,
+        "price_index.BTCUSD",
+        |_msg: PriceIndexNotification| {},
+    );
+    let report = client.subscribe_batch(batch).await.unwrap();
+    assert!(report.is_complete(), "Failed channels: {:?}", report.failed);
+    assert_eq!(report.subscribed.len(), 2);
+
+    let update = tokio::time::timeout(std::time::Duration::from_secs(5), tickers.recv()).await;
+    assert!(update.is_ok(), "No ticker update received");
+
+    client
+        .unsubscribe_channels(&report.subscribed)
+        .await
+        .unwrap();
+    assert!(client.public_subscriptions.is_empty());
+    client.shutdown("Test complete").await.unwrap();
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    models::{DirectionEnum, Instrument},
+    ticks::{Amount, Price, Rounding, TickError, TickSpec, is_on_step, round_to_step},
+};
+
+#[test]
+fn rounds_in_the_requested_direction() {
+    let price = |rounding| Price::new(dec!(90001.3), dec!(2.5), rounding).unwrap();
+    assert_eq!(price(Rounding::Down).value(), dec!(90000));
+    assert_eq!(price(Rounding::Up).value(), dec!(90002.5));
+    assert_eq!(price(Rounding::Nearest).value(), dec!(90002.5));
+    assert_eq!(
+        Price::new(dec!(1), dec!(0), Rounding::Down),
+        Err(TickError::InvalidStep(dec!(0)))
+    );
+
+    let bid = price(Rounding::Down);
+    assert_eq!(bid.step(3).value(), dec!(90007.5));
+    assert_eq!(bid.step(-2).value(), dec!(89995));
+    assert_eq!(bid.ticks_to(bid.step(-2)), dec!(-2));
+    // 10 bps of 90000 is 90, rounded onto the 2.5 grid.
+    assert_eq!(
+        bid.offset_bps(dec!(-10), Rounding::Down).value(),
+        dec!(89910)
+    );
+    assert_eq!(bid.offset_bps(dec!(1), Rounding::Up).value(), dec!(90010));
+    assert_eq!(*bid + dec!(1), dec!(90001));
+
+    let amount = Amount::new(dec!(0.12345), dec!(0.001), Rounding::Down).unwrap();
+    assert_eq!(amount.value(), dec!(0.123));
+    assert_eq!(amount.step(-200).value(), dec!(0));
+}
+
+#[test]
+fn zero_steps_are_errors() {
+    assert_eq!(
+        round_to_step(dec!(1.5), dec!(0), Rounding::Down),
+        Err(TickError::InvalidStep(dec!(0)))
+    );
+    assert_eq!(
+        is_on_step(dec!(1.5), dec!(-0.5)),
+        Err(TickError::InvalidStep(dec!(-0.5)))
+    );
+    assert_eq!(is_on_step(dec!(1.5), dec!(0.5)), Ok(true));
+    assert_eq!(
+        TickSpec::new(dec!(1), dec!(0), None),
+        Err(TickError::InvalidStep(dec!(0)))
+    );
+    let instrument = Instrument {
+        instrument_name: Some("BTC-PERPETUAL".to_string()),
+        tick_size: Some(dec!(0)),
+        volume_tick_size: Some(dec!(0.001)),
+        ..Default::default()
+    };
+    assert_eq!(
+        TickSpec::from_instrument(&instrument),
+        Err(TickError::MissingTickSize("BTC-PERPETUAL".to_string()))
+    );
+}
+
+#[test]
+fn tick_spec_from_instrument() {
+    let instrument: Instrument = serde_json::from_str(
+        r#"{"instrument_name":"BTC-PERPETUAL","tick_size":1,"volume_tick_size":0.001,
+        "min_order_amount":0.01,"type":"perpetual"}"#,
+    )
+    .unwrap();
+    let spec = TickSpec::from_instrument(&instrument).unwrap();
+    assert_eq!(
+        spec.passive_price(dec!(90000.6), DirectionEnum::Buy)
+            .value(),
+        dec!(90000)
+    );
+    assert_eq!(
+        spec.passive_price(dec!(90000.2), DirectionEnum::Sell)
+            .value(),
+        dec!(90001)
+    );
+    assert!(!spec.is_tradable(spec.amount(dec!(0.0099), Rounding::Down)));
+    assert!(spec.is_tradable(spec.amount(dec!(0.0101), Rounding::Down)));
+    assert_eq!(
+        TickSpec::from_instrument(&Instrument::default()),
+        Err(TickError::MissingTickSize(String::new()))
+    );
+}
@@ -0,0 +1,111 @@
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use thalex_rust_sdk::{
+    models::{Instrument, OptionTypeEnum, Ticker, TypeEnum},
+    vol_surface::VolSurface,
+};
+
+const NOW: i64 = 1_700_000_000;
+const DAY: i64 = 86_400;
+
+fn option(strike: Decimal, expiry: i64, expiry_date: &str) -> Instrument {
+    Instrument {
+        instrument_name: Some(format!("BTC-{expiry_date}-{strike}-C")),
+        underlying: Some("BTCUSD".to_string()),
+        r#type: Some(TypeEnum::Option),
+        option_type: Some(OptionTypeEnum::Call),
+        strike_price: Some(strike),
+        expiration_timestamp: Some(expiry as i32),
+        expiry_date: Some(expiry_date.to_string()),
+        ..Default::default()
+    }
+}
+
+fn ticker(iv: Decimal, forward: Decimal) -> Ticker {
+    Ticker {
+        mark_timestamp: Decimal::from(NOW),
+        iv: Some(iv),
+        forward: Some(forward),
+        ..Default::default()
+    }
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+fn two_expiry_surface() -> VolSurface {
+    let mut surface = VolSurface::new("BTCUSD");
+    for (strike, iv) in [
+        (dec!(90000), dec!(0.6)),
+        (dec!(100000), dec!(0.5)),
+        (dec!(110000), dec!(0.55)),
+    ] {
+        assert!(surface.update_ticker(
+            &option(strike, NOW + 10 * DAY, "2023-11-24"),
+            &ticker(iv, dec!(100000))
+        ));
+    }
+    for strike in [dec!(90000), dec!(100000), dec!(110000)] {
+        surface.update_ticker(
+            &option(strike, NOW + 40 * DAY, "2023-12-24"),
+            &ticker(dec!(0.7), dec!(101000)),
+        );
+    }
+    surface
+}
+
+#[test]
+fn smile_passes_through_marks_and_is_flat_outside() {
+    let surface = two_expiry_surface();
+    let front = NOW + 10 * DAY;
+    assert!(close(surface.iv(100000.0, front).unwrap(), 0.5));
+    assert!(close(surface.iv(90000.0, front).unwrap(), 0.6));
+    assert!(close(surface.iv(50000.0, front).unwrap(), 0.6));
+    let between = surface.iv(95000.0, front).unwrap();
+    assert!(between > 0.5 && between < 0.6);
+
+    let wrong_underlying = Instrument {
+        underlying: Some("ETHUSD".to_string()),
+        ..option(dec!(3000), front, "2023-11-24")
+    };
+    assert!(
+        !surface
+            .clone()
+            .update_ticker(&wrong_underlying, &ticker(dec!(0.9), dec!(3000)))
+    );
+}
+
+#[test]
+fn interpolates_total_variance_between_expiries() {
+    let surface = two_expiry_surface();
+    let mid = NOW + 25 * DAY;
+    // ATM total variance: 0.25 * 10 at the front, 0.49 * 40 at the back.
+    let expected = ((2.5 + (19.6 - 2.5) * 15.0 / 30.0) / 25.0_f64).sqrt();
+    let forward = surface.forward(mid).unwrap();
+    assert!(close(forward, 100500.0));
+    assert!(close(surface.iv(forward, mid).unwrap(), expected));
+
+    let term: Vec<f64> = surface
+        .atm_term_structure()
+        .into_iter()
+        .map(|(_, iv)| iv)
+        .collect();
+    assert!(close(term[0], 0.5) && close(term[1], 0.7));
+}
+
+#[test]
+fn snapshot_round_trip() {
+    let surface = two_expiry_surface();
+    let path = std::env::temp_dir().join(format!("vol_surface_{}.json", std::process::id()));
+    surface.save(&path).unwrap();
+    let loaded = VolSurface::load(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(loaded.as_of(), surface.as_of());
+    let back = NOW + 40 * DAY;
+    assert!(close(
+        loaded.iv(105000.0, back).unwrap(),
+        surface.iv(105000.0, back).unwrap()
+    ));
+}
@@ -1,12 +1,19 @@
 use std::time::Duration;
 
+use bytes::Bytes;
 use log::Level::Info;
+use serde_json::{Value, json};
 use simple_logger::init_with_level;
 use thalex_rust_sdk::{
     models::Delay,
-    types::{Environment, ExternalEvent, InternalCommand},
-    ws_client::WsClient,
+    types::{Environment, ExternalEvent, InternalCommand, RequestScope},
+    ws_client::{WsClient, is_reconnect_event},
 };
+use tokio::{sync::mpsc::unbounded_channel, time::timeout};
+
+#[path = "common/mock_exchange.rs"]
+mod mock_exchange;
+use mock_exchange::{logged_in_client, mock_exchange, reconnect_event};
 
 #[tokio::test]
 async fn test_websocket_subscription_working() {
@@ -130,3 +137,124 @@ async fn test_ws_reconnect() {
 
     handle.abort();
 }
+
+#[test]
+fn detects_reconnect_events() {
+    let reconnect =
+        Bytes::from_static(br#"{"channel_name":"system","notification":{"event":"reconnect"}}"#);
+    assert!(is_reconnect_event(&reconnect));
+    for other in [
+        r#"{"channel_name":"system","notification":{"event":"maintenance"}}"#,
+        r#"{"channel_name":"ticker.BTC-PERPETUAL.raw","notification":{"event":"reconnect"}}"#,
+        r#"{"id":1,"result":{"event":"reconnect"}}"#,
+    ] {
+        assert!(!is_reconnect_event(&Bytes::from(other)), "{other}");
+    }
+}
+
+#[tokio::test]
+async fn handover_renews_the_session() {
+    const TICKER: &str = "ticker.BTC-PERPETUAL.raw";
+    let (url, mut connections, mut methods) = mock_exchange().await;
+    let client = logged_in_client(url).await;
+    let (ticks_tx, mut ticks) = unbounded_channel();
+    client
+        .subscribe_channel(
+            RequestScope::Public,
+            TICKER.to_string(),
+            move |msg: Value| {
+                let _ = ticks_tx.send(msg);
+            },
+        )
+        .await
+        .unwrap();
+
+    let live = connections.recv().await.unwrap();
+    let mut login = client.login_status();
+    let mut state = client.connection_state();
+    state.mark_unchanged();
+    live.send(reconnect_event()).unwrap();
+
+    let standby = timeout(Duration::from_secs(5), connections.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    // Listeners re-run their login setup for the standby's session...
+    timeout(Duration::from_secs(5), login.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(*login.borrow());
+    // ...while the connection never looked down.
+    assert!(!state.has_changed().unwrap());
+    assert!(client.is_connected());
+
+    let mut seen = Vec::new();
+    while let Ok(method) = methods.try_recv() {
+        seen.push(method);
+    }
+    let logins = seen.iter().filter(|m| *m == "public/login").count();
+    assert_eq!(logins, 2, "{seen:?}");
+    assert_eq!(seen.last().map(String::as_str), Some("public/subscribe"));
+
+    standby
+        .send(json!({ "channel_name": TICKER, "notification": { "mark_price": 1 } }))
+        .unwrap();
+    let tick = timeout(Duration::from_secs(5), ticks.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(tick["notification"]["mark_price"], 1);
+    client.shutdown("Test complete").await.unwrap();
+}
+
+#[tokio::test]
+async fn shared_subscriptions_fan_out_until_the_last_release() {
+    const TICKER: &str = "ticker.BTC-PERPETUAL.raw";
+    let (url, mut connections, mut methods) = mock_exchange().await;
+    let client = logged_in_client(url).await;
+    let live = connections.recv().await.unwrap();
+
+    let (ticks_tx, mut ticks) = unbounded_channel();
+    let mut handles = Vec::new();
+    for name in ["first", "second"] {
+        let ticks_tx = ticks_tx.clone();
+        let handle = client
+            .subscribe_shared_channel(RequestScope::Public, TICKER.to_string(), move |_: Value| {
+                let _ = ticks_tx.send(name);
+            })
+            .await
+            .unwrap();
+        handles.push(handle);
+    }
+    // A plain subscription joins the shared ones instead of replacing them.
+    client
+        .subscribe_channel(RequestScope::Public, TICKER.to_string(), move |_: Value| {
+            let _ = ticks_tx.send("plain");
+        })
+        .await
+        .unwrap();
+    let mut tick = async |expected: &[&str]| {
+        live.send(json!({ "channel_name": TICKER, "notification": {} }))
+            .unwrap();
+        let mut received = Vec::new();
+        for _ in expected {
+            let name = timeout(Duration::from_secs(5), ticks.recv()).await.unwrap();
+            received.push(name.unwrap());
+        }
+        received.sort();
+        assert_eq!(received, expected);
+    };
+    tick(&["first", "plain", "second"]).await;
+
+    let mut drain = || std::iter::from_fn(|| methods.try_recv().ok()).collect::<Vec<_>>();
+    drain();
+    client.unsubscribe(TICKER).await.unwrap();
+    client.release(handles.pop()).await.unwrap();
+    assert!(!drain().iter().any(|m| m == "public/unsubscribe"));
+    tick(&["first"]).await;
+
+    client.release(handles).await.unwrap();
+    assert_eq!(drain(), vec!["public/unsubscribe".to_string()]);
+    client.shutdown("Test complete").await.unwrap();
+}